@@ -0,0 +1,306 @@
+//! Fuzz target for the rewriter.
+//!
+//! Each input selects one of the seed files in `fuzz/seeds/` and a sequence of structural
+//! mutations to apply to its AST.  We rewrite the seed's source text to match the mutated AST,
+//! using the same `rewrite` and `rewrite_files_with` paths as `c2rust-refactor` itself, then check
+//! that the result parses back to the mutated AST.
+//!
+//! Run with `cargo fuzz run rewrite` from the `c2rust-refactor` directory.
+#![no_main]
+#![feature(rustc_private)]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate c2rust_ast_builder;
+extern crate c2rust_refactor;
+extern crate rustc;
+extern crate rustc_errors;
+extern crate smallvec;
+extern crate syntax;
+extern crate syntax_pos;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use c2rust_ast_builder::mk;
+use rustc::session::{self, Session};
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::ptr::P;
+use syntax_pos::FileName;
+
+use c2rust_refactor::ast_manip::number_nodes::{number_nodes_with, reset_node_ids, NodeIdCounter};
+use c2rust_refactor::ast_manip::{remove_paren, AstEquiv, CommentMap};
+use c2rust_refactor::file_io::FileIO;
+use c2rust_refactor::rewrite;
+
+const SEEDS: &[&str] = &[
+    include_str!("../seeds/exprs.rs"),
+    include_str!("../seeds/items.rs"),
+];
+
+/// Source of mutation decisions, backed by the fuzzer input.  Once the input runs out, every
+/// decision is "don't mutate".
+struct Choices<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Choices<'a> {
+    fn next(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&b, rest)) => {
+                self.data = rest;
+                b
+            }
+            None => 0xff,
+        }
+    }
+}
+
+/// Binary operators that can replace one another without running into the parser's restrictions
+/// on chained comparisons.
+const ARITH_OPS: &[BinOpKind] = &[
+    BinOpKind::Add,
+    BinOpKind::Sub,
+    BinOpKind::Mul,
+    BinOpKind::Div,
+    BinOpKind::Rem,
+    BinOpKind::BitXor,
+    BinOpKind::BitAnd,
+    BinOpKind::BitOr,
+    BinOpKind::Shl,
+    BinOpKind::Shr,
+];
+
+/// Check whether `e` is a block-like expression.  These can appear in positions where other
+/// expressions can't (statements without a trailing semicolon, `else` branches), so replacing
+/// one with a different kind of expression could produce an AST that doesn't print validly.
+fn is_block_like(e: &Expr) -> bool {
+    match e.node {
+        ExprKind::If(..)
+        | ExprKind::IfLet(..)
+        | ExprKind::While(..)
+        | ExprKind::WhileLet(..)
+        | ExprKind::ForLoop(..)
+        | ExprKind::Loop(..)
+        | ExprKind::Match(..)
+        | ExprKind::Block(..)
+        | ExprKind::Async(..)
+        | ExprKind::TryBlock(..) => true,
+        _ => false,
+    }
+}
+
+/// Check whether items of this kind may have a visibility qualifier.
+fn allows_vis(i: &Item) -> bool {
+    match i.node {
+        ItemKind::Impl(..) | ItemKind::ForeignMod(..) | ItemKind::Mac(..) => false,
+        _ => true,
+    }
+}
+
+struct Mutate<'a> {
+    choices: Choices<'a>,
+}
+
+impl<'a> MutVisitor for Mutate<'a> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        if is_block_like(e) {
+            mut_visit::noop_visit_expr(e, self);
+            return;
+        }
+
+        if let ExprKind::Struct(_, ref mut fields, ref mut base) = e.node {
+            // Shorthand fields (`S { x }`) must keep their expression as a plain identifier.
+            for f in fields.iter_mut().filter(|f| !f.is_shorthand) {
+                self.visit_expr(&mut f.expr);
+            }
+            if let Some(ref mut base) = *base {
+                self.visit_expr(base);
+            }
+            return;
+        }
+
+        match self.choices.next() % 16 {
+            0 => {
+                // Use a path rather than a literal: `0.field` would print as a float.
+                *e = mk().ident_expr("x");
+                return;
+            }
+            1 => {
+                if let ExprKind::Binary(_, ref mut lhs, ref mut rhs) = e.node {
+                    std::mem::swap(lhs, rhs);
+                }
+            }
+            2 => {
+                let op = ARITH_OPS[self.choices.next() as usize % ARITH_OPS.len()];
+                if let ExprKind::Binary(ref mut binop, _, _) = e.node {
+                    if ARITH_OPS.contains(&binop.node) {
+                        binop.node = op;
+                    }
+                }
+            }
+            3 => {
+                *e = mk().unary_expr(UnOp::Neg, e.clone());
+            }
+            4 => {
+                if let ExprKind::Range(_, Some(_), ref mut limits) = e.node {
+                    *limits = match *limits {
+                        RangeLimits::HalfOpen => RangeLimits::Closed,
+                        RangeLimits::Closed => RangeLimits::HalfOpen,
+                    };
+                }
+            }
+            _ => {}
+        }
+        mut_visit::noop_visit_expr(e, self);
+    }
+
+    fn visit_block(&mut self, b: &mut P<Block>) {
+        let len = b.stmts.len();
+        if len > 0 {
+            let i = self.choices.next() as usize % len;
+            // A trailing expression (`StmtKind::Expr`) is only valid at the end of the block, so
+            // it can be deleted but not copied or moved.
+            let movable = |s: &Stmt| match s.node {
+                StmtKind::Expr(_) => false,
+                _ => true,
+            };
+            match self.choices.next() % 8 {
+                0 => {
+                    b.stmts.remove(i);
+                }
+                1 if movable(&b.stmts[i]) => {
+                    // The copy is a new node, so it shouldn't share IDs with the original.
+                    let mut stmt = b.stmts[i].clone();
+                    reset_node_ids(&mut stmt);
+                    b.stmts.insert(i, stmt);
+                }
+                2 if i + 1 < len && movable(&b.stmts[i]) && movable(&b.stmts[i + 1]) => {
+                    b.stmts.swap(i, i + 1);
+                }
+                _ => {}
+            }
+        }
+        mut_visit::noop_visit_block(b, self);
+    }
+
+    fn flat_map_item(&mut self, mut i: P<Item>) -> smallvec::SmallVec<[P<Item>; 1]> {
+        match self.choices.next() % 8 {
+            0 if allows_vis(&i) => {
+                i.vis.node = match i.vis.node {
+                    VisibilityKind::Inherited => VisibilityKind::Public,
+                    _ => VisibilityKind::Inherited,
+                };
+            }
+            1 => {
+                if let ItemKind::Static(_, ref mut mutbl, _) = i.node {
+                    *mutbl = match *mutbl {
+                        Mutability::Mutable => Mutability::Immutable,
+                        Mutability::Immutable => Mutability::Mutable,
+                    };
+                }
+            }
+            _ => {}
+        }
+        mut_visit::noop_flat_map_item(i, self)
+    }
+
+    fn visit_pat(&mut self, _p: &mut P<Pat>) {
+        // Expressions inside patterns (literals and range endpoints) are restricted to a small
+        // subset of the expression grammar, so leave patterns alone.
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+/// `FileIO` that captures the rewritten text instead of writing it to disk.
+struct CaptureIO {
+    output: RefCell<Option<String>>,
+}
+
+impl FileIO for CaptureIO {
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unexpected read of {:?}", path),
+        ))
+    }
+
+    fn write_file(&self, _path: &Path, s: &str) -> io::Result<()> {
+        *self.output.borrow_mut() = Some(s.to_owned());
+        Ok(())
+    }
+}
+
+fn build_session() -> Session {
+    let opts = session::config::Options::default();
+    let registry = rustc_errors::registry::Registry::new(&[]);
+    let sess = session::build_session(opts, None, registry);
+    // Keep real spans from colliding with `DUMMY_SP`, as `driver::build_session` does.
+    sess.source_map()
+        .new_source_file(FileName::Custom("<dummy>".to_string()), " ".to_string());
+    sess
+}
+
+fn parse_crate(sess: &Session, name: &str, src: &str) -> Option<Crate> {
+    let path = FileName::Real(PathBuf::from(name));
+    match syntax::parse::parse_crate_from_source_str(path, src.to_owned(), &sess.parse_sess) {
+        Ok(krate) => Some(krate),
+        Err(mut db) => {
+            db.cancel();
+            None
+        }
+    }
+}
+
+fn run(data: &[u8]) {
+    let (&seed_idx, data) = match data.split_first() {
+        Some(x) => x,
+        None => return,
+    };
+    let seed = SEEDS[seed_idx as usize % SEEDS.len()];
+
+    let sess = build_session();
+    let mut old = parse_crate(&sess, "seed.rs", seed).expect("failed to parse seed");
+    // Like `command::parse_crate`, the rewriter expects parens to be represented only implicitly.
+    remove_paren(&mut old);
+    let counter = NodeIdCounter::new(0);
+    number_nodes_with(&mut old, &counter);
+    let node_id_map: HashMap<NodeId, NodeId> = (0..counter.next().as_u32())
+        .map(|i| (NodeId::from_u32(i), NodeId::from_u32(i)))
+        .collect();
+
+    let mut new = old.clone();
+    Mutate {
+        choices: Choices { data },
+    }
+    .visit_crate(&mut new);
+
+    let comment_map = CommentMap::default();
+    let rw = rewrite::rewrite(&sess, &old, &new, &comment_map, node_id_map, |_| {});
+    let io = CaptureIO {
+        output: RefCell::new(None),
+    };
+    rewrite::files::rewrite_files_with(sess.source_map(), &rw, &io).unwrap();
+    let output = io.output.into_inner().unwrap_or_else(|| seed.to_owned());
+
+    let mut reparsed = match parse_crate(&sess, "output.rs", &output) {
+        Some(krate) => krate,
+        None => panic!("rewritten source failed to parse:\n{}", output),
+    };
+    remove_paren(&mut reparsed);
+    assert!(
+        reparsed.ast_equiv(&new),
+        "rewritten source doesn't match the mutated AST:\n{}",
+        output
+    );
+}
+
+fuzz_target!(|data: &[u8]| {
+    syntax::with_globals(|| run(data));
+});
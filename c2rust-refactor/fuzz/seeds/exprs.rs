@@ -0,0 +1,32 @@
+// Seed for the `rewrite` fuzz target.  Comments and unusual formatting are deliberate: the
+// rewriter should preserve them wherever the mutated AST allows.
+
+fn arith(a: i32, b: i32) -> i32 {
+    let x = a + b * 2;
+    let y = (a - b) /* diff */ * x;
+    if x > y {
+        x % 3
+    } else {
+        -y
+    }
+}
+
+fn ranges(v: &[u8], n: usize) -> usize {
+    let mut total = 0;
+    for i in 0 .. n {
+        total += v[i] as usize;
+    }
+    for _ in 1..=n { total -= 1; }
+    let _r = ..n;
+    total
+}
+
+fn calls(s: &str) -> usize {
+    let t = s.trim();
+    let parts = (t.len(), t.is_empty());
+    match parts {
+        (0, _) => 0,
+        (n, false) => n * 2,
+        _ => 1,
+    }
+}
@@ -0,0 +1,25 @@
+// Seed for the `rewrite` fuzz target, focusing on item headers.
+
+pub static TABLE: [u8; 4] = [1, 2, 3, 4];
+static mut COUNTER: u32 = 0;
+pub(crate) const LIMIT: usize = 16;
+
+pub struct S {
+    pub a: u32,
+    b: u32,
+}
+
+impl S {
+    pub fn new(a: u32) -> S {
+        S { a, b: a + 1 }
+    }
+
+    pub(in crate) fn sum(&self) -> u32 {
+        self.a + self.b
+    }
+}
+
+pub unsafe fn bump() -> u32 {
+    COUNTER += 1;
+    COUNTER
+}
@@ -0,0 +1,86 @@
+'''This module generates `AstDump` impls for each AST node type.
+
+- A struct value is dumped as its type name, followed by its fields, labeled and
+  indented one level.
+- An enum value is dumped as its variant path, followed by the fields of the
+  variant.
+- A flag value is dumped as its `Debug` representation.
+
+This uses the same attributes as `ast_equiv`, so the dump includes exactly the
+parts of the AST that `AstEquiv` compares:
+
+- `#[equiv_mode=eq]`: On a type declaration, dump values using `Debug`.
+
+- `#[equiv_mode=ignore]`: On a type declaration, omit values of this type from
+  the dump.
+'''
+
+from datetime import datetime
+from textwrap import indent, dedent
+
+from ast import *
+from util import *
+
+
+@linewise
+def dump_body(se, target):
+    # The dumper is named `dumper` rather than `d` so it can't clash with a field binding.
+    yield 'match %s {' % target
+    for v, path in variants_paths(se):
+        yield '  &%s => {' % struct_pattern(v, path)
+        yield '    dumper.node("%s", |dumper| {' % path
+        for f in v.fields:
+            yield '      dumper.field("%s", %s);' % (f.name, f.name)
+        yield '    });'
+        yield '  }'
+    yield '}'
+
+
+@linewise
+def dump_impl(se):
+    yield '#[allow(unused, non_shorthand_field_patterns)]'
+    yield 'impl AstDump for %s {' % se.name
+    yield '  fn ast_dump(&self, dumper: &mut Dumper) {'
+    yield indent(dump_body(se, 'self'), '    ')
+    yield '  }'
+    yield '}'
+
+@linewise
+def debug_impl(d):
+    yield '#[allow(unused)]'
+    yield 'impl AstDump for %s {' % d.name
+    yield '  fn ast_dump(&self, dumper: &mut Dumper) {'
+    yield '    dumper.leaf(&format!("{:?}", self));'
+    yield '  }'
+    yield '}'
+
+@linewise
+def ignore_impl(d):
+    yield '#[allow(unused)]'
+    yield 'impl AstDump for %s {' % d.name
+    yield '  fn ast_dump(&self, dumper: &mut Dumper) {}'
+    yield '  fn is_ignored(&self) -> bool {'
+    yield '    true'
+    yield '  }'
+    yield '}'
+
+@linewise
+def generate(decls):
+    yield '// AUTOMATICALLY GENERATED - DO NOT EDIT'
+    yield '// Produced %s by process_ast.py' % (datetime.now(),)
+    yield ''
+
+    for d in decls:
+        mode = d.attrs.get('equiv_mode')
+        if mode is None:
+            if isinstance(d, (Struct, Enum)):
+                mode = 'compare'
+            else:
+                mode = 'eq'
+
+        if mode == 'compare':
+            yield dump_impl(d)
+        elif mode == 'eq':
+            yield debug_impl(d)
+        elif mode == 'ignore':
+            yield ignore_impl(d)
@@ -0,0 +1,101 @@
+'''This module generates `AstHash` impls for each AST node type.
+
+The hashes are consistent with `AstEquiv`: two values that are equivalent under
+`AstEquiv` always have the same hash.
+
+- A struct value is hashed by hashing each of its fields.
+- An enum value is hashed by hashing the index of its variant, followed by
+  each of the variant's fields.
+- A flag value is hashed using its `Hash` impl.
+
+Attributes:
+
+- `#[equiv_mode=eq]`: On a type declaration, hash the value using its `Hash`
+  impl, just as for flags.
+
+- `#[equiv_mode=ignore]`: On a type declaration, generate a trivial `impl` that
+  hashes nothing, since `AstEquiv` ignores fields of this type.
+
+- `#[hash_mode=variant]`: On a type declaration, hash only the value's enum
+  variant, using `mem::discriminant`.  This is for flag types with no `Hash`
+  impl.  Values that are equivalent still have equal hashes, since they have
+  the same variant.
+'''
+
+from datetime import datetime
+from textwrap import indent
+
+from ast import *
+from util import *
+
+
+@linewise
+def hash_body(se, target):
+    yield 'match %s {' % target
+    for i, (v, path) in enumerate(variants_paths(se)):
+        yield '  &%s => {' % struct_pattern(v, path)
+        if isinstance(se, Enum):
+            yield '    Hash::hash(&%d_usize, h);' % i
+        for f in v.fields:
+            yield '    AstHash::ast_hash(%s, h);' % f.name
+        yield '  }'
+    yield '}'
+
+@linewise
+def hash_impl(se):
+    yield '#[allow(unused, non_shorthand_field_patterns)]'
+    yield 'impl AstHash for %s {' % se.name
+    yield '  fn ast_hash<H: Hasher>(&self, h: &mut H) {'
+    yield indent(hash_body(se, 'self'), '    ')
+    yield '  }'
+    yield '}'
+
+@linewise
+def eq_impl(d):
+    yield '#[allow(unused)]'
+    yield 'impl AstHash for %s {' % d.name
+    yield '  fn ast_hash<H: Hasher>(&self, h: &mut H) {'
+    yield '    Hash::hash(self, h)'
+    yield '  }'
+    yield '}'
+
+@linewise
+def variant_impl(d):
+    yield '#[allow(unused)]'
+    yield 'impl AstHash for %s {' % d.name
+    yield '  fn ast_hash<H: Hasher>(&self, h: &mut H) {'
+    yield '    Hash::hash(&mem::discriminant(self), h)'
+    yield '  }'
+    yield '}'
+
+@linewise
+def ignore_impl(d):
+    yield '#[allow(unused)]'
+    yield 'impl AstHash for %s {' % d.name
+    yield '  fn ast_hash<H: Hasher>(&self, h: &mut H) {}'
+    yield '}'
+
+@linewise
+def generate(decls):
+    yield '// AUTOMATICALLY GENERATED - DO NOT EDIT'
+    yield '// Produced %s by process_ast.py' % (datetime.now(),)
+    yield ''
+
+    for d in decls:
+        if d.attrs.get('hash_mode') == 'variant':
+            yield variant_impl(d)
+            continue
+
+        mode = d.attrs.get('equiv_mode')
+        if mode is None:
+            if isinstance(d, (Struct, Enum)):
+                mode = 'compare'
+            else:
+                mode = 'eq'
+
+        if mode == 'compare':
+            yield hash_impl(d)
+        elif mode == 'eq':
+            yield eq_impl(d)
+        elif mode == 'ignore':
+            yield ignore_impl(d)
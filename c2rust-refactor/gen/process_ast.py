@@ -212,6 +212,9 @@ if __name__ == '__main__':
     elif mode == 'ast_equiv':
         import ast_equiv
         text = ast_equiv.generate(decls)
+    elif mode == 'ast_hash':
+        import ast_hash
+        text = ast_hash.generate(decls)
     elif mode == 'matcher':
         import matcher
         text = matcher.generate(decls)
@@ -251,6 +254,9 @@ if __name__ == '__main__':
     elif mode == 'ast_names':
         import ast_names
         text = ast_names.generate(decls)
+    elif mode == 'ast_dump':
+        import ast_dump
+        text = ast_dump.generate(decls)
     else:
         raise ValueError('unknown mode: %r' % mode)
 
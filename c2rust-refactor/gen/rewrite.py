@@ -50,20 +50,33 @@ The traits are:
   `Recover` is available on the node type.
 
 The generated `Rewrite` impl will by default try either `equal` or `recursive`
-(depending on type kind), then `print`.  If one of these strategies is
-unavailabe (due to missing impls), it will be skipped.  If no strategies are
-available, code generation raises an error.
+(depending on type kind), then `partial`, then `print`.  If one of these
+strategies is unavailabe (due to missing impls), it will be skipped.  If no
+strategies are available, code generation raises an error.
 
-For the three built-in strategies:
+For the four built-in strategies:
 
 - `equal` requires no traits.
 
 - `recursive` requires `Recursive`.
 
+- `partial` requires everything `recursive` and `print` require.
+
 - `print` requires `PrintParse`, `RecoverChildren`, and `Splice`.
 
-Built-in strategy selection can be altered using `#[rewrite_strategies]` or
-`#[rewrite_extra_strategies]` (see below).
+While a `partial` rewrite is collecting deferred nodes, a node whose strategies
+all fail retries `recursive` with deferral enabled, and then, if it has a span
+and a generated `RecoverChildren` impl, is deferred instead of failing.
+
+With the `rewrite_profile` feature enabled, each generated impl also times
+itself for `rewrite::profile`.
+
+Each generated impl also charges one node comparison to the recycle budget (see
+`RewriteCtxt::set_recycle_budget`).  Once the budget is used up, the impl skips
+straight to `print`, or fails if `print` is unavailable.
+
+Built-in strategy selection can be altered using `#[rewrite_strategies]`,
+`#[rewrite_pre_strategies]`, or `#[rewrite_extra_strategies]` (see below).
 
 
 # Other trait-related attributes
@@ -72,6 +85,10 @@ Built-in strategy selection can be altered using `#[rewrite_strategies]` or
   node type, run the named strategies in order.  This completely overrides the
   automatic strategy selection normally performed by the `Rewrite` generator.
 
+- `#[rewrite_pre_strategies=s1,s2,s3]`: When generating a `Rewrite` impl for
+  this node type, try the named strategies before all others, including
+  `equal`/`recursive`.
+
 - `#[rewrite_extra_strategies=s1,s2,s3]`: When generating a `Rewrite` impl for
   this node type, add the named strategies to the list of strategies to try.
   The strategies will be inserted after `equal`/`recursive` and before `print`.
@@ -88,6 +105,9 @@ Built-in strategy selection can be altered using `#[rewrite_strategies]` or
   the contents of this field.  Perform no side effects and always return
   success, in all impls.
 
+- `#[rewrite_budget_scope]`: Start a new recycle budget at nodes of this type,
+  unless they are nested inside the node that started the current one.
+
 
 # Sequence rewriting attributes
 
@@ -120,6 +140,13 @@ These affect the behavior of generated `Recursive` and `RecoverChildren` impls.
   set expr precedence to `RESET` (don't parenthesize).  The expr precedence can
   be overridden using other `prec` attributes on specific fields.
 
+  Precedence is only changed when entering a child of a `prec_contains_expr`
+  type.  Every other node type passes the current precedence through to its
+  children unchanged, which is correct only because the precedence is `RESET`
+  whenever we aren't directly inside an expr.  To keep it that way, the other
+  `prec` attributes may only appear on fields of `prec_contains_expr` types,
+  and only on fields that hold exprs.
+
 - `#[prec=name]`: When entering this child node, set expr precedence to
   `PREC_[name]` (if `name` is all-caps) or to the precedence of
   `AssocOp::[name]` (otherwise).
@@ -240,6 +267,10 @@ def type_needs_generated_impl(d, trait):
     if skip is not None and trait in skip.split(','):
         return False
 
+    custom = d.attrs.get('rewrite_custom')
+    if custom is not None and trait in custom.split(','):
+        return False
+
     gen = d.attrs.get('rewrite_gen')
     if gen is not None and trait in gen.split(','):
         return True
@@ -262,6 +293,10 @@ def get_rewrite_strategies(d):
 
     strats = []
 
+    pre_strats = d.attrs.get('rewrite_pre_strategies')
+    if pre_strats is not None:
+        strats.extend(pre_strats.split(','))
+
     if isinstance(d, Flag):
         strats.append('equal')
     else:
@@ -273,10 +308,22 @@ def get_rewrite_strategies(d):
         strats.extend(extra_strats.split(','))
 
     if all(type_has_impl(d, t) for t in ('PrintParse', 'RecoverChildren', 'Splice')):
+        if type_has_impl(d, 'Recursive'):
+            strats.append('partial')
         strats.append('print')
 
     return strats
 
+def type_can_defer(d):
+    '''Returns `True` if a failed rewrite of a node of type `d` can be deferred to
+    an enclosing `partial` rewrite.  This requires a span, to know what text to
+    replace, and a generated `RecoverChildren` impl, to find the node again in
+    the printed text.  Printable nodes never fail, so they are left out.'''
+    return (has_get_span_impl(d)
+            and type_needs_generated_impl(d, 'RecoverChildren')
+            and 'print' not in get_rewrite_strategies(d)
+            and 'rewrite_ignore' not in d.attrs)
+
 @linewise
 def do_record_node_span(d, span_node, id_node, rcx):
     has_id = has_get_node_id_impl(d)
@@ -308,6 +355,19 @@ def do_rewrite_impl(d):
     yield '  fn rewrite(old: &Self, new: &Self, mut rcx: RewriteCtxtRef) -> bool {'
     if has_field(d, 'id'):
         yield '    trace!("{:?}: rewrite: begin (%s)", new.id);' % d.name
+    yield '    #[cfg(feature = "rewrite_profile")]'
+    yield '    let _timer = crate::rewrite::profile::enter("%s");' % d.name
+    if 'rewrite_budget_scope' in d.attrs:
+        yield '    rcx.enter_budget_scope(old.get_span());'
+    yield '    if !rcx.charge_recycle_budget() {'
+    if 'print' in get_rewrite_strategies(d):
+        yield '      return strategy::print::rewrite(old, new, rcx);'
+    else:
+        yield '      return false;'
+    yield '    }'
+    # The strategies themselves always run with deferral off.  See
+    # `strategy::partial`.
+    yield '    let defer = rcx.replace_defer_failures(false);'
     for strat in get_rewrite_strategies(d):
         yield '    let mark = rcx.mark();'
         if has_field(d, 'id'):
@@ -316,6 +376,7 @@ def do_rewrite_impl(d):
         yield '    if ok {'
         if has_field(d, 'id'):
             yield '      trace!("{:?}: rewrite: %s succeeded", new.id);' % strat
+        yield '      rcx.replace_defer_failures(defer);'
         yield '      return true;'
         yield '    } else {'
         if has_field(d, 'id'):
@@ -323,8 +384,24 @@ def do_rewrite_impl(d):
         yield '      rcx.rewind(mark);'
         yield '    }'
         yield ''
+    yield '    rcx.replace_defer_failures(defer);'
     if has_field(d, 'id'):
         yield '    trace!("{:?}: rewrite: ran out of strategies!", new.id);'
+    can_recurse = type_has_impl(d, 'Recursive')
+    can_defer = type_can_defer(d)
+    if can_recurse or can_defer:
+        yield '    if defer {'
+        if can_recurse:
+            # Retry with deferral on, so the failing children, rather than
+            # this whole node, get deferred.
+            yield '      let mark = rcx.mark();'
+            yield '      if strategy::recursive::rewrite(old, new, rcx.borrow()) {'
+            yield '        return true;'
+            yield '      }'
+            yield '      rcx.rewind(mark);'
+        if can_defer:
+            yield '      return strategy::partial::defer(old, new, rcx);'
+        yield '    }'
     yield '    false'
     yield '  }'
     yield '}'
@@ -365,9 +442,9 @@ def do_recursive_body(se, target1, target2):
             else:
                 outer_span_expr = f.attrs.get('seq_rewrite_outer_span')
                 if outer_span_expr is not None:
-                    # Replace `self.foo` with `foo2`, since we want the *old*
+                    # Replace `self.foo` with `foo1`, since we want the *old*
                     # outer span.
-                    outer_span_expr = rewrite_field_expr(outer_span_expr, '%s2')
+                    outer_span_expr = rewrite_field_expr(outer_span_expr, '%s1')
                 else:
                     outer_span_expr = 'DUMMY_SP'
                 mk_rewrite = lambda old, new: \
@@ -406,8 +483,23 @@ def do_recursive_body(se, target1, target2):
     yield '  (_, _) => false,'
     yield '}'
 
+PREC_FIELD_ATTRS = ('prec', 'prec_inc', 'prec_first', 'prec_left_of_binop',
+        'prec_right_of_binop', 'prec_special')
+
+def check_prec_attrs(d):
+    '''Check that `prec` attributes appear only where they take effect.'''
+    if 'prec_contains_expr' in d.attrs:
+        return
+    for v, path in variants_paths(d):
+        for f in v.fields:
+            for a in PREC_FIELD_ATTRS:
+                if a in f.attrs:
+                    raise ValueError('field %s of %s has #[%s], but %s is not '
+                            '#[prec_contains_expr]' % (f.name, path, a, d.name))
+
 @linewise
 def do_recursive_impl(d):
+    check_prec_attrs(d)
     if 'rewrite_ignore' in d.attrs:
         yield '#[allow(unused)]'
         yield 'impl Recursive for %s {' % d.name
@@ -486,6 +578,10 @@ def do_recover_children_impl(d):
     yield '#[allow(unused)]'
     yield 'impl RecoverChildren for %s {' % d.name
     yield '  fn recover_children(reparsed: &Self, new: &Self, mut rcx: RewriteCtxtRef) {'
+    if type_can_defer(d):
+        yield '    if rcx.locating_deferred() && partial::recover_deferred(reparsed, new, rcx.borrow()) {'
+        yield '      return;'
+        yield '    }'
     yield indent(do_record_node_span(d, 'reparsed', 'new', 'rcx'), '    ')
     yield indent(do_recover_children_match(d), '    ')
     yield '  }'
@@ -14,6 +14,13 @@ pub trait AstDeref {
 
 include!(concat!(env!("OUT_DIR"), "/ast_deref_gen.inc.rs"));
 
+impl<'a, T: AstDeref + ?Sized> AstDeref for &'a T {
+    type Target = <T as AstDeref>::Target;
+    fn ast_deref(&self) -> &Self::Target {
+        <T as AstDeref>::ast_deref(*self)
+    }
+}
+
 impl<T: AstDeref> AstDeref for P<T> {
     type Target = <T as AstDeref>::Target;
     fn ast_deref(&self) -> &Self::Target {
@@ -42,6 +42,9 @@ impl<T: AstEquiv> AstEquiv for Spanned<T> {
 
 impl<T: AstEquiv> AstEquiv for [T] {
     fn ast_equiv(&self, other: &[T]) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
         for (l, r) in self.iter().zip(other.iter()) {
             if !l.ast_equiv(r) {
                 return false;
@@ -0,0 +1,103 @@
+//! `AstHash` trait for hashing ASTs consistently with `AstEquiv`.
+use rustc_target::spec::abi::Abi;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+use syntax::ast::*;
+use syntax::parse::token::{DelimToken, Nonterminal, Token};
+use syntax::ptr::P;
+use syntax::source_map::{Span, Spanned};
+use syntax::tokenstream::{DelimSpan, TokenStream, TokenTree};
+use syntax::ThinVec;
+use syntax_pos::hygiene::SyntaxContext;
+
+/// Trait for hashing the structure of AST nodes.  Like `AstEquiv`, this ignores `Span`s,
+/// `NodeId`s, and other fields with no bearing on the semantics of the AST, so nodes that are
+/// equivalent under `AstEquiv` have equal hashes.  Unequal hashes mean the nodes are definitely
+/// not equivalent.
+pub trait AstHash {
+    fn ast_hash<H: Hasher>(&self, h: &mut H);
+}
+
+/// Compute the structural hash of `x`.
+pub fn structural_hash<T: AstHash + ?Sized>(x: &T) -> u64 {
+    let mut h = DefaultHasher::new();
+    x.ast_hash(&mut h);
+    h.finish()
+}
+
+impl<'a, T: AstHash + ?Sized> AstHash for &'a T {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        <T as AstHash>::ast_hash(*self, h)
+    }
+}
+
+impl<T: AstHash> AstHash for P<T> {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        <T as AstHash>::ast_hash(self, h)
+    }
+}
+
+impl<T: AstHash> AstHash for Rc<T> {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        <T as AstHash>::ast_hash(self, h)
+    }
+}
+
+impl<T: AstHash> AstHash for Spanned<T> {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        self.node.ast_hash(h)
+    }
+}
+
+impl<T: AstHash> AstHash for [T] {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        self.len().hash(h);
+        for x in self {
+            x.ast_hash(h);
+        }
+    }
+}
+
+impl<T: AstHash> AstHash for Vec<T> {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        <[T] as AstHash>::ast_hash(self, h)
+    }
+}
+
+impl<T: AstHash> AstHash for ThinVec<T> {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        <[T] as AstHash>::ast_hash(self, h)
+    }
+}
+
+impl<T: AstHash> AstHash for Option<T> {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        match *self {
+            Some(ref x) => {
+                1_u8.hash(h);
+                x.ast_hash(h);
+            }
+            None => 0_u8.hash(h),
+        }
+    }
+}
+
+impl<A: AstHash, B: AstHash> AstHash for (A, B) {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        self.0.ast_hash(h);
+        self.1.ast_hash(h);
+    }
+}
+
+impl<A: AstHash, B: AstHash, C: AstHash> AstHash for (A, B, C) {
+    fn ast_hash<H: Hasher>(&self, h: &mut H) {
+        self.0.ast_hash(h);
+        self.1.ast_hash(h);
+        self.2.ast_hash(h);
+    }
+}
+
+// Implementations for specific AST types are auto-generated.
+include!(concat!(env!("OUT_DIR"), "/ast_hash_gen.inc.rs"));
@@ -1,20 +1,28 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::ptr;
 use syntax::ast::*;
+use syntax::source_map::Span;
 use syntax::visit::{self, Visitor};
+use syntax_pos::BytePos;
 
-use crate::ast_manip::Visit;
+use crate::ast_manip::{structural_hash, AstEquiv, AstHash, GetSpan, Visit};
 
 /// A table of references to AST nodes of some type, indexed by NodeId.
 #[derive(Clone, Debug)]
 pub struct NodeTable<'s, T: ?Sized + 's> {
     nodes: HashMap<NodeId, &'s T>,
+    /// Structural hashes of the nodes, computed as each node is inserted.
+    hashes: HashMap<NodeId, u64>,
+    /// For each position where the span of some node starts, the nodes starting there, along with
+    /// the ends of their spans, sorted by end.  Built on first use by `get_by_span`, and dropped
+    /// whenever the table changes, so tables that are never searched by span don't pay for it.
+    spans: RefCell<Option<BTreeMap<BytePos, Vec<(BytePos, NodeId)>>>>,
 }
 
 impl<'s, T> Default for NodeTable<'s, T> {
     fn default() -> NodeTable<'s, T> {
-        NodeTable {
-            nodes: HashMap::new(),
-        }
+        NodeTable::new()
     }
 }
 
@@ -22,23 +30,131 @@ impl<'s, T: ?Sized> NodeTable<'s, T> {
     pub fn new() -> NodeTable<'s, T> {
         NodeTable {
             nodes: HashMap::new(),
+            hashes: HashMap::new(),
+            spans: RefCell::new(None),
+        }
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&'s T> {
+        self.nodes.get(&id).map(|&x| x)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Remove all nodes from the table, keeping its allocated storage for reuse.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.hashes.clear();
+        *self.spans.get_mut() = None;
+    }
+
+    /// Compare this table against `expected`, and get the IDs of nodes that are missing from this
+    /// table, that are absent from `expected`, or that refer to a different node than the one in
+    /// `expected`.  The IDs are sorted.
+    pub fn mismatched_ids(&self, expected: &NodeTable<'s, T>) -> Vec<NodeId> {
+        let mut ids = self
+            .nodes
+            .iter()
+            .filter(|&(id, &node)| {
+                expected
+                    .nodes
+                    .get(id)
+                    .map_or(true, |&other| !ptr::eq(node, other))
+            })
+            .map(|(&id, _)| id)
+            .chain(
+                expected
+                    .nodes
+                    .keys()
+                    .filter(|id| !self.nodes.contains_key(id))
+                    .cloned(),
+            )
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+}
+
+impl<'s, T: GetSpan + ?Sized> NodeTable<'s, T> {
+    /// Find the innermost node whose span contains `span`, such as the node enclosing an editor's
+    /// cursor position, given as an empty span.  Nodes with dummy spans are never found.  The
+    /// first call builds an index of the nodes' spans, which later calls reuse until the table
+    /// changes.
+    pub fn get_by_span(&self, span: Span) -> Option<(NodeId, &'s T)> {
+        let mut index = self.spans.borrow_mut();
+        let index = index.get_or_insert_with(|| {
+            let mut index = BTreeMap::new();
+            for (&id, &node) in &self.nodes {
+                let sp = node.get_span();
+                if sp.is_dummy() {
+                    continue;
+                }
+                index
+                    .entry(sp.lo())
+                    .or_insert_with(Vec::new)
+                    .push((sp.hi(), id));
+            }
+            for nodes in index.values_mut() {
+                nodes.sort();
+            }
+            index
+        });
+
+        // Spans of the AST nest, so the innermost node containing `span` is the first one found
+        // when going back from `span.lo()`, taking the shortest node at each position first.
+        for nodes in index.range(..=span.lo()).rev().map(|(_, nodes)| nodes) {
+            for &(hi, id) in nodes {
+                if hi >= span.hi() {
+                    return Some((id, self.nodes[&id]));
+                }
+            }
         }
+        None
     }
+}
 
+impl<'s, T: AstHash + ?Sized> NodeTable<'s, T> {
     pub fn insert(&mut self, id: NodeId, node: &'s T) {
         if id == DUMMY_NODE_ID {
             return;
         }
         assert!(!self.nodes.contains_key(&id));
+        self.insert_unchecked(id, node);
+    }
+
+    /// Like `insert`, but if a node with the same ID is already present, keep the existing node
+    /// instead of panicking.  Returns `true` if `node` was inserted.
+    pub fn try_insert(&mut self, id: NodeId, node: &'s T) -> bool {
+        if id == DUMMY_NODE_ID || self.nodes.contains_key(&id) {
+            return false;
+        }
+        self.insert_unchecked(id, node);
+        true
+    }
+
+    fn insert_unchecked(&mut self, id: NodeId, node: &'s T) {
         self.nodes.insert(id, node);
+        self.hashes.insert(id, structural_hash(node));
+        *self.spans.get_mut() = None;
     }
 
-    pub fn get(&self, id: NodeId) -> Option<&'s T> {
-        self.nodes.get(&id).map(|&x| x)
+    /// Get the structural hash of the node with ID `id`, as computed when the node was inserted.
+    pub fn structural_hash(&self, id: NodeId) -> Option<u64> {
+        self.hashes.get(&id).cloned()
     }
+}
 
-    pub fn len(&self) -> usize {
-        self.nodes.len()
+impl<'s, T: AstEquiv + AstHash + ?Sized> NodeTable<'s, T> {
+    /// Check whether the node with ID `id` is equivalent to `other`, as in `AstEquiv`, given the
+    /// structural hash of `other`.  If the hashes differ, the node is rejected without walking
+    /// both trees.  Returns `false` if there is no node with ID `id`.
+    pub fn equiv(&self, id: NodeId, other: &T, other_hash: u64) -> bool {
+        match self.structural_hash(id) {
+            Some(hash) => hash == other_hash && self.nodes[&id].ast_equiv(other),
+            None => false,
+        }
     }
 }
 
@@ -52,6 +168,11 @@ pub struct AstMap<'s> {
     pub items: NodeTable<'s, Item>,
     pub foreign_items: NodeTable<'s, ForeignItem>,
     pub blocks: NodeTable<'s, Block>,
+    pub lifetimes: NodeTable<'s, Lifetime>,
+    pub where_clauses: NodeTable<'s, WhereClause>,
+    /// Attributes have no `NodeId`, so they're indexed by their `AttrId` instead.  If several
+    /// attributes share an ID, only the first one is kept.
+    pub attrs: HashMap<AttrId, &'s Attribute>,
 }
 
 impl<'s> AstMap<'s> {
@@ -64,12 +185,74 @@ impl<'s> AstMap<'s> {
             items: NodeTable::new(),
             foreign_items: NodeTable::new(),
             blocks: NodeTable::new(),
+            lifetimes: NodeTable::new(),
+            where_clauses: NodeTable::new(),
+            attrs: HashMap::new(),
         }
     }
+
+    /// Remove all nodes from every table, keeping the allocated storage.  Together with
+    /// `map_ast_into`, this lets a tool processing many ASTs in turn reuse a single map.
+    pub fn clear(&mut self) {
+        self.exprs.clear();
+        self.pats.clear();
+        self.tys.clear();
+        self.stmts.clear();
+        self.items.clear();
+        self.foreign_items.clear();
+        self.blocks.clear();
+        self.lifetimes.clear();
+        self.where_clauses.clear();
+        self.attrs.clear();
+    }
+
+    /// Check this map for consistency with `ast`, which should be the AST it was built from.
+    /// This visits `ast` again to build a fresh map and checks that every table holds exactly the
+    /// nodes found by the fresh visit, as the same references.  On failure, returns the sorted IDs
+    /// of the missing, extra, or mismatched nodes.  This is a debugging aid for catching visitor
+    /// bugs; it isn't meant for use on every rewrite.
+    ///
+    /// Nodes are compared by `NodeId`, so duplicate IDs are handled as in `map_ast_lenient`.
+    /// Attributes have no `NodeId` and aren't checked.
+    pub fn verify_against<T: Visit>(&self, ast: &'s T) -> Result<(), Vec<NodeId>> {
+        let expected = map_ast_lenient(ast);
+        let mut ids = Vec::new();
+        ids.extend(self.exprs.mismatched_ids(&expected.exprs));
+        ids.extend(self.pats.mismatched_ids(&expected.pats));
+        ids.extend(self.tys.mismatched_ids(&expected.tys));
+        ids.extend(self.stmts.mismatched_ids(&expected.stmts));
+        ids.extend(self.items.mismatched_ids(&expected.items));
+        ids.extend(self.foreign_items.mismatched_ids(&expected.foreign_items));
+        ids.extend(self.blocks.mismatched_ids(&expected.blocks));
+        ids.extend(self.lifetimes.mismatched_ids(&expected.lifetimes));
+        ids.extend(self.where_clauses.mismatched_ids(&expected.where_clauses));
+        if ids.is_empty() {
+            return Ok(());
+        }
+        ids.sort();
+        ids.dedup();
+        Err(ids)
+    }
 }
 
 struct MapAstInto<'a, 's: 'a> {
     map: &'a mut AstMap<'s>,
+    /// If set, nodes with duplicate IDs are ignored instead of causing a panic.  Transforms are
+    /// allowed to copy nodes without renumbering them, so the new AST may contain duplicates.
+    allow_duplicates: bool,
+}
+
+fn insert_node<'s, T: AstHash>(
+    table: &mut NodeTable<'s, T>,
+    id: NodeId,
+    node: &'s T,
+    allow_duplicates: bool,
+) {
+    if allow_duplicates {
+        table.try_insert(id, node);
+    } else {
+        table.insert(id, node);
+    }
 }
 
 impl<'a, 's> Visitor<'s> for MapAstInto<'a, 's> {
@@ -78,48 +261,86 @@ impl<'a, 's> Visitor<'s> for MapAstInto<'a, 's> {
             // Ignore.  `Paren` nodes cause problems because they have the same NodeId as the inner
             // expression.
         } else {
-            self.map.exprs.insert(x.id, x);
+            insert_node(&mut self.map.exprs, x.id, x, self.allow_duplicates);
         }
         visit::walk_expr(self, x);
     }
 
     fn visit_pat(&mut self, x: &'s Pat) {
-        self.map.pats.insert(x.id, x);
+        insert_node(&mut self.map.pats, x.id, x, self.allow_duplicates);
         visit::walk_pat(self, x);
     }
 
     fn visit_ty(&mut self, x: &'s Ty) {
-        self.map.tys.insert(x.id, x);
+        insert_node(&mut self.map.tys, x.id, x, self.allow_duplicates);
         visit::walk_ty(self, x);
     }
 
     fn visit_stmt(&mut self, x: &'s Stmt) {
-        self.map.stmts.insert(x.id, x);
+        insert_node(&mut self.map.stmts, x.id, x, self.allow_duplicates);
         visit::walk_stmt(self, x);
     }
 
     fn visit_item(&mut self, x: &'s Item) {
-        self.map.items.insert(x.id, x);
+        insert_node(&mut self.map.items, x.id, x, self.allow_duplicates);
         visit::walk_item(self, x);
     }
 
     fn visit_foreign_item(&mut self, x: &'s ForeignItem) {
-        self.map.foreign_items.insert(x.id, x);
+        insert_node(&mut self.map.foreign_items, x.id, x, self.allow_duplicates);
         visit::walk_foreign_item(self, x);
     }
 
     fn visit_block(&mut self, x: &'s Block) {
-        self.map.blocks.insert(x.id, x);
+        insert_node(&mut self.map.blocks, x.id, x, self.allow_duplicates);
         visit::walk_block(self, x);
     }
 
+    fn visit_lifetime(&mut self, x: &'s Lifetime) {
+        insert_node(&mut self.map.lifetimes, x.id, x, self.allow_duplicates);
+        visit::walk_lifetime(self, x);
+    }
+
+    fn visit_generics(&mut self, x: &'s Generics) {
+        insert_node(
+            &mut self.map.where_clauses,
+            x.where_clause.id,
+            &x.where_clause,
+            self.allow_duplicates,
+        );
+        visit::walk_generics(self, x);
+    }
+
+    fn visit_attribute(&mut self, x: &'s Attribute) {
+        self.map.attrs.entry(x.id).or_insert(x);
+    }
+
     fn visit_mac(&mut self, mac: &'s Mac) {
         visit::walk_mac(self, mac);
     }
 }
 
 pub fn map_ast_into<'s, T: Visit>(x: &'s T, map: &mut AstMap<'s>) {
-    x.visit(&mut MapAstInto { map })
+    x.visit(&mut MapAstInto {
+        map,
+        allow_duplicates: false,
+    })
+}
+
+/// Like `map_ast_into`, but tolerates duplicate IDs, as in `map_ast_lenient`.
+pub fn map_ast_lenient_into<'s, T: Visit>(x: &'s T, map: &mut AstMap<'s>) {
+    x.visit(&mut MapAstInto {
+        map,
+        allow_duplicates: true,
+    })
+}
+
+/// Like `map_ast`, but tolerates nodes with duplicate IDs, keeping only the first node seen for
+/// each ID.  This is suitable for mapping ASTs produced by transforms.
+pub fn map_ast_lenient<'s, T: Visit>(x: &'s T) -> AstMap<'s> {
+    let mut m = AstMap::new();
+    map_ast_lenient_into(x, &mut m);
+    m
 }
 
 pub fn map_ast<'s, T: Visit>(x: &'s T) -> AstMap<'s> {
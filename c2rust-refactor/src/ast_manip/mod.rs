@@ -4,6 +4,7 @@
 // Modules with simple APIs are private, with their public definitions reexported.
 mod ast_deref;
 mod ast_equiv;
+mod ast_hash;
 mod ast_names;
 mod fold;
 mod get_node_id;
@@ -17,6 +18,7 @@ mod visit_node;
 
 pub use self::ast_deref::AstDeref;
 pub use self::ast_equiv::AstEquiv;
+pub use self::ast_hash::{structural_hash, AstHash};
 pub use self::ast_names::AstName;
 pub use self::fold::{FlatMapNodes, MutVisit, MutVisitNodes};
 pub use self::get_node_id::{GetNodeId, MaybeGetNodeId};
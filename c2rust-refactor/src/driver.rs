@@ -630,6 +630,7 @@ pub fn parse_foreign_items(sess: &Session, src: &str) -> Vec<ForeignItem> {
 pub fn parse_block(sess: &Session, src: &str) -> P<Block> {
     let mut p = make_parser(sess, src);
 
+    let lo = p.span;
     let rules = if p.eat_keyword(keywords::Unsafe) {
         BlockCheckMode::Unsafe(UnsafeSource::UserProvided)
     } else {
@@ -640,6 +641,9 @@ pub fn parse_block(sess: &Session, src: &str) -> P<Block> {
         Ok(mut block) => {
             remove_paren(&mut block);
             block.rules = rules;
+            // Like the spans of blocks parsed in their usual context, the span covers the
+            // `unsafe` keyword.
+            block.span = lo.to(block.span);
             block
         }
         Err(db) => emit_and_panic(db, "block"),
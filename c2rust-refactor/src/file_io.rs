@@ -214,7 +214,7 @@ impl FileIO for RealFileIO {
             new_span: Span::new(sf.start_pos, sf.end_pos, SyntaxContext::empty()),
             rewrites: rws.to_owned(),
             nodes: nodes.to_owned(),
-            adjust: rewrite::TextAdjust::None,
+            adjust: rewrite::TextAdjust::NONE,
         };
         state
             .rewrites_json
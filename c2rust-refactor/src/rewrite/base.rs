@@ -15,6 +15,7 @@
 //! `[T]` implementation.
 use rustc_target::spec::abi::Abi;
 use syntax::ast::*;
+use syntax::parse::lexer::StringReader;
 use syntax::parse::token::{DelimToken, Nonterminal, Token};
 use syntax::source_map::{Span, SyntaxContext};
 use syntax::tokenstream::{DelimSpan, TokenStream, TokenTree};
@@ -29,11 +30,11 @@ use syntax::source_map::{Spanned, DUMMY_SP};
 use syntax::util::parser::{AssocOp, Fixity};
 use syntax_pos::{BytePos, Pos};
 
-use crate::ast_manip::{AstDeref, CommentStyle, GetSpan};
+use crate::ast_manip::{AstDeref, AstEquiv, CommentStyle, GetSpan};
 
 use super::strategy;
 use super::strategy::print;
-use super::{ExprPrec, RewriteCtxt, RewriteCtxtRef, SeqItemId, TextRewrite};
+use super::{ExprPrec, RewriteCtxt, RewriteCtxtRef, SeqItemId, TextRewrite, TrailingComma};
 
 pub trait Rewrite {
     /// Given an old AST, a new AST, and text corresponding to the old AST, transform the text into
@@ -59,11 +60,35 @@ impl<T: Rewrite> Rewrite for Rc<T> {
 }
 
 impl<T: Rewrite> Rewrite for Spanned<T> {
-    fn rewrite(old: &Self, new: &Self, rcx: RewriteCtxtRef) -> bool {
+    default fn rewrite(old: &Self, new: &Self, rcx: RewriteCtxtRef) -> bool {
         <T as Rewrite>::rewrite(&old.node, &new.node, rcx)
     }
 }
 
+/// `Visibility` is a `Spanned<VisibilityKind>`, but unlike most `Spanned` nodes, its span is
+/// meaningful (see `strategy::vis`), so it gets its own strategy on top of the usual ones.
+impl Rewrite for Visibility {
+    fn rewrite(old: &Self, new: &Self, mut rcx: RewriteCtxtRef) -> bool {
+        if rcx.with_temp_rewrites(|rcx| Rewrite::rewrite(&old.node, &new.node, rcx)) {
+            return true;
+        }
+
+        strategy::vis::rewrite(old, new, rcx)
+    }
+}
+
+/// Adding or removing the discriminant of a `Variant` needs the variant's span (see
+/// `strategy::variant`), so `Variant` also gets its own strategy on top of the usual ones.
+impl Rewrite for Variant {
+    fn rewrite(old: &Self, new: &Self, mut rcx: RewriteCtxtRef) -> bool {
+        if rcx.with_temp_rewrites(|rcx| Rewrite::rewrite(&old.node, &new.node, rcx)) {
+            return true;
+        }
+
+        strategy::variant::rewrite(old, new, rcx)
+    }
+}
+
 impl<T: Rewrite> Rewrite for Option<T> {
     fn rewrite(old: &Self, new: &Self, rcx: RewriteCtxtRef) -> bool {
         match (old, new) {
@@ -116,6 +141,12 @@ impl<T: MaybeRewriteSeq> Rewrite for ThinVec<T> {
 /// deleting, and moving around of individual items.
 pub trait SeqItem {
     fn seq_item_id(&self) -> SeqItemId;
+
+    /// Whether the item must go on a line of its own.  This is true of `///` doc comments, which
+    /// comment out anything following them on the same line.
+    fn needs_own_line(&self) -> bool {
+        false
+    }
 }
 
 include!(concat!(env!("OUT_DIR"), "/rewrite_seq_item_gen.inc.rs"));
@@ -124,6 +155,10 @@ impl SeqItem for Attribute {
     fn seq_item_id(&self) -> SeqItemId {
         SeqItemId::Attr(self.id)
     }
+
+    fn needs_own_line(&self) -> bool {
+        self.is_sugared_doc
+    }
 }
 
 impl SeqItem for Arg {
@@ -136,6 +171,10 @@ impl<T: SeqItem> SeqItem for P<T> {
     fn seq_item_id(&self) -> SeqItemId {
         <T as SeqItem>::seq_item_id(self)
     }
+
+    fn needs_own_line(&self) -> bool {
+        <T as SeqItem>::needs_own_line(self)
+    }
 }
 
 /// This trait is implemented (using generated impls) for every node type.  On types that implement
@@ -158,6 +197,73 @@ include!(concat!(
     "/rewrite_maybe_rewrite_seq_gen.inc.rs"
 ));
 
+/// Outer attributes (`#[...]`) and inner attributes (`#![...]`) are rewritten as two separate
+/// sequences, since they live in different places: outer attributes come before the node they
+/// apply to, while inner ones come after the opening brace of a module or block, or at the top of
+/// a module's file.  Handling them as one sequence could insert an inner attribute among the outer
+/// ones or vice versa, either of which changes what it applies to.  An attribute that switches
+/// between inner and outer is deleted from one sequence and inserted into the other, rather than
+/// reprinted in its old place.  Both sequences keep their old order.
+///
+/// `outer_span`, if given, should be an empty span at the start of the node the attributes apply
+/// to, where new outer attributes can go when the node had none before.
+impl MaybeRewriteSeq for Attribute {
+    fn maybe_rewrite_seq(
+        old: &[Self],
+        new: &[Self],
+        outer_span: Span,
+        mut rcx: RewriteCtxtRef,
+    ) -> bool {
+        for &style in &[AttrStyle::Outer, AttrStyle::Inner] {
+            let old = old.iter().filter(|a| a.style == style).collect::<Vec<_>>();
+            let new = new.iter().filter(|a| a.style == style).collect::<Vec<_>>();
+            let paired;
+            let new = if rcx.options().preserve_lint_attrs {
+                paired = pair_lint_attrs(&old, &new);
+                paired.iter().collect::<Vec<_>>()
+            } else {
+                new
+            };
+            // With no old attributes of this style, and no `outer_span` for outer ones, there's no
+            // telling where new ones should go, so `rewrite_seq` fails and the enclosing node gets
+            // reprinted.
+            let seq_span = if style == AttrStyle::Outer && old.is_empty() {
+                outer_span
+            } else {
+                DUMMY_SP
+            };
+            if !rewrite_seq(&old, &new, seq_span, rcx.borrow()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Give each rebuilt lint attribute in `new`, whose ID is not in `old`, the ID of an equivalent old
+/// attribute (see `strategy::lint_attr`) that has no counterpart in `new`.  `rewrite_seq` then
+/// matches the two up and keeps the old text, instead of deleting it and printing the new one.
+fn pair_lint_attrs(old: &[&Attribute], new: &[&Attribute]) -> Vec<Attribute> {
+    let mut unmatched = old
+        .iter()
+        .filter(|a| !new.iter().any(|b| b.id == a.id))
+        .collect::<Vec<_>>();
+    new.iter()
+        .map(|&a| {
+            let mut a = a.clone();
+            if !old.iter().any(|b| b.id == a.id) {
+                if let Some(i) = unmatched
+                    .iter()
+                    .position(|b| strategy::lint_attr::lint_equiv(b, &a))
+                {
+                    a.id = unmatched.remove(i).id;
+                }
+            }
+            a
+        })
+        .collect()
+}
+
 impl<T: Rewrite> MaybeRewriteSeq for Spanned<T> {}
 impl<A: Rewrite, B: Rewrite> MaybeRewriteSeq for (A, B) {}
 
@@ -223,6 +329,12 @@ where
                     SeqItemId::Node(id) => extend_span_comments(&id, old_span, &rcx),
                     _ => old_span,
                 };
+                // Don't leave an empty line behind in place of a doc comment.
+                let old_span = if ast(&old[i]).needs_own_line() {
+                    whole_lines_span(rcx.session(), old_span).unwrap_or(old_span)
+                } else {
+                    old_span
+                };
 
                 info!(
                     "DELETE {}",
@@ -246,6 +358,36 @@ where
                     outer_span.shrink_to_hi()
                 };
 
+                // An item that needs a line of its own, or that follows one that does, goes at
+                // the start of a new line instead of right next to its neighbor.
+                let own_line = if !ast(&new[j]).needs_own_line()
+                    && !(i > 0 && ast(&old[i - 1]).needs_own_line())
+                {
+                    None
+                } else if i > 0 && is_rewritable(before) {
+                    next_line_start(rcx.session(), before)
+                } else if is_rewritable(before) {
+                    line_start(rcx.session(), before)
+                } else if is_rewritable(after) {
+                    line_start(rcx.session(), after)
+                } else {
+                    None
+                };
+
+                if let Some((at, indent)) = own_line {
+                    info!("INSERT (OWN LINE) {}", describe(rcx.session(), at));
+                    rcx.record_text(at, &indent);
+                    if !ast(&new[j]).rewrite_at(at, rcx.borrow()) {
+                        return false;
+                    }
+                    // Items that need their own line are printed with a trailing newline already.
+                    if !ast(&new[j]).needs_own_line() {
+                        rcx.record_text(at, "\n");
+                    }
+                    j += 1;
+                    continue;
+                }
+
                 let old_span = if is_rewritable(before) {
                     before.with_lo(before.hi())
                 } else if is_rewritable(after) {
@@ -396,6 +538,260 @@ where
     true
 }
 
+/// The separator between elements of a list handled by `rewrite_list`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ListSep {
+    /// `,`, as in argument lists and `where` clauses.  A trailing comma is allowed.
+    Comma,
+    /// `+`, as in the bounds of a trait object type.
+    Plus,
+    /// `|`, as in the patterns of a `match` arm.
+    Pipe,
+}
+
+impl ListSep {
+    fn token(self) -> &'static str {
+        match self {
+            ListSep::Comma => ",",
+            ListSep::Plus => "+",
+            ListSep::Pipe => "|",
+        }
+    }
+}
+
+/// Get the separator text to insert between elements of the list whose elements have spans
+/// `spans`.
+fn list_separator(rcx: &RewriteCtxtRef, spans: &[Span], sep: ListSep) -> String {
+    let cm = rcx.session().source_map();
+
+    if spans.len() >= 2 {
+        match rcx.span_info(spans[0].between(spans[1])) {
+            // Don't copy any comments that appear between the elements.
+            Some(ref info) if info.text.trim() == sep.token() => return info.text.clone(),
+            _ => {}
+        }
+    }
+
+    // With only one element, check whether it's on a line of its own, as in a multi-line `where`
+    // clause.  This doesn't apply to the patterns of a `match` arm, since each arm starts a line
+    // of its own anyway.
+    if let Ok(before) = cm.span_to_prev_source(spans[0]) {
+        if let Some(idx) = before.rfind('\n') {
+            let indent = &before[idx + 1..];
+            if indent.trim().is_empty() && sep != ListSep::Pipe {
+                return match sep {
+                    ListSep::Comma => format!(",\n{}", indent),
+                    _ => format!(" {}\n{}", sep.token(), indent),
+                };
+            }
+        }
+    }
+
+    match sep {
+        ListSep::Comma => ", ".to_owned(),
+        ListSep::Plus => " + ".to_owned(),
+        ListSep::Pipe => " | ".to_owned(),
+    }
+}
+
+/// Wrapper for matching up list elements by structural equality, for use as keys in
+/// `rewrite_list`.  This is useful for elements that have no `NodeId`.
+pub struct EquivKey<'a, T>(pub &'a T);
+
+impl<'a, T: AstEquiv> PartialEq for EquivKey<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.ast_equiv(other.0)
+    }
+}
+
+/// Rewrite the non-empty list `old`, whose elements are separated by `sep`, into the non-empty
+/// list `new`.  Unlike `rewrite_seq_comma_sep`, this works on element types that aren't
+/// `SeqItem`s, and it takes care of the separators itself: `old_spans` should cover only the
+/// elements, not their separators.
+///
+/// `old_keys` and `new_keys` are used to match up old and new elements.  Matched elements are
+/// rewritten in place, and any that fail to rewrite are replaced using `splice`, which should
+/// record a rewrite of the given old span to the text of the new element.  New elements are
+/// inserted using `splice` on an empty span, with the same separator the old list uses.  Removed
+/// elements are deleted along with their separators, and any trailing separator after the last
+/// element is left alone.
+pub fn rewrite_list<T: Rewrite, K: PartialEq>(
+    old: &[T],
+    new: &[T],
+    old_spans: &[Span],
+    old_keys: &[K],
+    new_keys: &[K],
+    sep: ListSep,
+    splice: fn(&T, Span, RewriteCtxtRef) -> bool,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let list_sep = sep;
+    let sep = list_separator(&rcx, old_spans, sep);
+
+    // For each old element, the index of the matching new element, if any.
+    let mut matches = vec![None; old.len()];
+    // Indices of new elements to insert, each paired with the index of the last matched old
+    // element preceding it.
+    let mut inserts = Vec::new();
+
+    let mut i = 0;
+    let mut j = 0;
+    let mut last_match = None;
+    for step in diff::slice(old_keys, new_keys) {
+        match step {
+            diff::Result::Left(_) => {
+                i += 1;
+            }
+            diff::Result::Right(_) => {
+                inserts.push((last_match, j));
+                j += 1;
+            }
+            diff::Result::Both(_, _) => {
+                matches[i] = Some(j);
+                last_match = Some(i);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    let kept = (0..old.len())
+        .filter(|&i| matches[i].is_some())
+        .collect::<Vec<_>>();
+    if kept.is_empty() {
+        // Nothing to recycle, so replace the whole list.
+        let old_span = old_spans[0].to(old_spans[old.len() - 1]);
+        info!("REWRITE (LIST) {}", describe(rcx.session(), old_span));
+        if !splice(&new[0], old_span, rcx.borrow()) {
+            return false;
+        }
+        let end = old_span.shrink_to_hi();
+        for x in &new[1..] {
+            rcx.record_text(end, &sep);
+            if !splice(x, end, rcx.borrow()) {
+                return false;
+            }
+        }
+        if list_sep == ListSep::Comma {
+            rewrite_trailing_comma(old_span, new.len(), rcx);
+        }
+        return true;
+    }
+
+    for (i, m) in matches.iter().enumerate() {
+        if let Some(j) = *m {
+            if !rcx.with_temp_rewrites(|rcx| Rewrite::rewrite(&old[i], &new[j], rcx)) {
+                info!("REWRITE (LIST) {}", describe(rcx.session(), old_spans[i]));
+                if !splice(&new[j], old_spans[i], rcx.borrow()) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    // Delete unmatched elements.  Between each pair of consecutive kept elements, we keep only the
+    // separator immediately following the first one.
+    let last_kept = *kept.last().unwrap();
+    let mut pending: Option<Span> = None;
+    let delete = |sp: Span, rcx: &mut RewriteCtxtRef| {
+        info!("DELETE (LIST) {}", describe(rcx.session(), sp));
+        rcx.record_text(sp, "");
+    };
+    for i in 0..old.len() {
+        if matches[i].is_none() {
+            pending = Some(pending.map_or(old_spans[i], |sp| sp.to(old_spans[i])));
+        } else if let Some(sp) = pending.take() {
+            delete(sp, &mut rcx);
+        }
+
+        if i + 1 < old.len() {
+            let gap = old_spans[i].between(old_spans[i + 1]);
+            if matches[i].is_some() && i < last_kept {
+                if let Some(sp) = pending.take() {
+                    delete(sp, &mut rcx);
+                }
+            } else {
+                pending = Some(pending.map_or(gap, |sp| sp.to(gap)));
+            }
+        }
+    }
+    if let Some(sp) = pending.take() {
+        delete(sp, &mut rcx);
+    }
+
+    for (anchor, j) in inserts {
+        match anchor {
+            Some(i) => {
+                let at = old_spans[i].shrink_to_hi();
+                info!("INSERT (LIST) {}", describe(rcx.session(), at));
+                rcx.record_text(at, &sep);
+                if !splice(&new[j], at, rcx.borrow()) {
+                    return false;
+                }
+            }
+            None => {
+                let at = old_spans[kept[0]].shrink_to_lo();
+                info!("INSERT (LIST) {}", describe(rcx.session(), at));
+                if !splice(&new[j], at, rcx.borrow()) {
+                    return false;
+                }
+                rcx.record_text(at, &sep);
+            }
+        }
+    }
+
+    if list_sep == ListSep::Comma {
+        rewrite_trailing_comma(old_spans[old.len() - 1], new.len(), rcx);
+    }
+    true
+}
+
+/// Add or delete the trailing comma of a rewritten comma-separated list, as required by the
+/// `trailing_comma` option.  `last_span` is the old span of the list's last element, and `len` is
+/// the number of elements in the new list.
+///
+/// This relies on the list's old trailing comma, if any, staying in place after the new last
+/// element, which `rewrite_list` ensures.  Comments between the last element and the comma aren't
+/// handled, and such a comma is left alone.
+pub fn rewrite_trailing_comma(last_span: Span, len: usize, mut rcx: RewriteCtxtRef) {
+    let policy = rcx.options().trailing_comma;
+    if policy == TrailingComma::Preserve {
+        return;
+    }
+
+    let loc = rcx.session().source_map().lookup_byte_offset(last_span.hi());
+    let src = match loc.sf.src {
+        Some(ref src) => src,
+        None => return,
+    };
+    let after = &src[loc.pos.to_usize()..];
+    let comma_pos = after.len() - after.trim_start().len();
+    let has_comma = after[comma_pos..].starts_with(',');
+    let rest = if has_comma {
+        &after[comma_pos + 1..]
+    } else {
+        after
+    };
+    let vertical = rest[..rest.len() - rest.trim_start().len()].contains('\n');
+
+    let want_comma = match policy {
+        TrailingComma::Preserve => has_comma,
+        TrailingComma::Always => true,
+        TrailingComma::Never => has_comma && len == 1,
+        TrailingComma::Vertical => vertical,
+    };
+    if has_comma && !want_comma {
+        let lo = last_span.hi() + BytePos::from_usize(comma_pos);
+        let comma_span = last_span.with_lo(lo).with_hi(lo + BytePos(1));
+        info!("DELETE (COMMA) {}", describe(rcx.session(), comma_span));
+        rcx.record_text(comma_span, "");
+    } else if !has_comma && want_comma {
+        let at = last_span.shrink_to_hi();
+        info!("INSERT (COMMA) {}", describe(rcx.session(), at));
+        rcx.record_text(at, ",");
+    }
+}
+
 // Misc helpers
 
 pub fn binop_left_prec(op: &BinOp) -> ExprPrec {
@@ -452,6 +848,95 @@ pub fn describe(sess: &Session, span: Span) -> String {
     }
 }
 
+/// Extend `sp` forward to cover any spaces and tabs immediately following it.
+pub fn extend_span_spaces(sess: &Session, sp: Span) -> Span {
+    let loc = sess.source_map().lookup_byte_offset(sp.hi());
+    let src = match loc.sf.src {
+        Some(ref src) => src,
+        None => return sp,
+    };
+    let rest = &src[loc.pos.to_usize()..];
+    let len = rest.len() - rest.trim_start_matches(|c| c == ' ' || c == '\t').len();
+    sp.with_hi(sp.hi() + BytePos::from_usize(len))
+}
+
+/// Extend `sp` backward to cover any whitespace (including newlines) immediately preceding it.
+pub fn extend_span_back_whitespace(sess: &Session, sp: Span) -> Span {
+    let loc = sess.source_map().lookup_byte_offset(sp.lo());
+    let src = match loc.sf.src {
+        Some(ref src) => src,
+        None => return sp,
+    };
+    let before = &src[..loc.pos.to_usize()];
+    let len = before.len() - before.trim_end().len();
+    sp.with_lo(sp.lo() - BytePos::from_usize(len))
+}
+
+/// If `sp` starts at the beginning of a line, after only indentation, get an empty span at the
+/// start of that line, along with the indentation.
+pub fn line_start(sess: &Session, sp: Span) -> Option<(Span, String)> {
+    let loc = sess.source_map().lookup_byte_offset(sp.lo());
+    let src = loc.sf.src.as_ref()?;
+    let before = &src[..loc.pos.to_usize()];
+    let indent = &before[before.rfind('\n').map_or(0, |i| i + 1)..];
+    if !indent.trim().is_empty() {
+        return None;
+    }
+    let lo = sp.lo() - BytePos::from_usize(indent.len());
+    Some((sp.with_lo(lo).with_hi(lo), indent.to_owned()))
+}
+
+/// If `sp` ends its line, with only whitespace after it, get an empty span at the start of the
+/// next line, along with the indentation of the line where `sp` starts.
+pub fn next_line_start(sess: &Session, sp: Span) -> Option<(Span, String)> {
+    let loc = sess.source_map().lookup_byte_offset(sp.hi());
+    let src = loc.sf.src.as_ref()?;
+    let rest = &src[loc.pos.to_usize()..];
+    let eol = rest.find('\n')?;
+    if !rest[..eol].trim().is_empty() {
+        return None;
+    }
+
+    let hi = sp.hi() + BytePos::from_usize(eol + 1);
+    Some((sp.with_hi(hi).with_lo(hi), line_indent(sess, sp)))
+}
+
+/// Get the indentation of the line where `sp` starts.
+pub fn line_indent(sess: &Session, sp: Span) -> String {
+    let loc = sess.source_map().lookup_byte_offset(sp.lo());
+    let src = match loc.sf.src {
+        Some(ref src) => src,
+        None => return String::new(),
+    };
+    let before = &src[..loc.pos.to_usize()];
+    let line = &before[before.rfind('\n').map_or(0, |i| i + 1)..];
+    line[..line.len() - line.trim_start().len()].to_owned()
+}
+
+/// If `sp` is alone on its lines, get the span of those whole lines, including the indentation
+/// before `sp` and the newline after it.
+pub fn whole_lines_span(sess: &Session, sp: Span) -> Option<Span> {
+    let (start, _) = line_start(sess, sp)?;
+    let (end, _) = next_line_start(sess, sp)?;
+    Some(sp.with_lo(start.lo()).with_hi(end.hi()))
+}
+
+/// Lex the source text of `sp`, returning each token along with its span.  Whitespace and comments
+/// are omitted.  Returns `None` if the text can't be lexed.
+pub fn span_tokens(sess: &Session, sp: Span) -> Option<Vec<(Token, Span)>> {
+    let mut lexer = StringReader::retokenize(&sess.parse_sess, sp);
+    let mut tokens = Vec::new();
+    loop {
+        let tok = lexer.try_next_token().ok()?;
+        match tok.tok {
+            Token::Eof => break,
+            Token::Whitespace | Token::Comment | Token::Shebang(_) => {}
+            t => tokens.push((t, tok.sp)),
+        }
+    }
+    Some(tokens)
+}
+
 /// Extend a node span to cover comments around it.
 pub fn extend_span_comments(id: &NodeId, mut span: Span, rcx: &RewriteCtxt) -> Span {
     let comments = match rcx.comments().get(id) {
@@ -485,7 +970,7 @@ pub fn extend_span_comments(id: &NodeId, mut span: Span, rcx: &RewriteCtxt) -> S
 
     for comment in &before {
         let comment_span = span.shrink_to_lo().with_lo(comment.pos);
-        let source = rcx.session().source_map().span_to_snippet(comment_span).unwrap();
+        let source = &rcx.span_info(comment_span).unwrap().text;
         let matches = source.lines().zip(&comment.lines).all(|(src_line, comment_line)| {
             src_line.trim() == comment_line.trim()
         });
@@ -520,7 +1005,7 @@ pub fn extend_span_comments(id: &NodeId, mut span: Span, rcx: &RewriteCtxt) -> S
                 BytePos::from_usize(span.hi().to_usize() + comment_line.len())
             };
             let line_span = span.shrink_to_hi().with_hi(line_end);
-            let src_line = rcx.session().source_map().span_to_snippet(line_span).unwrap();
+            let src_line = &rcx.span_info(line_span).unwrap().text;
             if comment_line.trim() == src_line.trim() {
                 span = span.with_hi(line_end);
             } else {
@@ -1,6 +1,8 @@
+use rustc::session::Session;
 use syntax::source_map::{SourceMap, Span, DUMMY_SP};
 
-use crate::rewrite::TextRewrite;
+use crate::rewrite::span_util::{adjacent, overlaps};
+use crate::rewrite::{TextAdjust, TextRewrite};
 
 fn empty_span(sp: Span) -> bool {
     sp.lo() == sp.hi()
@@ -33,6 +35,30 @@ fn equal_span_text(cm: &SourceMap, sp1: Span, sp2: Span) -> bool {
 /// Clean up a list of rewrites, sorting them and trying to remove all overlapping rewrites without
 /// affecting the meaning of the rewrite list.
 pub fn cleanup_rewrites(cm: &SourceMap, rws: Vec<TextRewrite>) -> Vec<TextRewrite> {
+    cleanup_rewrites_with(cm, rws, &mut |prev, cur| {
+        panic!("conflicting rewrites:\nprev = {:#?}\ncur = {:#?}", prev, cur)
+    })
+}
+
+/// Find all pairs of rewrites in `rws` (including nested rewrites) that overlap in a way
+/// `cleanup_rewrites` can't resolve.  `cleanup_rewrites` panics if the result is non-empty.  Each
+/// pair gives the `old_span`s of the earlier and later rewrites.
+pub fn find_conflicts(cm: &SourceMap, rws: &[TextRewrite]) -> Vec<(Span, Span)> {
+    let mut conflicts = Vec::new();
+    cleanup_rewrites_with(cm, rws.to_owned(), &mut |prev, cur| {
+        conflicts.push((prev.old_span, cur.old_span))
+    });
+    conflicts
+}
+
+/// Like `cleanup_rewrites`, but calls `on_conflict` with the previous and current rewrites upon
+/// finding a conflict, and then discards the current one.  `on_conflict` may replace the previous
+/// rewrite, which is kept in the result.
+pub fn cleanup_rewrites_with(
+    cm: &SourceMap,
+    rws: Vec<TextRewrite>,
+    on_conflict: &mut FnMut(&mut TextRewrite, &TextRewrite),
+) -> Vec<TextRewrite> {
     let mut rws = rws;
     // Sort by start position ascending, then by end position descending.  This way, in case of a
     // pair of overlapping rewrites with the same start position, we see the longest one first.
@@ -51,11 +77,11 @@ pub fn cleanup_rewrites(cm: &SourceMap, rws: Vec<TextRewrite>) -> Vec<TextRewrit
             continue;
         }
 
-        rw.rewrites = cleanup_rewrites(cm, rw.rewrites);
+        rw.rewrites = cleanup_rewrites_with(cm, rw.rewrites, on_conflict);
 
         if new_rws
             .last()
-            .map_or(true, |prev| prev.old_span.hi().0 <= rw.old_span.lo().0)
+            .map_or(true, |prev| !overlaps(prev.old_span, rw.old_span))
         {
             // This rewrite doesn't overlap the previous rewrite, so we can keep it.
             //
@@ -66,7 +92,7 @@ pub fn cleanup_rewrites(cm: &SourceMap, rws: Vec<TextRewrite>) -> Vec<TextRewrit
         }
 
         // This rewrite *does* overlap the previous rewrite.  That's not allowed in `new_rws`, so
-        // we're either going to discard it or panic.
+        // we're either going to discard it or report a conflict.
 
         let prev = new_rws.last().unwrap();
 
@@ -90,8 +116,113 @@ pub fn cleanup_rewrites(cm: &SourceMap, rws: Vec<TextRewrite>) -> Vec<TextRewrit
             continue;
         }
 
-        panic!("conflicting rewrites:\nprev = {:#?}\ncur = {:#?}", prev, rw);
+        on_conflict(new_rws.last_mut().unwrap(), &rw);
+    }
+
+    new_rws
+}
+
+/// Check whether `rw` can be appended to `prev` to form a single rewrite.  This requires that both
+/// the old and the new spans of the two rewrites are contiguous, and that neither rewrite has an
+/// adjustment (merging would move the text added by a `PARENTHESIZE` rewrite, for example).
+fn can_merge(cm: &SourceMap, prev: &TextRewrite, rw: &TextRewrite) -> bool {
+    if prev.adjust != TextAdjust::NONE || rw.adjust != TextAdjust::NONE {
+        return false;
+    }
+    if prev.new_span == DUMMY_SP || rw.new_span == DUMMY_SP {
+        return false;
+    }
+    if !adjacent(prev.old_span, rw.old_span) || !adjacent(prev.new_span, rw.new_span) {
+        return false;
+    }
+    let sf1 = cm.lookup_byte_offset(prev.new_span.lo()).sf;
+    let sf2 = cm.lookup_byte_offset(rw.new_span.hi()).sf;
+    sf1.start_pos == sf2.start_pos
+}
+
+/// Remove every rewrite in `rws` that would change the text of one of the `verbatim` regions,
+/// leaving the old text of those regions intact.  Returns the `old_span`s of the removed
+/// rewrites.
+///
+/// Only the top-level rewrites in `rws` are checked.  Nested rewrites modify the new text of
+/// their parent rewrite, not the old source.
+pub fn suppress_verbatim(rws: &mut Vec<TextRewrite>, verbatim: &[Span]) -> Vec<Span> {
+    let mut suppressed = Vec::new();
+    rws.retain(|rw| {
+        // An insertion touches a region only if it lies strictly inside it.
+        if verbatim.iter().any(|&region| overlaps(rw.old_span, region)) {
+            suppressed.push(rw.old_span);
+            false
+        } else {
+            true
+        }
+    });
+    suppressed
+}
+
+/// Merge adjacent rewrites into larger ones, reducing the number of separate edits without
+/// changing the resulting text.  Two rewrites are merged only if they replace contiguous old text
+/// with contiguous new text from the same file.
+///
+/// The rewrites are cleaned up with `cleanup_rewrites` first, so `rws` doesn't need to be sorted.
+pub fn minimize(rws: Vec<TextRewrite>, sess: &Session) -> Vec<TextRewrite> {
+    let cm = sess.source_map();
+    let rws = cleanup_rewrites(cm, rws);
+    minimize_sorted(cm, rws)
+}
+
+fn minimize_sorted(cm: &SourceMap, rws: Vec<TextRewrite>) -> Vec<TextRewrite> {
+    let mut new_rws: Vec<TextRewrite> = Vec::with_capacity(rws.len());
+
+    for mut rw in rws {
+        rw.rewrites = minimize_sorted(cm, rw.rewrites);
+
+        if let Some(prev) = new_rws.last_mut() {
+            if can_merge(cm, prev, &rw) {
+                prev.old_span = prev.old_span.with_hi(rw.old_span.hi());
+                prev.new_span = prev.new_span.with_hi(rw.new_span.hi());
+                prev.rewrites.extend(rw.rewrites);
+                prev.nodes.extend(rw.nodes);
+                continue;
+            }
+        }
+
+        new_rws.push(rw);
     }
 
     new_rws
 }
+
+/// A pair of overlapping rewrites found by `merge_rewrites`.  Each span is the `old_span` of one
+/// of the rewrites, with the earlier one first.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RewriteConflict {
+    pub first: Span,
+    pub second: Span,
+}
+
+/// Combine the rewrites produced by two separate passes over the same old AST into a single list,
+/// which can then be applied in one go.  Rewrites of disjoint regions are kept as they are, and a
+/// rewrite made by both passes is kept only once.  Fails with the first conflict if the two lists,
+/// or either one alone, change overlapping regions of the old text in different ways.
+pub fn merge_rewrites(
+    a: Vec<TextRewrite>,
+    b: Vec<TextRewrite>,
+    sess: &Session,
+) -> Result<Vec<TextRewrite>, RewriteConflict> {
+    let mut rws = a;
+    rws.extend(b);
+    let mut conflict = None;
+    let rws = cleanup_rewrites_with(sess.source_map(), rws, &mut |prev, cur| {
+        if conflict.is_none() {
+            conflict = Some(RewriteConflict {
+                first: prev.old_span,
+                second: cur.old_span,
+            });
+        }
+    });
+    match conflict {
+        Some(c) => Err(c),
+        None => Ok(rws),
+    }
+}
@@ -1,17 +1,294 @@
 //! Code for applying `TextRewrite`s to the actual source files.
 use diff;
+use std::cmp;
 use std::collections::{HashMap, VecDeque};
 use std::io;
-use syntax::source_map::{SourceFile, SourceMap};
-use syntax_pos::{BytePos, FileName};
+use std::ops;
+use std::fmt::Write;
+use std::path::Path;
+use std::slice;
+use rustc_data_structures::sync::Lrc;
+use syntax::ast::NodeId;
+use syntax::source_map::{SourceFile, SourceMap, Span};
+use syntax_pos::{BytePos, FileName, Pos, SyntaxContext};
 
 use crate::file_io::FileIO;
-use crate::rewrite::cleanup::cleanup_rewrites;
-use crate::rewrite::{TextAdjust, TextRewrite};
+use crate::driver;
+use crate::rewrite::cleanup::{cleanup_rewrites, cleanup_rewrites_with};
+use crate::rewrite::span_util::overlaps;
+use crate::rewrite::TextRewrite;
 
 /// Apply a sequence of rewrites to the source code, handling the results by passing the new text
 /// to `callback` along with the `SourceFile` describing the original source file.
 pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &FileIO) -> io::Result<()> {
+    rewrite_files_with_indent(cm, rw, io, Indent::default())
+}
+
+/// Like `rewrite_files_with`, but with a custom default indentation style.  Pretty-printed code
+/// is reindented to match the indentation style of the file it's inserted into, as detected by
+/// `Indent::detect`.  `default_indent` is used for files whose style can't be detected.
+pub fn rewrite_files_with_indent(
+    cm: &SourceMap,
+    rw: &TextRewrite,
+    io: &FileIO,
+    default_indent: Indent,
+) -> io::Result<()> {
+    let opts = ApplyOptions::default().default_indent(default_indent);
+    rewrite_files_with_options(cm, rw, io, &opts)
+}
+
+/// Like `rewrite_files_with`, but with non-default `ApplyOptions`.
+pub fn rewrite_files_with_options(
+    cm: &SourceMap,
+    rw: &TextRewrite,
+    io: &FileIO,
+    opts: &ApplyOptions,
+) -> io::Result<()> {
+    for (rewrites, nodes, sf) in rewrites_by_file(cm, rw) {
+        let path = match sf.name {
+            FileName::Real(ref path) => path,
+            _ => {
+                warn!("can't rewrite virtual file {:?}", sf.name);
+                continue;
+            }
+        };
+
+        // TODO: do something with nodes
+        io.save_rewrites(cm, &sf, &rewrites, &nodes)?;
+        let mut buf = String::new();
+        if has_bom(io, path) {
+            buf.push(BOM);
+        }
+        buf.push_str(&rewrite_file_text(cm, &sf, rewrites, opts));
+        io.write_file(path, &buf)?;
+    }
+
+    io.end_rewrite(cm)?;
+
+    Ok(())
+}
+
+/// Apply `rw` to the source code, and get the new text of each file it changes, without writing
+/// anything.  As with `rewrite_file_text`, the text doesn't include the byte order mark.  This
+/// takes `rw` by value so its rewrites can be moved into their files' groups instead of copied.
+pub fn apply_rewrites(
+    cm: &SourceMap,
+    rw: TextRewrite,
+    opts: &ApplyOptions,
+) -> HashMap<FileName, String> {
+    top_rewrites_by_file(cm, rw)
+        .into_iter()
+        .map(|(rewrites, sf)| (sf.name.clone(), rewrite_file_text(cm, &sf, rewrites, opts)))
+        .collect()
+}
+
+/// Like `apply_rewrites`, but also get the byte ranges of each file's new text that came from
+/// rewrites, as in `rewrite_file_text_ranges`.  Text outside the ranges is unchanged.
+pub fn apply_rewrites_with_ranges(
+    cm: &SourceMap,
+    rw: TextRewrite,
+    opts: &ApplyOptions,
+) -> HashMap<FileName, (String, Vec<ops::Range<usize>>)> {
+    top_rewrites_by_file(cm, rw)
+        .into_iter()
+        .map(|(rewrites, sf)| {
+            let result = rewrite_file_text_ranges(cm, &sf, rewrites, opts);
+            (sf.name.clone(), result)
+        })
+        .collect()
+}
+
+/// Where a region of the new text of a file comes from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Origin {
+    /// The text was copied verbatim from the original source at this span.  This covers both text
+    /// that no rewrite touched and text that a rewrite recycled from elsewhere in the source.
+    Recycled(Span),
+    /// The text was produced by the pretty-printer, or inserted as literal text, such as
+    /// separators and conflict markers.
+    Fresh,
+}
+
+/// Like `apply_rewrites`, but also map out where each file's new text comes from, as a list of
+/// byte ranges of the new text with their `Origin`s.  The ranges are sorted, cover the whole text
+/// without gaps, and adjacent ranges with continuing origins are merged.  With a `Whitespace`
+/// policy other than `PreserveExact`, the whitespace at each splice boundary counts as fresh,
+/// since it may have been normalized.
+pub fn apply_rewrites_with_origins(
+    cm: &SourceMap,
+    rw: TextRewrite,
+    opts: &ApplyOptions,
+) -> HashMap<FileName, (String, Vec<(ops::Range<usize>, Origin)>)> {
+    top_rewrites_by_file(cm, rw)
+        .into_iter()
+        .map(|(rewrites, sf)| {
+            let result = rewrite_file_origins(cm, &sf, rewrites, opts);
+            (sf.name.clone(), result)
+        })
+        .collect()
+}
+
+/// Split the top-level rewrites of `rw` by the source file they apply to, moving them out of
+/// `rw`.  Unlike `rewrites_by_file`, this ignores node spans.
+fn top_rewrites_by_file(
+    cm: &SourceMap,
+    rw: TextRewrite,
+) -> Vec<(Vec<TextRewrite>, Lrc<SourceFile>)> {
+    let mut by_file = HashMap::new();
+    for rw in rw.rewrites {
+        let sf = cm.lookup_byte_offset(rw.old_span.lo()).sf;
+        let ptr = (&sf as &SourceFile) as *const _;
+        by_file
+            .entry(ptr)
+            .or_insert_with(|| (Vec::new(), sf))
+            .0
+            .push(rw);
+    }
+    by_file.into_iter().map(|(_, x)| x).collect()
+}
+
+/// Options controlling how rewrites are applied to the text of each file.  Use
+/// `ApplyOptions::default()` for the standard behavior, and the builder-style setters to change
+/// individual options.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ApplyOptions {
+    /// The indentation style for pretty-printed code in files whose own style can't be detected.
+    /// Defaults to `Indent::PRINTED`.
+    pub default_indent: Indent,
+    /// How to treat whitespace where rewritten text meets the surrounding text.  Defaults to
+    /// `Whitespace::PreserveExact`.
+    pub whitespace: Whitespace,
+    /// Whether to mark conflicting rewrites in the output instead of panicking.  When this is set,
+    /// each region where rewrites overlap in a way that can't be resolved is replaced with its old
+    /// text and its proposed new text, between `<<<<<<<`, `=======`, and `>>>>>>>` lines, as in a
+    /// merge conflict.  Defaults to `false`.
+    pub conflict_markers: bool,
+}
+
+impl ApplyOptions {
+    pub fn default_indent(mut self, indent: Indent) -> Self {
+        self.default_indent = indent;
+        self
+    }
+
+    pub fn whitespace(mut self, whitespace: Whitespace) -> Self {
+        self.whitespace = whitespace;
+        self
+    }
+
+    pub fn conflict_markers(mut self, conflict_markers: bool) -> Self {
+        self.conflict_markers = conflict_markers;
+        self
+    }
+}
+
+/// A policy for the whitespace at splice boundaries, where the text of a rewrite meets the
+/// surrounding text.  Recycled spans often carry part of their original surroundings, so splicing
+/// them into a new place can leave doubled blank lines, or run two tokens together.  Only the
+/// run of whitespace touching each boundary is affected.  Whitespace elsewhere is always kept
+/// exactly.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Whitespace {
+    /// Keep the whitespace exactly as it comes out of the rewrites.
+    PreserveExact,
+    /// Reduce a run of several blank lines at a boundary to a single blank line.
+    CollapseBlankRuns,
+    /// Reduce the whitespace at a boundary to a single separator: one line break (keeping the
+    /// indentation of the following line) if it contains any line breaks, and otherwise one
+    /// space.  Tokens that were run together, as in `x;let`, are separated by a space.
+    EnsureSingleSeparator,
+}
+
+impl Default for Whitespace {
+    fn default() -> Whitespace {
+        Whitespace::PreserveExact
+    }
+}
+
+impl Whitespace {
+    /// Normalize the whitespace runs of `text` that touch any of the byte offsets in
+    /// `boundaries`, which must be sorted.  Each boundary is updated to the corresponding offset
+    /// in the result.  A boundary inside a run that shrinks is clamped to the end of the new run.
+    fn apply(self, text: &str, boundaries: &mut [usize]) -> String {
+        if self == Whitespace::PreserveExact {
+            return text.to_owned();
+        }
+
+        let is_space = |c: char| c == ' ' || c == '\t' || c == '\n' || c == '\r';
+        let mut out = String::with_capacity(text.len());
+        let mut cur = 0;
+        // The start of the last normalized run in `text`, and its start and end in `out`.
+        let mut last_run = (0, 0, 0);
+        for b in boundaries.iter_mut() {
+            let start = text[..*b].trim_end_matches(is_space).len();
+            let end = text.len() - text[*b..].trim_start_matches(is_space).len();
+            if *b >= cur && start >= cur {
+                out.push_str(&text[cur..start]);
+                let out_start = out.len();
+                out.push_str(&self.normalize_run(text, start, end));
+                last_run = (start, out_start, out.len());
+                cur = end;
+            }
+            // Otherwise, the boundary was already handled as part of the previous run.
+            let (run_start, out_start, out_end) = last_run;
+            *b = out_start + cmp::min(*b - run_start, out_end - out_start);
+        }
+        out.push_str(&text[cur..]);
+        out
+    }
+
+    /// Normalize the whitespace run `text[start..end]`.
+    fn normalize_run(self, text: &str, start: usize, end: usize) -> String {
+        let run = &text[start..end];
+        let newlines = run.matches('\n').count();
+        // The indentation of the line following the run.
+        let indent = match run.rfind('\n') {
+            Some(i) => &run[i + 1..],
+            None => "",
+        };
+
+        if self != Whitespace::PreserveExact {
+            // At the start and end of the file, no separator is needed, other than the final
+            // line break.
+            if start == 0 {
+                return String::new();
+            }
+            if end == text.len() {
+                return if newlines > 0 { "\n".to_owned() } else { String::new() };
+            }
+        }
+
+        match self {
+            Whitespace::PreserveExact => run.to_owned(),
+            Whitespace::CollapseBlankRuns if newlines > 2 => format!("\n\n{}", indent),
+            Whitespace::CollapseBlankRuns => run.to_owned(),
+            Whitespace::EnsureSingleSeparator if newlines > 0 => format!("\n{}", indent),
+            Whitespace::EnsureSingleSeparator if !run.is_empty() => " ".to_owned(),
+            Whitespace::EnsureSingleSeparator => {
+                let before = text[..start].chars().next_back();
+                let after = text[end..].chars().next();
+                match (before, after) {
+                    (Some(b), Some(a)) if needs_separator(b, a) => " ".to_owned(),
+                    _ => String::new(),
+                }
+            }
+        }
+    }
+}
+
+/// Check whether the characters `before` and `after` must be separated when they meet at a splice
+/// boundary, either to keep them from lexing as one token or because `before` ends a statement or
+/// list element.
+fn needs_separator(before: char, after: char) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    is_word(after) && (is_word(before) || before == ';' || before == ',' || before == '}')
+}
+
+/// Split the top-level rewrites and node spans of `rw` by the source file they apply to.
+pub(super) fn rewrites_by_file(
+    cm: &SourceMap,
+    rw: &TextRewrite,
+) -> Vec<(Vec<TextRewrite>, Vec<(Span, NodeId)>, Lrc<SourceFile>)> {
     let mut by_file = HashMap::new();
 
     for rw in &rw.rewrites {
@@ -34,28 +311,657 @@ pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &FileIO) -> io::
             .push((span, id));
     }
 
-    for (_, (rewrites, nodes, sf)) in by_file {
-        let path = match sf.name {
-            FileName::Real(ref path) => path,
-            _ => {
-                warn!("can't rewrite virtual file {:?}", sf.name);
-                continue;
+    by_file.into_iter().map(|(_, x)| x).collect()
+}
+
+/// Compute the new text of `sf` after applying `rewrites`, which must all lie within `sf`.  The
+/// result doesn't include the byte order mark, if the file has one.  Pretty-printed code is
+/// reindented in the file's indentation style, or in `opts.default_indent` if the file has no
+/// consistent style, and the whitespace at the boundaries of the rewrites is treated according to
+/// `opts.whitespace`.  Conflicting rewrites cause a panic, unless `opts.conflict_markers` is set.
+pub(super) fn rewrite_file_text(
+    cm: &SourceMap,
+    sf: &SourceFile,
+    rewrites: Vec<TextRewrite>,
+    opts: &ApplyOptions,
+) -> String {
+    apply_file_rewrites(cm, sf, rewrites, opts, None).0
+}
+
+/// Like `rewrite_file_text`, but also get the byte range of the result that each rewrite
+/// produced, in order.  Rewrites that were merged or dropped by `cleanup_rewrites` are counted
+/// after cleanup, so there may be fewer ranges than rewrites.
+pub(super) fn rewrite_file_text_ranges(
+    cm: &SourceMap,
+    sf: &SourceFile,
+    rewrites: Vec<TextRewrite>,
+    opts: &ApplyOptions,
+) -> (String, Vec<ops::Range<usize>>) {
+    let (text, changes) = apply_file_rewrites(cm, sf, rewrites, opts, None);
+    (text, changes.into_iter().map(|(_, range)| range).collect())
+}
+
+/// Like `rewrite_file_text_ranges`, but pair each range with the rewrite that produced it, as it
+/// was applied after cleanup.
+#[cfg(feature = "serde")]
+pub(super) fn rewrite_file_changes(
+    cm: &SourceMap,
+    sf: &SourceFile,
+    rewrites: Vec<TextRewrite>,
+    opts: &ApplyOptions,
+) -> (String, Vec<(TextRewrite, ops::Range<usize>)>) {
+    apply_file_rewrites(cm, sf, rewrites, opts, None)
+}
+
+/// Like `rewrite_file_text`, but also get the origin of each region of the result, as in
+/// `apply_rewrites_with_origins`.
+fn rewrite_file_origins(
+    cm: &SourceMap,
+    sf: &SourceFile,
+    rewrites: Vec<TextRewrite>,
+    opts: &ApplyOptions,
+) -> (String, Vec<(ops::Range<usize>, Origin)>) {
+    let mut origins = Vec::new();
+    let (text, _) = apply_file_rewrites(cm, sf, rewrites, opts, Some(&mut origins));
+    (text, origins)
+}
+
+fn apply_file_rewrites(
+    cm: &SourceMap,
+    sf: &SourceFile,
+    rewrites: Vec<TextRewrite>,
+    opts: &ApplyOptions,
+    mut origins: Option<&mut Vec<(ops::Range<usize>, Origin)>>,
+) -> (String, Vec<(TextRewrite, ops::Range<usize>)>) {
+    let mut buf = String::new();
+    let rewrites = if opts.conflict_markers {
+        // The `new_span`s of rewrites that mark conflicts, along with the rewrites proposed for
+        // each conflicting region.
+        let mut conflicts = HashMap::new();
+        cleanup_rewrites_with(cm, rewrites, &mut |prev, cur| {
+            let mut proposed = conflicts
+                .remove(&prev.new_span)
+                .unwrap_or_else(|| vec![prev.clone()]);
+            let region = prev.old_span.with_hi(cmp::max(prev.old_span.hi(), cur.old_span.hi()));
+            // Rewrites that don't conflict with the ones before them are still proposed.
+            if proposed
+                .last()
+                .map_or(true, |rw: &TextRewrite| !overlaps(rw.old_span, cur.old_span))
+            {
+                proposed.push(cur.clone());
             }
+            *prev = conflict_rewrite(cm, region, &proposed);
+            conflicts.insert(prev.new_span, proposed);
+        })
+    } else {
+        cleanup_rewrites(cm, rewrites)
+    };
+    let rewrites = protect_prologue(rewrites, prologue_end(sf));
+    let indent = file_indent(sf, opts.default_indent);
+    // Apply the rewrites one at a time, to find the offsets of the boundaries in the new text.
+    // The origins of the text, if wanted, are collected along the way.
+    let mut regions = Vec::new();
+    let want_origins = origins.is_some();
+    let mut push = |buf: &mut String, s: &str, origin: Origin| {
+        let start = buf.len();
+        buf.push_str(s);
+        if want_origins {
+            push_region(&mut regions, start..buf.len(), origin);
+        }
+    };
+    let mut boundaries = Vec::with_capacity(rewrites.len() * 2);
+    let mut cur = sf.start_pos;
+    for rw in &rewrites {
+        emit_chunk_origin(cm, cur, rw.old_span.lo(), indent, |s, o| push(&mut buf, s, o));
+        boundaries.push(buf.len());
+        rewrite_range_origins(
+            cm,
+            rw.old_span.lo(),
+            rw.old_span.hi(),
+            slice::from_ref(rw),
+            indent,
+            &mut |s, o| push(&mut buf, s, o),
+        );
+        boundaries.push(buf.len());
+        cur = rw.old_span.hi();
+    }
+    emit_chunk_origin(cm, cur, sf.end_pos, indent, |s, o| push(&mut buf, s, o));
+    let raw_boundaries = boundaries.clone();
+    let mut text = opts.whitespace.apply(&buf, &mut boundaries);
+    if let Some(ref mut origins) = origins {
+        **origins = if opts.whitespace == Whitespace::PreserveExact {
+            regions
+        } else {
+            remap_regions(&buf, &text, &raw_boundaries, &boundaries, regions)
         };
+    }
+    preserve_final_newline(sf, &rewrites, &boundaries, &mut text);
+    let len = text.len();
+    if let Some(origins) = origins {
+        clamp_regions(origins, len);
+    }
+    let changes = rewrites
+        .into_iter()
+        .zip(boundaries.chunks(2))
+        .map(|(rw, b)| (rw, cmp::min(b[0], len)..cmp::min(b[1], len)))
+        .collect();
+    (text, changes)
+}
 
-        // TODO: do something with nodes
-        io.save_rewrites(cm, &sf, &rewrites, &nodes)?;
-        let mut buf = String::new();
-        let rewrites = cleanup_rewrites(cm, rewrites);
-        rewrite_range(cm, sf.start_pos, sf.end_pos, &rewrites, &mut |s| {
-            buf.push_str(s)
-        });
-        io.write_file(path, &buf)?;
+/// Make `text`, the new text of `sf`, end with the same line breaks as the old text: none if the
+/// old text had no final line break, and otherwise the same number, in the same style (`\n` or
+/// `\r\n`).  Depending on where the span of the last item in the file ends, rewriting or deleting
+/// the item can add or drop line breaks at the end of the file, which shows up as a spurious
+/// change to the last line.  A file that the rewrites make empty stays empty.
+///
+/// `rewrites` are the rewrites applied to produce `text`, and `boundaries` the start and end of
+/// each one's new text, as in `apply_file_rewrites`.  The line breaks are left alone when a rewrite
+/// deliberately changes the end of the file: when the new text of some rewrite reaches the end,
+/// and the old text replaced by the last rewrite doesn't end right before the old line breaks.
+fn preserve_final_newline(
+    sf: &SourceFile,
+    rewrites: &[TextRewrite],
+    boundaries: &[usize],
+    text: &mut String,
+) {
+    let src = match sf.src {
+        Some(ref src) => src,
+        None => return,
+    };
+    let is_break = |c| c == '\n' || c == '\r';
+    let old_len = src.trim_end_matches(is_break).len();
+    let old_breaks = &src[old_len..];
+    let len = text.trim_end_matches(is_break).len();
+    if len == 0 || text[len..] == *old_breaks {
+        return;
     }
 
-    io.end_rewrite(cm)?;
+    let reaches_end = boundaries.chunks(2).any(|b| b[0] < b[1] && b[1] >= len);
+    let ends_at_breaks = rewrites
+        .last()
+        .map_or(true, |rw| (rw.old_span.hi() - sf.start_pos).to_usize() == old_len);
+    if reaches_end && !ends_at_breaks {
+        return;
+    }
 
-    Ok(())
+    text.truncate(len);
+    text.push_str(old_breaks);
+}
+
+/// Add the region `range` of the new text, with origin `origin`, to the end of `regions`, merging
+/// it into the last region if they continue each other.  Empty regions are skipped.
+fn push_region(
+    regions: &mut Vec<(ops::Range<usize>, Origin)>,
+    range: ops::Range<usize>,
+    origin: Origin,
+) {
+    if range.start == range.end {
+        return;
+    }
+    if let Some(&mut (ref mut last, ref mut last_origin)) = regions.last_mut() {
+        if last.end == range.start {
+            match (*last_origin, origin) {
+                (Origin::Fresh, Origin::Fresh) => {
+                    last.end = range.end;
+                    return;
+                }
+                (Origin::Recycled(sp1), Origin::Recycled(sp2)) if sp1.hi() == sp2.lo() => {
+                    last.end = range.end;
+                    *last_origin = Origin::Recycled(sp1.with_hi(sp2.hi()));
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+    regions.push((range, origin));
+}
+
+/// Map the `regions` of `raw`, the text before whitespace normalization, to `text`, the result of
+/// `Whitespace::apply`.  `raw_boundaries` and `boundaries` are the splice boundaries before and
+/// after normalization.  Text away from the boundaries is unchanged and keeps its origin, while
+/// the whitespace touching a boundary may have been changed, so it counts as fresh.
+fn remap_regions(
+    raw: &str,
+    text: &str,
+    raw_boundaries: &[usize],
+    boundaries: &[usize],
+    regions: Vec<(ops::Range<usize>, Origin)>,
+) -> Vec<(ops::Range<usize>, Origin)> {
+    let is_space = |c: char| c == ' ' || c == '\t' || c == '\n' || c == '\r';
+    // The ends of the stretches between boundaries, in both texts.
+    let with_ends = |bounds: &[usize], len: usize| {
+        let mut points = vec![0];
+        points.extend_from_slice(bounds);
+        points.push(len);
+        points
+    };
+    let raw_points = with_ends(raw_boundaries, raw.len());
+    let points = with_ends(boundaries, text.len());
+
+    let mut result = Vec::new();
+    // The first region that might overlap the current stretch.
+    let mut first = 0;
+    for k in 0..raw_points.len() - 1 {
+        let (r0, r1) = (raw_points[k], raw_points[k + 1]);
+        let (o0, o1) = (points[k], points[k + 1]);
+        if r0 >= r1 {
+            push_region(&mut result, o0..o1, Origin::Fresh);
+            continue;
+        }
+        // Only the whitespace at ends that are boundaries was normalized.
+        let trim_start = k > 0;
+        let trim_end = k + 1 < raw_points.len() - 1;
+        let trimmed = |s: &str| {
+            let s1 = if trim_start { s.trim_start_matches(is_space) } else { s };
+            let lead = s.len() - s1.len();
+            let s2 = if trim_end { s1.trim_end_matches(is_space) } else { s1 };
+            (lead, s2.len())
+        };
+        let (raw_lead, raw_mid) = trimmed(&raw[r0..r1]);
+        let (lead, mid) = trimmed(&text[o0..cmp::max(o0, o1)]);
+        if raw_mid == 0 || raw_mid != mid {
+            push_region(&mut result, o0..o1, Origin::Fresh);
+            continue;
+        }
+
+        let (m0, m1) = (r0 + raw_lead, r0 + raw_lead + raw_mid);
+        let shift = |pos: usize| o0 + lead + (pos - m0);
+        push_region(&mut result, o0..o0 + lead, Origin::Fresh);
+        while first < regions.len() && regions[first].0.end <= m0 {
+            first += 1;
+        }
+        for &(ref range, origin) in regions[first..].iter().take_while(|r| r.0.start < m1) {
+            let lo = cmp::max(range.start, m0);
+            let hi = cmp::min(range.end, m1);
+            let origin = match origin {
+                Origin::Recycled(sp) => {
+                    let sp_lo = sp.lo() + BytePos::from_usize(lo - range.start);
+                    let sp_hi = sp_lo + BytePos::from_usize(hi - lo);
+                    Origin::Recycled(sp.with_hi(sp_hi).with_lo(sp_lo))
+                }
+                Origin::Fresh => Origin::Fresh,
+            };
+            push_region(&mut result, shift(lo)..shift(hi), origin);
+        }
+        push_region(&mut result, o0 + lead + mid..o1, Origin::Fresh);
+    }
+    result
+}
+
+/// Fix up `regions` after `preserve_final_newline` changed the length of the text to `len`:
+/// regions past the end are cut off, and added line breaks count as fresh.
+fn clamp_regions(regions: &mut Vec<(ops::Range<usize>, Origin)>, len: usize) {
+    regions.retain(|&(ref range, _)| range.start < len);
+    if let Some(&mut (ref mut last, ref mut origin)) = regions.last_mut() {
+        if last.end > len {
+            if let Origin::Recycled(ref mut sp) = *origin {
+                *sp = sp.with_hi(sp.hi() - BytePos::from_usize(last.end - len));
+            }
+            last.end = len;
+        }
+    }
+    let end = regions.last().map_or(0, |r| r.0.end);
+    push_region(regions, end..len, Origin::Fresh);
+}
+
+const CONFLICT_START: &str = "<<<<<<< original\n";
+const CONFLICT_SEP: &str = "=======\n";
+const CONFLICT_END: &str = ">>>>>>> rewritten";
+
+/// Build a rewrite that marks a conflict between rewrites in `region`.  The rewrite replaces the
+/// region with its old text and the text proposed by the rewrites in `proposed`, between conflict
+/// markers.  `proposed` must be a sorted list of non-overlapping rewrites within `region`.  The
+/// markers always appear on lines of their own, so line breaks are added around them where
+/// needed.
+fn conflict_rewrite(cm: &SourceMap, region: Span, proposed: &[TextRewrite]) -> TextRewrite {
+    warn!("marking conflicting rewrites in {:?}", region);
+    let mut old_text = String::new();
+    emit_chunk(cm, region.lo(), region.hi(), |s| old_text.push_str(s));
+    let mut new_text = String::new();
+    rewrite_range(cm, region.lo(), region.hi(), proposed, &mut |s| new_text.push_str(s));
+
+    let loc = cm.lookup_byte_offset(region.lo());
+    let (at_line_start, at_line_end) = match loc.sf.src {
+        Some(ref src) => {
+            let lo = loc.pos.to_usize();
+            let hi = lo + old_text.len();
+            (
+                lo == 0 || src[..lo].ends_with('\n'),
+                hi == src.len() || src[hi..].starts_with(|c| c == '\n' || c == '\r'),
+            )
+        }
+        None => (false, false),
+    };
+    let line_break = |text: &str| if text.is_empty() || text.ends_with('\n') { "" } else { "\n" };
+
+    // The marker text has empty slots where the old and new text of the region go.  Those are
+    // filled in by nested rewrites, so they keep the indentation handling of ordinary rewrites.
+    let mut markers = String::new();
+    if !at_line_start {
+        markers.push('\n');
+    }
+    markers.push_str(CONFLICT_START);
+    let old_slot = markers.len();
+    markers.push_str(line_break(&old_text));
+    markers.push_str(CONFLICT_SEP);
+    let new_slot = markers.len();
+    markers.push_str(line_break(&new_text));
+    markers.push_str(CONFLICT_END);
+    if !at_line_end {
+        markers.push('\n');
+    }
+
+    let markers_span = driver::make_span_for_text(cm, &markers);
+    let slot = |offset: usize| {
+        let pos = markers_span.lo() + BytePos::from_usize(offset);
+        markers_span.with_lo(pos).with_hi(pos)
+    };
+    let mut new_rw = TextRewrite::new(slot(new_slot), region);
+    new_rw.rewrites = proposed.to_owned();
+
+    let mut rw = TextRewrite::new(region, markers_span);
+    rw.rewrites.push(TextRewrite::new(slot(old_slot), region));
+    rw.rewrites.push(new_rw);
+    rw
+}
+
+/// Compute the edits that `rewrites` make to `sf`, as pairs of an old span and its replacement
+/// text.  The edits are sorted and don't overlap, and applying them to the old text gives the
+/// same result as `rewrite_file_text`.
+pub(super) fn rewrite_file_edits(
+    cm: &SourceMap,
+    sf: &SourceFile,
+    rewrites: Vec<TextRewrite>,
+    default_indent: Indent,
+) -> Vec<(Span, String)> {
+    let rewrites = cleanup_rewrites(cm, rewrites);
+    let rewrites = protect_prologue(rewrites, prologue_end(sf));
+    let indent = file_indent(sf, default_indent);
+    rewrites
+        .iter()
+        .map(|rw| {
+            let mut buf = String::new();
+            rewrite_range_indent(
+                cm,
+                rw.old_span.lo(),
+                rw.old_span.hi(),
+                slice::from_ref(rw),
+                indent,
+                &mut |s| buf.push_str(s),
+            );
+            (rw.old_span, buf)
+        })
+        .collect()
+}
+
+/// Get the indentation style to use for pretty-printed code inserted into `sf`.
+fn file_indent(sf: &SourceFile, default_indent: Indent) -> Indent {
+    sf.src
+        .as_ref()
+        .and_then(|src| Indent::detect(src))
+        .unwrap_or(default_indent)
+}
+
+/// The width of one level of indentation in pretty-printer output.
+const PRINTED_WIDTH: usize = 4;
+
+/// A unit of indentation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+impl Default for Indent {
+    fn default() -> Indent {
+        Indent::PRINTED
+    }
+}
+
+impl Indent {
+    /// The indentation used by the pretty-printer.
+    pub const PRINTED: Indent = Indent::Spaces(PRINTED_WIDTH);
+
+    /// Detect the indentation style of `src`.  The unit is the most common amount by which a line
+    /// is indented further than the previous non-blank line.  Returns `None` if `src` has no
+    /// indented lines, or if it mixes lines indented with tabs and lines indented with spaces.
+    pub fn detect(src: &str) -> Option<Indent> {
+        let mut tabs = 0;
+        let mut space_steps = HashMap::new();
+        let mut prev_width = 0;
+
+        for line in src.lines() {
+            let trimmed = line.trim_start();
+            // Skip blank lines, and the continuation lines of block comments, which are usually
+            // aligned rather than indented.
+            if trimmed.is_empty() || trimmed.starts_with('*') {
+                continue;
+            }
+            let ws = &line[..line.len() - trimmed.len()];
+            if ws.starts_with('\t') {
+                tabs += 1;
+            } else if ws.len() > prev_width {
+                *space_steps.entry(ws.len() - prev_width).or_insert(0) += 1;
+            }
+            if !ws.contains('\t') {
+                prev_width = ws.len();
+            }
+        }
+
+        match (tabs, space_steps.is_empty()) {
+            (0, true) => None,
+            (_, true) => Some(Indent::Tabs),
+            (0, false) => space_steps
+                .into_iter()
+                .max_by_key(|&(step, count)| (count, cmp::Reverse(step)))
+                .map(|(step, _)| Indent::Spaces(step)),
+            (_, false) => None,
+        }
+    }
+
+    fn push_units(self, n: usize, buf: &mut String) {
+        for _ in 0..n {
+            match self {
+                Indent::Tabs => buf.push('\t'),
+                Indent::Spaces(w) => buf.extend((0..w).map(|_| ' ')),
+            }
+        }
+    }
+
+    /// Convert the indentation of each line of `s` after the first from `Indent::PRINTED` to
+    /// `self`.  Leftover spaces that don't make up a whole unit are kept as they are.
+    fn reindent(self, s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+        let mut lines = s.split('\n');
+        out.push_str(lines.next().unwrap_or(""));
+        for line in lines {
+            out.push('\n');
+            let trimmed = line.trim_start_matches(' ');
+            let width = line.len() - trimmed.len();
+            self.push_units(width / PRINTED_WIDTH, &mut out);
+            out.extend((0..width % PRINTED_WIDTH).map(|_| ' '));
+            out.push_str(trimmed);
+        }
+        out
+    }
+}
+
+/// Widen `span`, a region about to be deleted, so the deletion leaves tidy text behind:
+///
+///  * A `,` or `;` right after the region is deleted with it.  If there is none, a `,` right
+///    before the region is deleted instead, so removing the last argument of `f(a, b)` leaves
+///    `f(a)`.
+///  * If nothing else remains on the region's lines, the lines are deleted entirely, including
+///    their indentation and line break.
+///  * Otherwise, the spaces on one side of the region are deleted, so no doubled or trailing
+///    space remains.
+///
+/// `span` is returned unchanged if its source text is unavailable.
+pub(super) fn extend_deletion(cm: &SourceMap, span: Span) -> Span {
+    let lo = cm.lookup_byte_offset(span.lo());
+    let src = match lo.sf.src {
+        Some(ref src) => src,
+        None => return span,
+    };
+    let start_pos = lo.sf.start_pos;
+    let is_space = |c| c == ' ' || c == '\t';
+
+    let mut start = lo.pos.to_usize();
+    let mut end = (span.hi() - start_pos).to_usize();
+    if end > src.len() {
+        return span;
+    }
+
+    // Take the separator after the region, or failing that, the one before it.
+    let after = end + (src[end..].len() - src[end..].trim_start_matches(is_space).len());
+    if src[after..].starts_with(',') || src[after..].starts_with(';') {
+        end = after + 1;
+    } else {
+        let before = src[..start].trim_end().len();
+        if src[..before].ends_with(',') {
+            start = before - 1;
+        }
+    }
+
+    let line_start = src[..start].trim_end_matches(is_space).len();
+    let line_end = end + (src[end..].len() - src[end..].trim_start_matches(is_space).len());
+    let at_line_start = line_start == 0 || src[..line_start].ends_with('\n');
+    let at_line_end = line_end == src.len() || src[line_end..].starts_with('\n')
+        || src[line_end..].starts_with("\r\n");
+
+    if at_line_start && at_line_end {
+        start = line_start;
+        end = line_end;
+        if src[end..].starts_with("\r\n") {
+            end += 2;
+        } else if src[end..].starts_with('\n') {
+            end += 1;
+        }
+    } else if at_line_end {
+        start = line_start;
+    } else {
+        end = line_end;
+    }
+
+    span.with_lo(start_pos + BytePos::from_usize(start))
+        .with_hi(start_pos + BytePos::from_usize(end))
+}
+
+/// Work out the text to add around `text`, which is about to be inserted at `at`, so that it sits
+/// in the surrounding list or block like its neighbors.  Returns the text to add before and after
+/// it:
+///
+///  * Right after a `,` or `;`, the new text gets a separator of its own after it.  A `;` isn't
+///    added to text that already ends in `;` or `}`.  Right before a `,`, the new text gets one
+///    before it instead, so inserting just after `a` in `f(a, b)` gives `f(a, NEW, b)`.  Right
+///    after the `(` or `[` of a non-empty list, it gets a `,` after it.
+///  * At the end of a line after other text, the new text goes on a line of its own after it,
+///    indented like that line.  At the start of a line, after the indentation, it goes on a line
+///    of its own before the rest of the line.
+///  * Otherwise, a space separates the new text from the text on either side, except next to
+///    brackets and separators.
+///
+/// Both are empty if the source text around `at` is unavailable.
+pub(super) fn insertion_padding(cm: &SourceMap, at: BytePos, text: &str) -> (String, String) {
+    let loc = cm.lookup_byte_offset(at);
+    let src = match loc.sf.src {
+        Some(ref src) => src,
+        None => return (String::new(), String::new()),
+    };
+    let is_space = |c| c == ' ' || c == '\t';
+    let pos = loc.pos.to_usize();
+    let (before, after) = src.split_at(pos);
+
+    let mut sep_before = "";
+    let mut sep_after = "";
+    match (before.trim_end().chars().last(), after.trim_start().chars().next()) {
+        (Some(','), _) => sep_after = ",",
+        (Some(';'), _) => {
+            let text = text.trim_end();
+            if !text.ends_with(';') && !text.ends_with('}') {
+                sep_after = ";";
+            }
+        }
+        (_, Some(',')) => sep_before = ",",
+        (Some('('), Some(c)) | (Some('['), Some(c)) if !")]".contains(c) => sep_after = ",",
+        _ => {}
+    }
+
+    let line = &before[before.rfind('\n').map_or(0, |i| i + 1)..];
+    let indent = &line[..line.len() - line.trim_start_matches(is_space).len()];
+    let rest = &after[..after.find('\n').unwrap_or(after.len())];
+    let at_line_start = line.trim().is_empty();
+    let at_line_end = rest.trim().is_empty();
+
+    if at_line_end && !at_line_start {
+        (format!("{}\n{}", sep_before, indent), sep_after.to_owned())
+    } else if at_line_start && !at_line_end {
+        (sep_before.to_owned(), format!("{}\n{}", sep_after, indent))
+    } else if at_line_start && at_line_end {
+        (sep_before.to_owned(), sep_after.to_owned())
+    } else {
+        let space_before = match before.chars().last() {
+            Some(c) => !c.is_whitespace() && !"([<".contains(c),
+            None => false,
+        };
+        let space_after = match after.chars().next() {
+            Some(c) => !c.is_whitespace() && !")]>,;".contains(c),
+            None => false,
+        };
+        (
+            format!(
+                "{}{}",
+                sep_before,
+                if space_before || !sep_before.is_empty() { " " } else { "" }
+            ),
+            format!("{}{}", sep_after, if space_after { " " } else { "" }),
+        )
+    }
+}
+
+/// The UTF-8 byte order mark.
+const BOM: char = '\u{feff}';
+
+/// Check whether the file at `path` starts with a byte order mark.  `SourceFile` strips the BOM
+/// from its copy of the text, so we have to look at the file itself.  Files that can't be read
+/// (such as ones that are about to be created) have no BOM.
+fn has_bom(io: &FileIO, path: &Path) -> bool {
+    io.read_file(path)
+        .map(|s| s.starts_with(BOM))
+        .unwrap_or(false)
+}
+
+/// Find the end of the prologue of `sf`: the `#!` line at the start of the file, if there is one,
+/// including its newline.  The lexer skips this line, so no node's span covers it, and printing
+/// nodes never reproduces it.
+fn prologue_end(sf: &SourceFile) -> BytePos {
+    let src = match sf.src {
+        Some(ref src) => src,
+        None => return sf.start_pos,
+    };
+    // `#![...]` is an inner attribute, not a shebang.
+    if !src.starts_with("#!") || src[2..].trim_start().starts_with('[') {
+        return sf.start_pos;
+    }
+    let len = src.find('\n').map_or(src.len(), |i| i + 1);
+    sf.start_pos + BytePos::from_usize(len)
+}
+
+/// Move any rewrites that start inside the prologue (see `prologue_end`) so they start at `end`
+/// instead.  A rewrite lying entirely inside the prologue becomes an insertion at `end`.  `rws`
+/// must be sorted, and the result remains sorted.
+fn protect_prologue(rws: Vec<TextRewrite>, end: BytePos) -> Vec<TextRewrite> {
+    rws.into_iter()
+        .map(|mut rw| {
+            if rw.old_span.lo() < end {
+                warn!("moving rewrite of {:?} out of the file prologue", rw.old_span);
+                let hi = cmp::max(rw.old_span.hi(), end);
+                rw.old_span = rw.old_span.with_lo(end).with_hi(hi);
+            }
+            rw
+        })
+        .collect()
 }
 
 #[allow(dead_code)] // Helper function for debugging
@@ -87,50 +993,100 @@ fn print_rewrites(rws: &[TextRewrite]) {
 ///
 /// All rewrites must be in order, and must lie between `start` and `end`.  Otherwise a panic may
 /// occur.
-fn rewrite_range(
+pub(super) fn rewrite_range(
+    cm: &SourceMap,
+    start: BytePos,
+    end: BytePos,
+    rewrites: &[TextRewrite],
+    callback: &mut FnMut(&str),
+) {
+    rewrite_range_indent(cm, start, end, rewrites, Indent::PRINTED, callback)
+}
+
+/// Like `rewrite_range`, but reindents text that doesn't come from a real file (that is,
+/// pretty-printed text) from `Indent::PRINTED` to `indent`.
+fn rewrite_range_indent(
     cm: &SourceMap,
     start: BytePos,
     end: BytePos,
     rewrites: &[TextRewrite],
+    indent: Indent,
     callback: &mut FnMut(&str),
+) {
+    rewrite_range_origins(cm, start, end, rewrites, indent, &mut |s, _| callback(s))
+}
+
+/// Like `rewrite_range_indent`, but also pass `callback` the origin of each block of text.
+fn rewrite_range_origins(
+    cm: &SourceMap,
+    start: BytePos,
+    end: BytePos,
+    rewrites: &[TextRewrite],
+    indent: Indent,
+    callback: &mut FnMut(&str, Origin),
 ) {
     let mut cur = start;
 
     for rw in rewrites {
         if rw.old_span.lo() != cur {
-            emit_chunk(cm, cur, rw.old_span.lo(), |s| callback(s));
+            emit_chunk_origin(cm, cur, rw.old_span.lo(), indent, |s, o| callback(s, o));
         }
 
-        match rw.adjust {
-            TextAdjust::None => {}
-            TextAdjust::Parenthesize => callback("("),
+        if !rw.adjust.is_none() {
+            callback(&rw.adjust.prefix(), Origin::Fresh);
         }
 
         if rw.rewrites.len() == 0 {
-            emit_chunk(cm, rw.new_span.lo(), rw.new_span.hi(), |s| callback(s));
+            emit_chunk_origin(cm, rw.new_span.lo(), rw.new_span.hi(), indent, |s, o| {
+                callback(s, o)
+            });
         } else {
-            rewrite_range(
+            rewrite_range_origins(
                 cm,
                 rw.new_span.lo(),
                 rw.new_span.hi(),
                 &rw.rewrites,
+                indent,
                 callback,
             );
         }
 
-        match rw.adjust {
-            TextAdjust::None => {}
-            TextAdjust::Parenthesize => callback(")"),
+        if !rw.adjust.is_none() {
+            callback(&rw.adjust.suffix(), Origin::Fresh);
         }
 
         cur = rw.old_span.hi();
     }
 
     if cur != end {
-        emit_chunk(cm, cur, end, |s| callback(s));
+        emit_chunk_origin(cm, cur, end, indent, |s, o| callback(s, o));
     }
 }
 
+/// Like `emit_chunk`, but reindents the text to `indent` if it doesn't come from a real file, and
+/// also passes `callback` the origin of the text: the span it was copied from if it comes from a
+/// real file, and `Origin::Fresh` otherwise.
+fn emit_chunk_origin<F: FnMut(&str, Origin)>(
+    cm: &SourceMap,
+    lo: BytePos,
+    hi: BytePos,
+    indent: Indent,
+    mut callback: F,
+) {
+    let printed = match cm.lookup_byte_offset(lo).sf.name {
+        FileName::Real(_) => false,
+        _ => true,
+    };
+    if !printed {
+        let sp = Span::new(lo, hi, SyntaxContext::empty());
+        return emit_chunk(cm, lo, hi, |s| callback(s, Origin::Recycled(sp)));
+    }
+    if indent == Indent::PRINTED {
+        return emit_chunk(cm, lo, hi, |s| callback(s, Origin::Fresh));
+    }
+    emit_chunk(cm, lo, hi, |s| callback(&indent.reindent(s), Origin::Fresh));
+}
+
 /// Runs `callback` on the source text between `lo` and `hi`.
 fn emit_chunk<F: FnMut(&str)>(cm: &SourceMap, lo: BytePos, hi: BytePos, mut callback: F) {
     let lo = cm.lookup_byte_offset(lo);
@@ -145,6 +1101,49 @@ fn emit_chunk<F: FnMut(&str)>(cm: &SourceMap, lo: BytePos, hi: BytePos, mut call
 
 /// Print a unified diff between lines of `s1` and lines of `s2`.
 pub fn print_diff(s1: &str, s2: &str) {
+    print!("{}", diff_text(s1, s2));
+}
+
+/// Build a unified diff between lines of `s1` and lines of `s2`, in the format printed by
+/// `print_diff`.
+pub fn diff_text(s1: &str, s2: &str) -> String {
+    write_diff(diff::lines(s1, s2), false)
+}
+
+/// Build a unified diff between `s1` and `s2` in the exact form `git apply` expects.  Unlike
+/// `diff_text`, this keeps `\r` at the ends of lines, treats a missing newline at the end of
+/// either text as a change, marking it with `\ No newline at end of file`, and numbers empty
+/// hunk sides from the line before, as in `@@ -0,0 +1,2 @@`.
+pub(super) fn git_diff_text(s1: &str, s2: &str) -> String {
+    let l_lines = split_lines_inclusive(s1);
+    let r_lines = split_lines_inclusive(s2);
+    let results = diff::slice(&l_lines, &r_lines)
+        .into_iter()
+        .map(|r| match r {
+            diff::Result::Left(&s) => diff::Result::Left(s),
+            diff::Result::Right(&s) => diff::Result::Right(s),
+            diff::Result::Both(&s1, &s2) => diff::Result::Both(s1, s2),
+        })
+        .collect();
+    write_diff(results, true)
+}
+
+/// Split `s` into lines, keeping each line's `\n` terminator.  The last line has no terminator if
+/// `s` doesn't end with a newline.
+fn split_lines_inclusive(s: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        lines.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    lines
+}
+
+/// Build a unified diff from the line-by-line comparison `results`.  In `git` mode, the lines
+/// include their terminators, as in `git_diff_text`.
+fn write_diff(results: Vec<diff::Result<&str>>, git: bool) -> String {
     enum State {
         /// We're not in a hunk, just keeping `buf` populated with `CONTEXT` lines of history.
         History,
@@ -159,13 +1158,14 @@ pub fn print_diff(s1: &str, s2: &str) {
 
     const CONTEXT: usize = 3;
 
+    let mut out = String::new();
     let mut buf = VecDeque::new();
     let mut state = State::History;
 
     let mut l_line = 1;
     let mut r_line = 1;
 
-    for r in diff::lines(s1, s2) {
+    for r in results {
         let changed = match r {
             diff::Result::Both(l, r) => l != r,
             _ => true,
@@ -206,7 +1206,7 @@ pub fn print_diff(s1: &str, s2: &str) {
                         // End of the hunk
                         let end = buf.len() - CONTEXT;
                         let suffix = buf.split_off(end);
-                        print_hunk(&buf, l_start, r_start);
+                        write_hunk(&mut out, &buf, l_start, r_start, git);
                         buf = suffix;
                         state = State::History;
                     } else {
@@ -251,15 +1251,23 @@ pub fn print_diff(s1: &str, s2: &str) {
                 let end = buf.len() - (CONTEXT - unchanged_limit);
                 buf.truncate(end);
             }
-            print_hunk(&buf, l_start, r_start);
+            write_hunk(&mut out, &buf, l_start, r_start, git);
         }
         _ => {}
     }
+
+    out
 }
 
-/// Print a single diff hunk, starting at line `l_start` in the left file and `r_start` in the
-/// right file.
-fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize) {
+/// Write a single diff hunk to `out`, starting at line `l_start` in the left file and `r_start` in
+/// the right file.  In `git` mode, the lines include their terminators, as in `git_diff_text`.
+fn write_hunk(
+    out: &mut String,
+    buf: &VecDeque<diff::Result<&str>>,
+    l_start: usize,
+    r_start: usize,
+    git: bool,
+) {
     let l_size = buf
         .iter()
         .filter(|r| match r {
@@ -276,9 +1284,23 @@ fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize
         })
         .count();
 
-    println!("@@ -{},{} +{},{} @@", l_start, l_size, r_start, r_size);
+    // `git apply` numbers an empty side of a hunk from the line before it.
+    let l_start = if git && l_size == 0 { l_start - 1 } else { l_start };
+    let r_start = if git && r_size == 0 { r_start - 1 } else { r_start };
+    writeln!(out, "@@ -{},{} +{},{} @@", l_start, l_size, r_start, r_size).unwrap();
 
-    // Print all "left" lines immediately.  Keep all "right" lines and print them just before the
+    let write_line = |out: &mut String, prefix: char, s: &str| {
+        if !git {
+            writeln!(out, "{}{}", prefix, s).unwrap();
+        } else if s.ends_with('\n') {
+            write!(out, "{}{}", prefix, s).unwrap();
+        } else {
+            writeln!(out, "{}{}", prefix, s).unwrap();
+            writeln!(out, "\\ No newline at end of file").unwrap();
+        }
+    };
+
+    // Write all "left" lines immediately.  Keep all "right" lines and write them just before the
     // next unchanged line.  This way we get the usual output, with separate old and new blocks:
     //   unchanged
     //  -old1
@@ -290,22 +1312,120 @@ fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize
     for r in buf {
         match r {
             diff::Result::Left(s) => {
-                println!("-{}", s);
+                write_line(out, '-', s);
             }
             diff::Result::Right(s) => {
                 right_buf.push(s);
             }
             diff::Result::Both(s1, s2) => {
                 if s1 != s2 {
-                    println!("-{}", s1);
+                    write_line(out, '-', s1);
                     right_buf.push(s2);
                 } else {
                     for s in right_buf.drain(..) {
-                        println!("+{}", s);
+                        write_line(out, '+', s);
                     }
-                    println!(" {}", s1);
+                    write_line(out, ' ', s1);
                 }
             }
         }
     }
+    // A hunk at the end of the file may end with added lines instead of context.
+    for s in right_buf.drain(..) {
+        write_line(out, '+', s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::source_map::DUMMY_SP;
+
+    use crate::ast_manip::CommentMap;
+    use crate::rewrite::rewrite;
+    use crate::rewrite::test_util::{same_ids, with_crate, with_overlapping_edits};
+
+    /// Apply `ws` to `text` with a single boundary at `b`, and get the result and the new
+    /// boundary.
+    fn apply_at(ws: Whitespace, text: &str, b: usize) -> (String, usize) {
+        let mut boundaries = [b];
+        let text = ws.apply(text, &mut boundaries);
+        (text, boundaries[0])
+    }
+
+    #[test]
+    fn whitespace_leading() {
+        let text = "\n\nfn f() {}\n";
+        assert_eq!(apply_at(Whitespace::PreserveExact, text, 1), (text.to_owned(), 1));
+        let expected = ("fn f() {}\n".to_owned(), 0);
+        assert_eq!(apply_at(Whitespace::CollapseBlankRuns, text, 1), expected);
+        assert_eq!(apply_at(Whitespace::EnsureSingleSeparator, text, 1), expected);
+    }
+
+    #[test]
+    fn whitespace_trailing() {
+        let text = "fn f() {}\n\n\n";
+        assert_eq!(apply_at(Whitespace::PreserveExact, text, 12), (text.to_owned(), 12));
+        // Only the final line break is kept.
+        let expected = ("fn f() {}\n".to_owned(), 10);
+        assert_eq!(apply_at(Whitespace::CollapseBlankRuns, text, 12), expected);
+        assert_eq!(apply_at(Whitespace::EnsureSingleSeparator, text, 12), expected);
+    }
+
+    #[test]
+    fn whitespace_blank_run() {
+        let text = "fn f() {}\n\n\n\n    fn g() {}";
+        assert_eq!(apply_at(Whitespace::PreserveExact, text, 10), (text.to_owned(), 10));
+        assert_eq!(
+            apply_at(Whitespace::CollapseBlankRuns, text, 10),
+            ("fn f() {}\n\n    fn g() {}".to_owned(), 10)
+        );
+        assert_eq!(
+            apply_at(Whitespace::EnsureSingleSeparator, text, 10),
+            ("fn f() {}\n    fn g() {}".to_owned(), 10)
+        );
+    }
+
+    /// Replace the text `old` of the file `fn f() {}\n` with `new`, and get the new text.
+    fn replace_in_file(old: &str, new: &str) -> String {
+        let src = "fn f() {}\n";
+        with_crate(src, |sess, _| {
+            let cm = sess.source_map();
+            let sf = cm.lookup_byte_offset(BytePos(0)).sf;
+            let lo = sf.start_pos + BytePos::from_usize(src.find(old).unwrap());
+            let hi = lo + BytePos::from_usize(old.len());
+            let old_span = Span::new(lo, hi, SyntaxContext::empty());
+            let printed = cm.new_source_file(FileName::anon_source_code(new), new.to_owned());
+            let new_span = Span::new(printed.start_pos, printed.end_pos, SyntaxContext::empty());
+
+            let mut rw = TextRewrite::new(DUMMY_SP, DUMMY_SP);
+            rw.rewrites.push(TextRewrite::new(old_span, new_span));
+            let texts = apply_rewrites(cm, rw, &ApplyOptions::default());
+            texts.values().next().unwrap().clone()
+        })
+    }
+
+    #[test]
+    fn final_newline() {
+        // A line break added after the last item is dropped again.
+        assert_eq!(replace_in_file("{}", "{ 0 }\n"), "fn f() { 0 }\n");
+        // A rewrite that replaces the final line break itself decides how the file ends.
+        assert_eq!(replace_in_file("{}\n", "{ 0 }"), "fn f() { 0 }");
+        assert_eq!(replace_in_file("{}\n", "{ 0 }\n\n"), "fn f() { 0 }\n\n");
+    }
+
+    #[test]
+    fn conflict_markers() {
+        with_overlapping_edits(|sess, old, new, _| {
+            let rw = rewrite(sess, old, new, &CommentMap::default(), same_ids(old), |_| {});
+            let opts = ApplyOptions::default().conflict_markers(true);
+            let texts = apply_rewrites(sess.source_map(), rw, &opts);
+            assert_eq!(texts.len(), 1);
+            // The second rewrite overlaps the first, so only the first is proposed.
+            assert_eq!(
+                texts.values().next().unwrap(),
+                "fn f() { (\n<<<<<<< original\n1\n=======\n-1\n>>>>>>> rewritten\n, 2); }"
+            );
+        })
+    }
 }
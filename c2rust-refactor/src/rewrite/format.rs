@@ -0,0 +1,85 @@
+//! Formatting of rewritten code with `rustfmt`, limited to the lines the rewrites changed.
+//! Running `rustfmt` on a whole file would also reformat code the rewrites never touched, so the
+//! diff would no longer show only the actual changes.
+//!
+//! This runs the `rustfmt` binary (or the one named by the `RUSTFMT` environment variable), using
+//! its unstable `--file-lines` option to restrict formatting to the changed lines.  It's only
+//! available with the `rustfmt` feature.
+use json::{self, JsonValue};
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use syntax::source_map::SourceMap;
+use syntax_pos::FileName;
+
+use crate::rewrite::files::{apply_rewrites_with_ranges, ApplyOptions};
+use crate::rewrite::lines::changed_line_ranges;
+use crate::rewrite::TextRewrite;
+
+/// Run `rustfmt` on `text`, formatting only the lines in `lines` (as returned by
+/// `lines::changed_line_ranges`).  If `lines` is empty, `text` is returned as is, without running
+/// `rustfmt`.
+pub fn rustfmt_lines(text: &str, lines: &[(usize, usize)]) -> io::Result<String> {
+    if lines.is_empty() {
+        return Ok(text.to_owned());
+    }
+
+    let file_lines = JsonValue::Array(
+        lines
+            .iter()
+            .map(|&(lo, hi)| {
+                object! {
+                    "file" => "stdin",
+                    "range" => array![lo, hi],
+                }
+            })
+            .collect(),
+    );
+
+    let rustfmt = env::var_os("RUSTFMT").unwrap_or_else(|| "rustfmt".into());
+    let mut child = Command::new(rustfmt)
+        .arg("--unstable-features")
+        .arg("--file-lines")
+        .arg(json::stringify(file_lines))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // If `rustfmt` exits early, report its error message rather than the broken pipe.
+    let written = child
+        .stdin
+        .take()
+        .expect("rustfmt stdin was not captured")
+        .write_all(text.as_bytes());
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "rustfmt failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim_end(),
+            ),
+        ));
+    }
+    written?;
+    String::from_utf8(output.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Apply `rw` to the source code, as in `files::apply_rewrites`, and then run `rustfmt` on the
+/// lines of each file that the rewrites changed.
+pub fn apply_rewrites_formatted(
+    cm: &SourceMap,
+    rw: TextRewrite,
+    opts: &ApplyOptions,
+) -> io::Result<HashMap<FileName, String>> {
+    apply_rewrites_with_ranges(cm, rw, opts)
+        .into_iter()
+        .map(|(name, (text, ranges))| {
+            let lines = changed_line_ranges(&text, &ranges);
+            Ok((name, rustfmt_lines(&text, &lines)?))
+        })
+        .collect()
+}
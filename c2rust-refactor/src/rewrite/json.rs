@@ -1,10 +1,20 @@
 use json::{self, JsonValue};
+#[cfg(feature = "serde")]
+use rustc::session::Session;
 use std::collections::{HashMap, HashSet};
 use syntax::ast::*;
+#[cfg(feature = "serde")]
+use syntax::source_map::DUMMY_SP;
 use syntax::source_map::{SourceMap, Span};
 use syntax::symbol::Symbol;
 use syntax::visit::{self, FnKind, Visitor};
+#[cfg(feature = "serde")]
+use syntax_pos::FileName;
 
+#[cfg(feature = "serde")]
+use crate::rewrite::files::{rewrite_file_changes, rewrites_by_file, ApplyOptions};
+#[cfg(feature = "serde")]
+use crate::rewrite::{span_to_range, LineCol};
 use crate::rewrite::{TextAdjust, TextRewrite};
 
 fn encode_span(sm: &SourceMap, sp: Span) -> JsonValue {
@@ -48,10 +58,19 @@ impl<'a> Encoder<'a> {
         }
     }
 
+    /// Encode `adj` as `null` if it's empty, as the adjustment's name if it has only one, and
+    /// otherwise as an array of names in application order.
     fn encode_adjust(&self, adj: TextAdjust) -> JsonValue {
-        match adj {
-            TextAdjust::None => JsonValue::Null,
-            TextAdjust::Parenthesize => JsonValue::String("parenthesize".to_owned()),
+        let names = adj.iter().map(|a| a.name()).collect::<Vec<_>>();
+        match names.len() {
+            0 => JsonValue::Null,
+            1 => JsonValue::String(names[0].to_owned()),
+            _ => JsonValue::Array(
+                names
+                    .into_iter()
+                    .map(|n| JsonValue::String(n.to_owned()))
+                    .collect(),
+            ),
         }
     }
 }
@@ -73,6 +92,114 @@ pub fn stringify_rewrites(sm: &SourceMap, rs: &[TextRewrite]) -> String {
     json::stringify_pretty(encode_rewrites(sm, rs), 2)
 }
 
+#[cfg(feature = "serde")]
+fn encode_line_col(lc: LineCol) -> JsonValue {
+    object! {
+        "line" => lc.line,
+        "col" => lc.col,
+    }
+}
+
+#[cfg(feature = "serde")]
+fn encode_range(lo: usize, hi: usize, (start, end): (LineCol, LineCol)) -> JsonValue {
+    object! {
+        "lo" => lo,
+        "hi" => hi,
+        "start" => encode_line_col(start),
+        "end" => encode_line_col(end),
+    }
+}
+
+/// Get the line and column of byte offset `pos` in `text`, counted the same way as in
+/// `span_to_range`.
+#[cfg(feature = "serde")]
+fn text_line_col(text: &str, pos: usize) -> LineCol {
+    let before = &text[..pos];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    LineCol {
+        line: before.matches('\n').count() + 1,
+        col: before[line_start..].chars().count(),
+    }
+}
+
+/// Classify a top-level rewrite by where its replacement text comes from, as counted by
+/// `TextRewrite::byte_stats`.
+#[cfg(feature = "serde")]
+fn change_kind(cm: &SourceMap, rw: &TextRewrite) -> &'static str {
+    if rw.new_span == DUMMY_SP {
+        return "deleted";
+    }
+    if rw.byte_stats(cm).fresh == 0 {
+        "recycled"
+    } else {
+        "fresh"
+    }
+}
+
+/// Apply `rw` to the source text and describe each change it makes, for tools that want the
+/// changes in structured form rather than as a diff.  This is only available with the `serde`
+/// feature.  The result is an array with an entry for each changed file, in order of their names:
+///
+/// ```text
+/// { "file": ..., "changes": [{ "old_range", "new_range", "old_text", "new_text", "kind" }, ...] }
+/// ```
+///
+/// `old_range` is the range of `old_text` in the old text of the file, and `new_range` the range
+/// of `new_text` in its new text.  A range gives the byte offsets `lo` and `hi` within the file,
+/// along with the `start` and `end` positions as in `span_to_range`.  `kind` is `"recycled"` if
+/// all of the new text was copied from elsewhere in the original source, `"fresh"` if any of it
+/// was produced by the pretty-printer, or `"deleted"` if the old text is removed.
+///
+/// The changes are listed after cleanup, as they are applied to the file.  Virtual files are
+/// skipped, and conflicting rewrites cause a panic, as when writing the files.
+#[cfg(feature = "serde")]
+pub fn rewrites_to_json(sess: &Session, rw: TextRewrite) -> String {
+    let cm = sess.source_map();
+    let mut files = Vec::new();
+
+    for (rewrites, _nodes, sf) in rewrites_by_file(cm, &rw) {
+        match sf.name {
+            FileName::Real(_) => {}
+            _ => continue,
+        }
+
+        let old_text = sf.src.as_ref().map_or("", |s| s);
+        let (new_text, changes) = rewrite_file_changes(cm, &sf, rewrites, &ApplyOptions::default());
+        let changes = changes
+            .into_iter()
+            .map(|(rw, range)| {
+                let old_lo = (rw.old_span.lo() - sf.start_pos).0 as usize;
+                let old_hi = (rw.old_span.hi() - sf.start_pos).0 as usize;
+                let new_pos = (
+                    text_line_col(&new_text, range.start),
+                    text_line_col(&new_text, range.end),
+                );
+                object! {
+                    "old_range" => encode_range(old_lo, old_hi, span_to_range(sess, rw.old_span)),
+                    "new_range" => encode_range(range.start, range.end, new_pos),
+                    "old_text" => &old_text[old_lo..old_hi],
+                    "new_text" => &new_text[range.clone()],
+                    "kind" => change_kind(cm, &rw),
+                }
+            })
+            .collect();
+
+        files.push((sf.name.to_string(), JsonValue::Array(changes)));
+    }
+
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    let files = files
+        .into_iter()
+        .map(|(name, changes)| {
+            object! {
+                "file" => name,
+                "changes" => changes,
+            }
+        })
+        .collect();
+    json::stringify_pretty(JsonValue::Array(files), 2)
+}
+
 struct MarkVisitor<'a> {
     node_id_map: &'a HashMap<NodeId, NodeId>,
     marks: HashMap<NodeId, Vec<Symbol>>,
@@ -202,3 +329,47 @@ pub fn stringify_marks(
 ) -> String {
     json::stringify_pretty(encode_marks(krate, node_id_map, marks), 2)
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use syntax_pos::{BytePos, Pos, SyntaxContext};
+
+    use crate::rewrite::test_util::with_crate;
+
+    #[test]
+    fn change_kinds() {
+        let src = "fn f() { a; b; c; }\n";
+        with_crate(src, |sess, krate| {
+            let cm = sess.source_map();
+            let span_of = |text: &str| {
+                let lo = krate.span.lo() + BytePos::from_usize(src.find(text).unwrap());
+                Span::new(lo, lo + BytePos::from_usize(text.len()), SyntaxContext::empty())
+            };
+            let printed = cm.new_source_file(FileName::anon_source_code("x + 1"), "x + 1".into());
+            let printed = Span::new(printed.start_pos, printed.end_pos, SyntaxContext::empty());
+
+            let mut rw = TextRewrite::new(DUMMY_SP, DUMMY_SP);
+            rw.rewrites = vec![
+                TextRewrite::new(span_of("a"), span_of("b")),
+                TextRewrite::new(span_of("b"), printed),
+                TextRewrite::new(span_of("c;"), DUMMY_SP),
+            ];
+            let out = ::json::parse(&rewrites_to_json(sess, rw)).unwrap();
+
+            assert_eq!(out.len(), 1);
+            assert_eq!(out[0]["file"], "test.rs");
+            let changes = &out[0]["changes"];
+            let summary = changes
+                .members()
+                .map(|c| (c["kind"].as_str().unwrap(), c["old_text"].as_str().unwrap()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                summary,
+                [("recycled", "a"), ("fresh", "b"), ("deleted", "c;")]
+            );
+            assert_eq!(changes[1]["new_text"], "x + 1");
+            assert_eq!(changes[1]["new_range"]["start"]["col"], 12);
+        })
+    }
+}
@@ -0,0 +1,91 @@
+//! The line numbers of the rewritten source that rewrites changed, for tools that only need to
+//! know which lines of each file are affected, such as CI annotations or running just the tests
+//! that cover the changed code.
+use rustc::session::Session;
+use std::collections::HashMap;
+use std::ops;
+use syntax_pos::FileName;
+
+use crate::rewrite::files::{apply_rewrites_with_ranges, ApplyOptions};
+use crate::rewrite::TextRewrite;
+
+/// Apply `rw` to the source text, as `files::apply_rewrites` does with the default
+/// `ApplyOptions`, and get the 1-based numbers of the lines of each file's new text that contain
+/// text produced by a rewrite.  The numbers are sorted and contain no duplicates.
+///
+/// A rewrite that deletes text counts as changing the line where the text used to be.  A
+/// rewrite whose new text ends with a line break doesn't count the line after it.  Virtual files
+/// are skipped, as in `patch::rewrites_to_patch`.
+pub fn changed_lines(sess: &Session, rw: TextRewrite) -> HashMap<FileName, Vec<usize>> {
+    apply_rewrites_with_ranges(sess.source_map(), rw, &ApplyOptions::default())
+        .into_iter()
+        .filter(|&(ref name, _)| match *name {
+            FileName::Real(_) => true,
+            _ => false,
+        })
+        .map(|(name, (text, ranges))| {
+            let lines = changed_line_ranges(&text, &ranges)
+                .into_iter()
+                .flat_map(|(lo, hi)| lo..=hi)
+                .collect();
+            (name, lines)
+        })
+        .collect()
+}
+
+/// Get the lines of `text` touched by the byte ranges `ranges`, as sorted, non-overlapping ranges
+/// of 1-based line numbers.  The end of each range is inclusive, as `rustfmt` expects, and
+/// adjacent ranges are merged.  An empty range touches the line it's on, and a range ending just
+/// after a line break doesn't touch the next line.
+pub fn changed_line_ranges(text: &str, ranges: &[ops::Range<usize>]) -> Vec<(usize, usize)> {
+    let mut line_starts = vec![0];
+    line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+    let line_of = |pos: usize| match line_starts.binary_search(&pos) {
+        Ok(i) => i + 1,
+        Err(i) => i,
+    };
+
+    let mut lines = ranges
+        .iter()
+        .map(|r| {
+            let first = line_of(r.start);
+            let last = if r.end > r.start {
+                line_of(r.end - 1)
+            } else {
+                first
+            };
+            (first, last)
+        })
+        .collect::<Vec<_>>();
+    lines.sort();
+
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(lines.len());
+    for (lo, hi) in lines {
+        if let Some(last) = merged.last_mut() {
+            if lo <= last.1 + 1 {
+                last.1 = last.1.max(hi);
+                continue;
+            }
+        }
+        merged.push((lo, hi));
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_ranges() {
+        let text = "fn a() {}\nfn b() {\n    2\n}\nfn c() {}\n";
+        // The range ending after the line break of line 2 doesn't touch line 3, but it's merged
+        // with the adjacent range on line 3.  The empty range covers its line.
+        assert_eq!(
+            changed_line_ranges(text, &[10..19, 19..24, 30..30]),
+            vec![(2, 3), (5, 5)]
+        );
+        assert_eq!(changed_line_ranges(text, &[10..19]), vec![(2, 2)]);
+        assert_eq!(changed_line_ranges(text, &[]), vec![]);
+    }
+}
@@ -53,32 +53,144 @@
 //! does support another strategy, such as `Item`.  This is the point where rewriting actually
 //! happens: when `recursive` fails, `Rewrite::rewrite` will try the next strategy (such as
 //! `print`), which can perform rewrites to correct the error at this higher level.
+//!
+//! Before resorting to `print`, such a node tries `partial`, which reprints only the nodes inside
+//! it where rewriting failed, taking their text from the printed node and keeping the old text of
+//! everything else.  See the `rewrite::strategy::partial` module docs for details.
 
 use rustc::session::Session;
+use rustc_data_structures::sync::Lrc;
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::mem;
-use std::ops::{Deref, DerefMut};
+use std::ops::{self, BitOr, BitOrAssign, Deref, DerefMut};
+use std::rc::Rc;
+use std::slice;
 use syntax::ast::*;
-use syntax::source_map::{Span, DUMMY_SP};
+use syntax::source_map::{SourceFile, SourceMap, Span, SyntaxContext, DUMMY_SP};
 use syntax::util::parser;
+use syntax_pos::{BytePos, FileName, Pos};
 
-use crate::ast_manip::ast_map::{map_ast, AstMap};
-use crate::ast_manip::{GetSpan, Visit, CommentMap};
+use crate::ast_manip::ast_map::{map_ast, AstMap, NodeTable};
+use crate::ast_manip::{
+    structural_hash, AstHash, AstName, CommentMap, GetSpan, ListNodeIds, MaybeGetNodeId, Visit,
+};
 use crate::driver;
 
 mod cleanup;
 pub mod files;
+#[cfg(feature = "rustfmt")]
+pub mod format;
 pub mod json;
+pub mod lines;
+pub mod patch;
+#[cfg(feature = "rewrite_profile")]
+pub mod profile;
+pub mod report;
+#[cfg(feature = "debug")]
+pub mod snapshot;
+pub mod span_util;
+pub mod text_edit;
+#[cfg(test)]
+mod test_util;
 
 mod base;
 mod strategy;
 
+use self::cleanup::{cleanup_rewrites_with, suppress_verbatim};
+use self::strategy::partial::Deferred;
+use self::strategy::print::{PrintParse, Splice};
+
 pub use self::base::Rewrite;
+pub use self::cleanup::{merge_rewrites, minimize, RewriteConflict};
+pub use self::strategy::print::{DefaultPrinter, FreshPrinter};
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub enum TextAdjust {
-    None,
-    Parenthesize,
+/// A set of adjustments to apply to the new text of a `TextRewrite` as it's spliced in.
+///
+/// Each adjustment wraps the text in a prefix and a suffix.  When a rewrite has several
+/// adjustments, they are applied in the fixed order given by `TextAdjust::ORDER`, each one wrapping
+/// the result of the ones before it.  So for adjustments `A` and `B`, in that order, the final text
+/// is `B.prefix A.prefix text A.suffix B.suffix`, no matter how the set was built.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct TextAdjust(u8);
+
+impl TextAdjust {
+    /// No adjustment: the new text is used as-is.
+    pub const NONE: TextAdjust = TextAdjust(0);
+    /// Wrap the new text in parentheses.
+    pub const PARENTHESIZE: TextAdjust = TextAdjust(1 << 0);
+
+    /// Every single adjustment, in the order they are applied (innermost first).
+    pub const ORDER: &'static [TextAdjust] = &[TextAdjust::PARENTHESIZE];
+
+    pub fn is_none(self) -> bool {
+        self == TextAdjust::NONE
+    }
+
+    /// Check whether `self` includes all the adjustments in `other`.
+    pub fn contains(self, other: TextAdjust) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Iterate over the single adjustments in `self`, in application order.
+    pub fn iter(self) -> impl Iterator<Item = TextAdjust> {
+        TextAdjust::ORDER
+            .iter()
+            .cloned()
+            .filter(move |&adj| self.contains(adj))
+    }
+
+    /// The name of a single adjustment, as used in JSON output.
+    pub fn name(self) -> &'static str {
+        match self {
+            TextAdjust::PARENTHESIZE => "parenthesize",
+            _ => panic!("{:?} is not a single adjustment", self),
+        }
+    }
+
+    /// The text to emit before and after the new text (with all earlier adjustments already
+    /// applied) for a single adjustment.
+    fn wrapper(self) -> (&'static str, &'static str) {
+        match self {
+            TextAdjust::PARENTHESIZE => ("(", ")"),
+            _ => panic!("{:?} is not a single adjustment", self),
+        }
+    }
+
+    /// The combined text to emit before the new text.
+    pub fn prefix(self) -> String {
+        let mut s = String::new();
+        for adj in self.iter().collect::<Vec<_>>().into_iter().rev() {
+            s.push_str(adj.wrapper().0);
+        }
+        s
+    }
+
+    /// The combined text to emit after the new text.
+    pub fn suffix(self) -> String {
+        let mut s = String::new();
+        for adj in self.iter() {
+            s.push_str(adj.wrapper().1);
+        }
+        s
+    }
+}
+
+impl BitOr for TextAdjust {
+    type Output = TextAdjust;
+
+    fn bitor(self, other: TextAdjust) -> TextAdjust {
+        TextAdjust(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for TextAdjust {
+    fn bitor_assign(&mut self, other: TextAdjust) {
+        self.0 |= other.0;
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -95,7 +207,7 @@ pub struct TextRewrite {
 
 impl TextRewrite {
     pub fn new(old_span: Span, new_span: Span) -> TextRewrite {
-        Self::adjusted(old_span, new_span, TextAdjust::None)
+        Self::adjusted(old_span, new_span, TextAdjust::NONE)
     }
 
     pub fn adjusted(old_span: Span, new_span: Span, adjust: TextAdjust) -> TextRewrite {
@@ -107,6 +219,108 @@ impl TextRewrite {
             nodes: Vec::new(),
         }
     }
+
+    /// Get the source text that this rewrite replaces.  Returns `None` if the text is unavailable,
+    /// as for rewrites of `DUMMY_SP`.
+    pub fn old_text(&self, sess: &Session) -> Option<String> {
+        if self.old_span == DUMMY_SP {
+            return None;
+        }
+        sess.source_map().span_to_snippet(self.old_span).ok()
+    }
+
+    /// Get the text that replaces `old_span`, with any nested rewrites and the text adjustment
+    /// applied.  A `new_span` of `DUMMY_SP` denotes a deletion, whose new text is empty.  Returns
+    /// `None` if some of the text is unavailable, or if nested rewrites conflict.
+    pub fn new_text(&self, sess: &Session) -> Option<String> {
+        if self.new_span == DUMMY_SP {
+            return Some(String::new());
+        }
+        let cm = sess.source_map();
+        if !self.new_text_available(cm) {
+            return None;
+        }
+
+        // Nested rewrites are in the order they were produced, not necessarily in source order,
+        // so sort them as `files::apply_rewrites` does.
+        let mut conflict = false;
+        let mut rw = self.clone();
+        rw.rewrites = cleanup_rewrites_with(cm, rw.rewrites, &mut |_, _| conflict = true);
+        if conflict {
+            return None;
+        }
+
+        let mut text = String::new();
+        files::rewrite_range(
+            cm,
+            rw.old_span.lo(),
+            rw.old_span.hi(),
+            slice::from_ref(&rw),
+            &mut |s| text.push_str(s),
+        );
+        Some(text)
+    }
+
+    fn new_text_available(&self, cm: &SourceMap) -> bool {
+        cm.span_to_snippet(self.new_span).is_ok()
+            && self.rewrites.iter().all(|rw| rw.new_text_available(cm))
+    }
+
+    /// Count the bytes of new text produced by this rewrite and its nested rewrites, by where the
+    /// text comes from.  Text whose `new_span` lies in a real file is recycled.  Otherwise it was
+    /// printed, as is the text added by `adjust`.
+    pub fn byte_stats(&self, cm: &SourceMap) -> ByteStats {
+        let mut stats = ByteStats::default();
+        stats.fresh += self.adjust.prefix().len() + self.adjust.suffix().len();
+        if self.new_span == DUMMY_SP {
+            return stats;
+        }
+
+        let mut len = span_len(self.new_span);
+        for child in &self.rewrites {
+            len -= span_len(child.old_span);
+            stats.add(child.byte_stats(cm));
+        }
+
+        match cm.lookup_byte_offset(self.new_span.lo()).sf.name {
+            FileName::Real(_) => stats.recycled += len,
+            _ => stats.fresh += len,
+        }
+        stats
+    }
+}
+
+fn span_len(sp: Span) -> usize {
+    (sp.hi().0 - sp.lo().0) as usize
+}
+
+/// Byte counts of the replacement text produced by rewrites, split by whether it was recycled
+/// from the old source or freshly printed.  Unlike `report::Coverage`, this doesn't count the text
+/// that rewrites leave alone, so it measures how well the rewritten regions themselves preserve
+/// the original source.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ByteStats {
+    /// Bytes of replacement text recycled from the original source.
+    pub recycled: usize,
+    /// Bytes of replacement text produced by the pretty-printer or inserted as literal text.
+    pub fresh: usize,
+}
+
+impl ByteStats {
+    pub fn add(&mut self, other: ByteStats) {
+        self.recycled += other.recycled;
+        self.fresh += other.fresh;
+    }
+
+    /// The fraction of the replacement text that was recycled.  This is 1 if there is no
+    /// replacement text at all.
+    pub fn fraction_recycled(&self) -> f64 {
+        let total = self.recycled + self.fresh;
+        if total == 0 {
+            return 1.0;
+        }
+        self.recycled as f64 / total as f64
+    }
 }
 
 /// Common ID type for nodes and `Attribute`s.  Both are sequence items, but `Attribute`s have
@@ -159,12 +373,269 @@ pub enum ExprPrec {
     LeftLess(i8),
 }
 
+impl ExprPrec {
+    /// Determine the adjustment needed when splicing `expr` into a position with this precedence.
+    /// This is the one place that decides when an expression needs parentheses.
+    pub fn adjustment(self, expr: &Expr) -> TextAdjust {
+        // Check for cases where we can safely omit parentheses.
+        let prec = expr.precedence();
+        let need_parens = match self {
+            ExprPrec::Normal(min_prec) => prec.order() < min_prec,
+            ExprPrec::Cond(min_prec) => {
+                prec.order() < min_prec || parser::contains_exterior_struct_lit(expr)
+            }
+            ExprPrec::Callee(min_prec) => match expr.node {
+                ExprKind::Field(..) => true,
+                _ => prec.order() < min_prec,
+            },
+            ExprPrec::LeftLess(min_prec) => match expr.node {
+                ExprKind::Cast(..) | ExprKind::Type(..) => true,
+                _ => prec.order() < min_prec,
+            },
+        };
+
+        if need_parens {
+            TextAdjust::PARENTHESIZE
+        } else {
+            TextAdjust::NONE
+        }
+    }
+}
+
+/// Options controlling the behavior of `rewrite_with_options`.  Use `RewriteOptions::default()`
+/// for the standard behavior, and the builder-style setters to change individual options:
+///
+/// ```ignore
+/// let opts = RewriteOptions::default().minimize(true);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RewriteOptions {
+    /// Run `minimize` on the finished rewrites, which merges adjacent rewrites of contiguous text
+    /// into larger ones, so there are fewer separate edits.  The resulting text is the same either
+    /// way.  Like `cleanup_rewrites`, this panics on conflicting rewrites, which `rewrite` itself
+    /// leaves for the caller to detect or resolve.  Defaults to `false`.
+    pub minimize: bool,
+    /// If set, items whose `#[cfg]` attributes are disabled under this configuration are never
+    /// rewritten, and keep their original text even if the new AST changes them.  Defaults to
+    /// `None`, which rewrites all items.
+    pub active_cfg: Option<CrateConfig>,
+    /// Regions of the old source whose text must be preserved byte-for-byte.  Any rewrite that
+    /// would change text inside one of these regions is dropped, even if the new AST differs
+    /// there.  Defaults to empty.
+    pub verbatim: Vec<Span>,
+    /// The printer for fresh code, which has no old text to recycle.  See `FreshPrinter`.
+    /// Defaults to `DefaultPrinter`, the built-in pretty-printer.
+    pub printer: Rc<dyn FreshPrinter>,
+    /// A callback to run on each `TransitionEvent`, as the rewriter switches between recycling old
+    /// text and printing fresh code.  Defaults to `None`.
+    pub on_transition: Option<TransitionHook>,
+    /// Keep the old spelling of integer literals in printed code: when a literal in a printed
+    /// node still has the span of an old literal with the same value, the old text, such as
+    /// `0xff` or `1_000`, is used instead of the printer's plain decimal.  Defaults to `false`.
+    pub preserve_int_bases: bool,
+    /// The number of node comparisons allowed in each top-level item before the rest of it is
+    /// reprinted instead.  See `RewriteCtxt::set_recycle_budget`.  Defaults to `None`, which
+    /// allows any amount of work.
+    pub recycle_budget: Option<usize>,
+    /// How to treat the comma after the last element of each comma-separated list that gets
+    /// rewritten element by element.  See `TrailingComma`.  Defaults to
+    /// `TrailingComma::Preserve`.
+    pub trailing_comma: TrailingComma,
+    /// Comments to put around fresh statements and items, so reviewers can tell printed code from
+    /// recycled code.  See `ProvenanceMarkers`.  Defaults to `None`, which adds no comments.
+    pub provenance_markers: Option<ProvenanceMarkers>,
+    /// Keep the old text of lint attributes like `#[allow(dead_code)]` whose level and set of lints
+    /// are unchanged, even if the new AST lists the lints in another order or rebuilt the
+    /// attribute.  See `strategy::lint_attr`.  Defaults to `true`.
+    pub preserve_lint_attrs: bool,
+}
+
+impl Default for RewriteOptions {
+    fn default() -> RewriteOptions {
+        RewriteOptions {
+            minimize: false,
+            active_cfg: None,
+            verbatim: Vec::new(),
+            printer: Rc::new(DefaultPrinter),
+            on_transition: None,
+            preserve_int_bases: false,
+            recycle_budget: None,
+            trailing_comma: TrailingComma::Preserve,
+            provenance_markers: None,
+            preserve_lint_attrs: true,
+        }
+    }
+}
+
+impl RewriteOptions {
+    pub fn minimize(mut self, minimize: bool) -> Self {
+        self.minimize = minimize;
+        self
+    }
+
+    pub fn active_cfg(mut self, cfg: CrateConfig) -> Self {
+        self.active_cfg = Some(cfg);
+        self
+    }
+
+    /// Add `span` to the regions that are never rewritten.  See `RewriteOptions::verbatim`.
+    pub fn verbatim(mut self, span: Span) -> Self {
+        self.verbatim.push(span);
+        self
+    }
+
+    pub fn printer(mut self, printer: Rc<dyn FreshPrinter>) -> Self {
+        self.printer = printer;
+        self
+    }
+
+    pub fn on_transition<F: FnMut(TransitionEvent) + 'static>(mut self, f: F) -> Self {
+        self.on_transition = Some(TransitionHook(Rc::new(RefCell::new(f))));
+        self
+    }
+
+    pub fn preserve_int_bases(mut self, preserve_int_bases: bool) -> Self {
+        self.preserve_int_bases = preserve_int_bases;
+        self
+    }
+
+    pub fn recycle_budget(mut self, budget: usize) -> Self {
+        self.recycle_budget = Some(budget);
+        self
+    }
+
+    pub fn trailing_comma(mut self, trailing_comma: TrailingComma) -> Self {
+        self.trailing_comma = trailing_comma;
+        self
+    }
+
+    pub fn provenance_markers(mut self, markers: ProvenanceMarkers) -> Self {
+        self.provenance_markers = Some(markers);
+        self
+    }
+
+    pub fn preserve_lint_attrs(mut self, preserve_lint_attrs: bool) -> Self {
+        self.preserve_lint_attrs = preserve_lint_attrs;
+        self
+    }
+}
+
+/// The comments to put around fresh code.  See `RewriteOptions::provenance_markers`.
+///
+/// Each statement or item that gets printed fresh, with its old text (if any) replaced by the
+/// printer's output, gets the `begin` line before it and the `end` line after it, indented to
+/// match.  Only the outermost fresh node is marked, so statements inside a fresh item are
+/// covered by the item's markers.  Fresh code inside expressions, types, and other nodes below
+/// the statement level is never marked, since line comments there could break up the surrounding
+/// code.  A marked node that shares its line with other code is moved to a line of its own.
+///
+/// Recycled code inside a marked region is still marked as part of it: the markers show where
+/// the printer took over, not which text is new.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ProvenanceMarkers {
+    pub begin: String,
+    pub end: String,
+}
+
+impl Default for ProvenanceMarkers {
+    fn default() -> ProvenanceMarkers {
+        ProvenanceMarkers {
+            begin: "// BEGIN generated".to_owned(),
+            end: "// END generated".to_owned(),
+        }
+    }
+}
+
+/// A policy for the trailing comma of comma-separated lists, such as call arguments, struct
+/// fields, generic params, and `match` arms.  It applies only to lists that change in length,
+/// which are rewritten element by element, and leaves all other lists as they are.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrailingComma {
+    /// Keep the trailing comma if the old list had one, and don't add one otherwise.
+    Preserve,
+    /// Always end the list with a comma.
+    Always,
+    /// Never end the list with a comma, except for a list of one element, where the comma may be
+    /// needed to make a one-element tuple.
+    Never,
+    /// End the list with a comma only if its closing delimiter is on a line of its own, as in a
+    /// list with one element per line.
+    Vertical,
+}
+
+/// The direction of a `TransitionEvent`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Transition {
+    /// Switching from recycled to fresh: the old text at `old_span` is being replaced with the
+    /// printed text of a new node.
+    Fresh,
+    /// Switching from fresh to recycled: inside printed text, the text of a node at `new_span` is
+    /// being replaced with the node's old text at `old_span`.
+    Recycle,
+}
+
+/// A switch between recycling old source text and printing fresh code, as reported to
+/// `RewriteOptions::on_transition`.  The same switches are logged as `REWRITE` or `INSERT AT`
+/// (for `Fresh`) and `REVERT` (for `Recycle`).
+///
+/// `new_span` is the span of the node's printed text, which lives in a temporary file of the
+/// source map rather than in the file being rewritten.
+#[derive(Clone, Debug)]
+pub struct TransitionEvent {
+    pub transition: Transition,
+    /// The kind of node, as given by `AstName::ast_name`.
+    pub kind: String,
+    pub old_span: Span,
+    pub new_span: Span,
+}
+
+/// A callback for `TransitionEvent`s.  See `RewriteOptions::on_transition`.
+///
+/// The callback gets only the event, not the `RewriteCtxt`, so it can't interfere with the
+/// ongoing rewrite.  It's not allowed to start another rewrite that reports to the same hook.
+#[derive(Clone)]
+pub struct TransitionHook(Rc<RefCell<dyn FnMut(TransitionEvent)>>);
+
+impl fmt::Debug for TransitionHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<TransitionHook>")
+    }
+}
+
+/// The location and text of a span of source code.  See `RewriteCtxt::span_info`.
+#[derive(Clone, Debug)]
+pub struct SpanInfo {
+    /// The file containing the span.
+    pub file: Lrc<SourceFile>,
+    /// The byte range of the span within the file, relative to the start of the file.
+    pub range: ops::Range<usize>,
+    /// The source text of the span.
+    pub text: String,
+}
+
 pub struct RewriteCtxt<'s> {
     sess: &'s Session,
-    old_nodes: AstMap<'s>,
+    opts: RewriteOptions,
+    /// Lookup table for nodes of the old AST.  This is usually built for the rewrite, but
+    /// `rewrite_node` borrows a table the caller built ahead of time.
+    old_nodes: Cow<'s, AstMap<'s>>,
     comment_map: &'s CommentMap,
     text_span_cache: HashMap<String, Span>,
 
+    /// Cache of source map lookups, keyed on the span looked up.  See `span_info`.
+    span_cache: RefCell<HashMap<Span, Option<Rc<SpanInfo>>>>,
+
+    /// Cache of reparsed ASTs, keyed on the parsed type and the printed text.  See `reparse`.
+    reparse_cache: HashMap<(TypeId, String), Rc<dyn Any>>,
+
+    /// Structural hashes of nodes of the new AST, keyed on the node's type and address.  See
+    /// `new_node_hash`.
+    new_hashes: RefCell<HashMap<(TypeId, usize), u64>>,
+
+    /// Number of fresh-mode regions (pretty-printed nodes being spliced in) we're currently
+    /// inside.  When this drops back to zero, `reparse_cache` is cleared.
+    fresh_depth: usize,
+
     /// The span of the new AST the last time we entered "fresh" mode.  This lets us avoid infinite
     /// recursion - see comment in `splice_fresh`.
     fresh_start: Span,
@@ -173,6 +644,27 @@ pub struct RewriteCtxt<'s> {
     /// precedence, it will be parenthesized.
     expr_prec: ExprPrec,
 
+    /// Whether a node that no strategy can rewrite is deferred to the enclosing `partial` rewrite
+    /// instead of failing.  See `strategy::partial`.
+    defer_failures: bool,
+
+    /// Nodes deferred so far by the current `partial` rewrite.
+    deferred: Vec<Deferred>,
+
+    /// While `partial` is looking for the reparsed counterparts of its deferred nodes, the ones
+    /// not found yet.  Recovery only looks for these nodes while this is set.
+    locating: Option<Vec<Deferred>>,
+
+    /// The number of `Rewrite` calls allowed per top-level item, or `None` for no limit.  See
+    /// `set_recycle_budget`.
+    recycle_budget: Option<usize>,
+
+    /// The number of `Rewrite` calls left in the budget of the current top-level item.
+    budget_left: usize,
+
+    /// The span of the top-level item whose budget is being spent.
+    budget_scope: Span,
+
     /// Mapping from NodeIds in the new AST to corresponding NodeIds in the old AST.  This has two
     /// purposes.  (1) If `node_id_map[new_node.id] == old_node.id`, then `new_node` and `old_node`
     /// are considered "the same node" for sequence rewriting purposes.  This affects the
@@ -186,18 +678,31 @@ pub struct RewriteCtxt<'s> {
 impl<'s> RewriteCtxt<'s> {
     fn new(
         sess: &'s Session,
-        old_nodes: AstMap<'s>,
+        old_nodes: Cow<'s, AstMap<'s>>,
         comment_map: &'s CommentMap,
         node_id_map: HashMap<NodeId, NodeId>,
+        opts: RewriteOptions,
     ) -> RewriteCtxt<'s> {
+        let recycle_budget = opts.recycle_budget;
         RewriteCtxt {
             sess,
+            opts,
             old_nodes,
             comment_map,
             text_span_cache: HashMap::new(),
+            span_cache: RefCell::new(HashMap::new()),
+            reparse_cache: HashMap::new(),
+            new_hashes: RefCell::new(HashMap::new()),
+            fresh_depth: 0,
 
             fresh_start: DUMMY_SP,
             expr_prec: ExprPrec::Normal(parser::PREC_RESET),
+            defer_failures: false,
+            deferred: Vec::new(),
+            locating: None,
+            recycle_budget,
+            budget_left: 0,
+            budget_scope: DUMMY_SP,
             node_id_map,
         }
     }
@@ -206,10 +711,105 @@ impl<'s> RewriteCtxt<'s> {
         self.sess
     }
 
+    pub fn options(&self) -> &RewriteOptions {
+        &self.opts
+    }
+
+    /// Check whether the old source text at `span` can be recycled: the span must come from
+    /// user-written source, not from a macro expansion, its text must be available, and some node
+    /// of the old AST must cover it.  Transforms can use this to decide whether to reuse an old
+    /// subtree or build a new one.
+    pub fn can_recycle(&self, span: Span) -> bool {
+        self.is_source_text(span) && self.in_old_nodes(span)
+    }
+
+    /// Like `can_recycle`, but without requiring an old node to cover `span`.  Strategies use this
+    /// for text next to the nodes, such as outer attributes and the comments above an item.
+    fn is_source_text(&self, span: Span) -> bool {
+        if !base::is_rewritable(span) {
+            return false;
+        }
+        match self.span_info(span) {
+            Some(info) => match info.file.name {
+                FileName::Macros(..) => false,
+                _ => true,
+            },
+            None => false,
+        }
+    }
+
+    /// Check whether some node in the tables of the old AST covers `span`.
+    fn in_old_nodes(&self, span: Span) -> bool {
+        let nodes = &self.old_nodes;
+        nodes.exprs.get_by_span(span).is_some()
+            || nodes.pats.get_by_span(span).is_some()
+            || nodes.tys.get_by_span(span).is_some()
+            || nodes.stmts.get_by_span(span).is_some()
+            || nodes.items.get_by_span(span).is_some()
+            || nodes.foreign_items.get_by_span(span).is_some()
+            || nodes.blocks.get_by_span(span).is_some()
+    }
+
+    /// Look up the file, byte range, and source text of `span`.  Returns `None` if the text isn't
+    /// available, as for a span that crosses file boundaries.  Strategies often consult the same
+    /// spans several times, so the result is cached until the end of the rewrite.
+    pub fn span_info(&self, span: Span) -> Option<Rc<SpanInfo>> {
+        if let Some(info) = self.span_cache.borrow().get(&span) {
+            return info.clone();
+        }
+
+        let cm = self.sess.source_map();
+        let info = cm.span_to_snippet(span).ok().map(|text| {
+            let loc = cm.lookup_byte_offset(span.lo());
+            let lo = loc.pos.to_usize();
+            Rc::new(SpanInfo {
+                file: loc.sf,
+                range: lo..lo + text.len(),
+                text,
+            })
+        });
+        self.span_cache.borrow_mut().insert(span, info.clone());
+        info
+    }
+
+    /// Check whether `span` lies inside one of the verbatim regions given in the options.
+    /// Strategies can use this to avoid work whose result would be discarded.
+    pub fn is_verbatim(&self, span: Span) -> bool {
+        self.opts
+            .verbatim
+            .iter()
+            .any(|&region| span_util::contains(region, span))
+    }
+
+    /// Print the fresh node `node` with the printer given in the options.
+    pub fn print<T: PrintParse + ?Sized>(&self, node: &T) -> String {
+        node.print_with(&*self.opts.printer)
+    }
+
     pub fn old_nodes(&self) -> &AstMap<'s> {
         &self.old_nodes
     }
 
+    /// Get the lifetimes of the old AST, indexed by `NodeId`.  Lifetimes that were elided in the
+    /// source (as in `&T`) have no `Lifetime` node and don't appear here.
+    pub fn old_lifetimes(&self) -> &NodeTable<'s, Lifetime> {
+        &self.old_nodes.lifetimes
+    }
+
+    /// Get the structural hash of `new`, a node of the new AST.  The hash is computed the first
+    /// time it's requested and cached for the rest of the rewrite, since recovery may compare the
+    /// same new node against old ones many times.  The cache is keyed on the node's address, which
+    /// is only a hint: a hash is used to rule out equivalence, so a stale entry costs an
+    /// unnecessary rewrite, never a wrong one.
+    pub fn new_node_hash<T: AstHash + 'static>(&self, new: &T) -> u64 {
+        let key = (TypeId::of::<T>(), new as *const T as usize);
+        *self
+            .new_hashes
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| structural_hash(new))
+    }
+
     pub fn comments(&self) -> &'s CommentMap {
         &self.comment_map
     }
@@ -230,6 +830,91 @@ impl<'s> RewriteCtxt<'s> {
         mem::replace(&mut self.expr_prec, prec)
     }
 
+    /// Reset the expression precedence, as when entering a child that isn't an operand of the
+    /// current expression (such as a block, or an element of an array literal).  Exprs spliced in
+    /// there won't be parenthesized because of an enclosing operator.  Returns the old precedence,
+    /// which should be restored afterward with `replace_expr_prec`.
+    pub fn reset_expr_prec(&mut self) -> ExprPrec {
+        self.replace_expr_prec(ExprPrec::Normal(parser::PREC_RESET))
+    }
+
+    pub fn replace_defer_failures(&mut self, defer: bool) -> bool {
+        mem::replace(&mut self.defer_failures, defer)
+    }
+
+    pub fn push_deferred(&mut self, d: Deferred) {
+        self.deferred.push(d);
+    }
+
+    pub fn deferred_len(&self) -> usize {
+        self.deferred.len()
+    }
+
+    /// Remove and return the nodes deferred since `deferred_len` returned `start`.
+    pub fn take_deferred(&mut self, start: usize) -> Vec<Deferred> {
+        self.deferred.split_off(start)
+    }
+
+    pub fn locating_deferred(&self) -> bool {
+        self.locating.is_some()
+    }
+
+    pub fn replace_locating(&mut self, locating: Option<Vec<Deferred>>) -> Option<Vec<Deferred>> {
+        mem::replace(&mut self.locating, locating)
+    }
+
+    /// Limit the work spent recycling the text of each top-level item to `budget` node
+    /// comparisons, that is, calls to `Rewrite::rewrite`, or remove the limit if `budget` is
+    /// `None`.  Once an item's budget runs out, each remaining node is reprinted by the nearest
+    /// node that supports `print`, without recovering the old text of its children, so unchanged
+    /// parts may lose their formatting.  This trades fidelity for predictable latency on huge
+    /// items.  The budget starts over at each item that isn't nested inside the last one to start
+    /// it.
+    pub fn set_recycle_budget(&mut self, budget: Option<usize>) {
+        self.recycle_budget = budget;
+        self.budget_left = 0;
+        self.budget_scope = DUMMY_SP;
+    }
+
+    /// Start a new budget for the item at `span`, unless it lies inside the item whose budget is
+    /// currently being spent.  Called by the generated `Rewrite` impls.
+    pub fn enter_budget_scope(&mut self, span: Span) {
+        if self.recycle_budget.is_none() || span_util::contains(self.budget_scope, span) {
+            return;
+        }
+        self.budget_left = self.recycle_budget.unwrap();
+        self.budget_scope = span;
+    }
+
+    /// Spend one node comparison from the budget.  Returns `false` if the budget is already used
+    /// up.  Nodes outside of any item, like the crate itself, are free.  Called by the generated
+    /// `Rewrite` impls.
+    pub fn charge_recycle_budget(&mut self) -> bool {
+        if self.recycle_budget.is_none() || self.budget_scope == DUMMY_SP {
+            return true;
+        }
+        if self.budget_left == 0 {
+            return false;
+        }
+        self.budget_left -= 1;
+        true
+    }
+
+    pub fn recycle_budget_exhausted(&self) -> bool {
+        self.recycle_budget.is_some() && self.budget_scope != DUMMY_SP && self.budget_left == 0
+    }
+
+    /// Build a rewrite of `old_span` to `new_span`, where `new_span` holds the text of `new`.  The
+    /// `TextAdjust` is inferred from `new` and the current precedence context.
+    pub fn adjusted_rewrite<T: Splice>(
+        &self,
+        old_span: Span,
+        new_span: Span,
+        new: &T,
+    ) -> TextRewrite {
+        TextRewrite::adjusted(old_span, new_span, new.get_adjustment(self))
+    }
+
     fn new_to_old_id<Id: MappableId>(&self, id: Id) -> Id {
         id.map_id(self)
     }
@@ -238,6 +923,55 @@ impl<'s> RewriteCtxt<'s> {
         RewriteCtxtRef { cx: self, rw }
     }
 
+    /// Parse `src` as a `T`, reusing the result of any earlier parse of the same text.  Nested
+    /// fresh-mode regions often pretty-print and reparse the same text repeatedly, and reparsing
+    /// is expensive.
+    pub fn reparse<T: PrintParse>(&mut self, src: &str) -> Rc<T::Parsed> {
+        let key = (TypeId::of::<T::Parsed>(), src.to_owned());
+        if let Some(parsed) = self.reparse_cache.get(&key) {
+            return parsed.clone().downcast().unwrap();
+        }
+
+        let parsed = Rc::new(T::parse(self.sess, src));
+        self.reparse_cache.insert(key, parsed.clone());
+        parsed
+    }
+
+    /// Report a transition for `node` to the `on_transition` hook, if there is one.
+    pub fn transition<T: AstName + ?Sized>(
+        &self,
+        transition: Transition,
+        node: &T,
+        old_span: Span,
+        new_span: Span,
+    ) {
+        if let Some(ref hook) = self.opts.on_transition {
+            let event = TransitionEvent {
+                transition,
+                kind: node.ast_name(),
+                old_span,
+                new_span,
+            };
+            (&mut *hook.0.borrow_mut())(event);
+        }
+    }
+
+    pub fn enter_fresh(&mut self) {
+        self.fresh_depth += 1;
+    }
+
+    /// The number of fresh regions being printed, each nested inside the last.
+    pub fn fresh_depth(&self) -> usize {
+        self.fresh_depth
+    }
+
+    pub fn leave_fresh(&mut self) {
+        self.fresh_depth -= 1;
+        if self.fresh_depth == 0 {
+            self.reparse_cache.clear();
+        }
+    }
+
     pub fn text_span(&mut self, s: &str) -> Span {
         if let Some(&sp) = self.text_span_cache.get(s) {
             return sp;
@@ -280,29 +1014,187 @@ impl<'s, 'a> RewriteCtxtRef<'s, 'a> {
         RewriteCtxtRef { cx: self.cx, rw }
     }
 
-    pub fn mark(&self) -> (usize, usize) {
-        (self.rw.rewrites.len(), self.rw.nodes.len())
+    pub fn mark(&self) -> (usize, usize, usize) {
+        (self.rw.rewrites.len(), self.rw.nodes.len(), self.deferred.len())
     }
 
-    pub fn rewind(&mut self, mark: (usize, usize)) {
+    pub fn rewind(&mut self, mark: (usize, usize, usize)) {
         self.rw.rewrites.truncate(mark.0);
         self.rw.nodes.truncate(mark.1);
+        self.deferred.truncate(mark.2);
+    }
+
+    /// Run `f` speculatively.  The rewrites and node spans it records go into a temporary buffer,
+    /// which is merged into the current rewrite if `f` returns `true` and discarded otherwise.
+    /// This is a safer alternative to pairing up `mark` and `rewind` by hand, since there is no
+    /// way to forget the `rewind` on an early return.  Nodes deferred by `f` are discarded along
+    /// with its rewrites, but other state of the context, such as the expression precedence, is
+    /// not rolled back.
+    pub fn with_temp_rewrites<F>(&mut self, f: F) -> bool
+    where
+        F: FnOnce(RewriteCtxtRef<'s, '_>) -> bool,
+    {
+        let mut tmp = TextRewrite::new(self.rw.old_span, self.rw.new_span);
+        let deferred = self.deferred.len();
+        if !f(self.enter(&mut tmp)) {
+            self.deferred.truncate(deferred);
+            return false;
+        }
+        self.rw.rewrites.extend(tmp.rewrites);
+        self.rw.nodes.extend(tmp.nodes);
+        true
     }
 
     pub fn record(&mut self, rw: TextRewrite) {
+        if cfg!(debug_assertions) {
+            self.check_old_span(&rw);
+        }
         self.rw.rewrites.push(rw);
     }
 
+    /// Check that the `old_span` of a newly recorded rewrite refers to actual source text.  A
+    /// rewrite with a bogus `old_span` would otherwise go unnoticed until it produces garbled
+    /// output when the rewrites are applied.
+    fn check_old_span(&self, rw: &TextRewrite) {
+        if rw.old_span == DUMMY_SP {
+            // Rewrites of dummy content are discarded by `cleanup_rewrites`.
+            return;
+        }
+
+        // Merging the spans of nodes from different files produces a span that starts in one file
+        // and ends in another.  Its text isn't available either, but this is worth its own message,
+        // since it points at a bug in whatever built the node.
+        let cm = self.session().source_map();
+        let lo_file = cm.lookup_byte_offset(rw.old_span.lo()).sf;
+        let hi_file = cm.lookup_byte_offset(rw.old_span.hi()).sf;
+        if !Lrc::ptr_eq(&lo_file, &hi_file) {
+            panic!(
+                "recorded a rewrite with old_span {:?} crossing from {} into {}\n  new text: {}\n  inside: {}",
+                rw.old_span,
+                lo_file.name,
+                hi_file.name,
+                base::describe(self.session(), rw.new_span),
+                base::describe(self.session(), self.rw.new_span),
+            );
+        }
+
+        if self.span_info(rw.old_span).is_none() {
+            let e = self.session().source_map().span_to_snippet(rw.old_span).unwrap_err();
+            panic!(
+                "recorded a rewrite with invalid old_span {:?} ({:?})\n  new text: {}\n  inside: {}",
+                rw.old_span,
+                e,
+                base::describe(self.session(), rw.new_span),
+                base::describe(self.session(), self.rw.new_span),
+            );
+        }
+    }
+
+    /// Record a rewrite of `old_span` to `new_span`, where `new_span` holds the text of `new`,
+    /// parenthesizing the new text if the current precedence context requires it.
+    pub fn record_adjusted<T: Splice>(&mut self, old_span: Span, new_span: Span, new: &T) {
+        let rw = self.adjusted_rewrite(old_span, new_span, new);
+        self.record(rw);
+    }
+
     pub fn record_text(&mut self, old_span: Span, text: &str) {
         let new_span = self.text_span(text);
         self.record(TextRewrite::new(old_span, new_span));
     }
 
+    /// Record the removal of the text at `old_span`, such as an item, a statement, or an element
+    /// of a list, with nothing in its place.  The deleted region is widened, as described in
+    /// `files::extend_deletion`, to take along the element's separator and any whitespace or
+    /// blank line the removal would leave behind.
+    ///
+    /// A deletion is represented as a rewrite whose `new_span` is `DUMMY_SP`.  Use `record_text`
+    /// with an empty string instead to remove exactly `old_span`.
+    pub fn record_delete(&mut self, old_span: Span) {
+        let cm = self.session().source_map();
+        let span = files::extend_deletion(cm, old_span);
+        self.record(TextRewrite::new(span, DUMMY_SP));
+    }
+
+    /// Record the insertion of the text at `new_span` at the position `at`, such as a new
+    /// statement, item, or element of a list, without replacing any old text.  The new text gets
+    /// the separator and whitespace it needs to fit in with its neighbors, as described in
+    /// `files::insertion_padding`.  `adjust` applies to the new text only, not to what is added
+    /// around it.
+    ///
+    /// An insertion is represented as a rewrite whose `old_span` is empty.  Use `record` with an
+    /// empty `old_span` instead to insert exactly the text of `new_span`.
+    pub fn record_insert(&mut self, at: BytePos, new_span: Span, adjust: TextAdjust) {
+        let cm = self.session().source_map();
+        let text = cm.span_to_snippet(new_span).unwrap_or_default();
+        let (before, after) = files::insertion_padding(cm, at, &text);
+
+        let old_span = Span::new(at, at, SyntaxContext::empty());
+        if !before.is_empty() {
+            self.record_text(old_span, &before);
+        }
+        self.record(TextRewrite::adjusted(old_span, new_span, adjust));
+        if !after.is_empty() {
+            self.record_text(old_span, &after);
+        }
+    }
+
     pub fn record_node_span(&mut self, span: Span, id: NodeId) {
         self.rw.nodes.push((span, id));
     }
+
+    /// Count the bytes of new text in the rewrites recorded so far, as in
+    /// `TextRewrite::byte_stats`.  This only covers the rewrites recorded into the current
+    /// `TextRewrite`, which at the top level of a rewrite is everything recorded so far.
+    /// Rewrites that were rewound or discarded aren't counted.
+    pub fn byte_stats(&self) -> ByteStats {
+        let cm = self.session().source_map();
+        let mut stats = ByteStats::default();
+        for rw in &self.rw.rewrites {
+            stats.add(rw.byte_stats(cm));
+        }
+        stats
+    }
+}
+
+/// A position in a source file, as a line and column.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct LineCol {
+    /// One-based line number.
+    pub line: usize,
+    /// Zero-based offset within the line, in `char`s rather than bytes.
+    pub col: usize,
+}
+
+/// Get the start and end positions of `span`.  Columns count `char`s, using the source map's
+/// index of multibyte characters, so a column is the same whether the line before it contains
+/// ASCII or non-ASCII text.  The end position is exclusive, as with `span.hi()`.
+pub fn span_to_range(sess: &Session, span: Span) -> (LineCol, LineCol) {
+    let cm = sess.source_map();
+    let line_col = |pos| {
+        let loc = cm.lookup_char_pos(pos);
+        LineCol {
+            line: loc.line,
+            col: loc.col.to_usize(),
+        }
+    };
+    (line_col(span.lo()), line_col(span.hi()))
 }
 
+/// Check that `old` and `new` are the same kind of node at the root, such as two `Item`s with the
+/// same `ItemKind` variant.  Rewriting always starts by matching up the two roots, so roots of
+/// different kinds usually mean the caller passed the wrong pair of trees.
+fn check_roots<T: AstName>(old: &T, new: &T) {
+    let (old_name, new_name) = (old.ast_name(), new.ast_name());
+    assert!(
+        old_name == new_name,
+        "can't rewrite old root {} into new root {}: the roots must be the same kind of node",
+        old_name,
+        new_name,
+    );
+}
+
+/// Rewrite the source text of `old` into text for `new`.  `old` and `new` must be the same kind
+/// of node at the root; this panics if they aren't.
 pub fn rewrite<'s, T>(
     sess: &Session,
     old: &'s T,
@@ -312,14 +1204,480 @@ pub fn rewrite<'s, T>(
     map_extra_ast: impl FnOnce(&mut AstMap<'s>),
 ) -> TextRewrite
 where
-    T: Rewrite + Visit + GetSpan,
+    T: Rewrite + Visit + GetSpan + AstName,
+{
+    rewrite_with_options(
+        sess,
+        old,
+        new,
+        comment_map,
+        node_id_map,
+        map_extra_ast,
+        RewriteOptions::default(),
+    )
+}
+
+/// Like `rewrite`, but with non-default `RewriteOptions`.
+pub fn rewrite_with_options<'s, T>(
+    sess: &Session,
+    old: &'s T,
+    new: &T,
+    comment_map: &CommentMap,
+    node_id_map: HashMap<NodeId, NodeId>,
+    map_extra_ast: impl FnOnce(&mut AstMap<'s>),
+    opts: RewriteOptions,
+) -> TextRewrite
+where
+    T: Rewrite + Visit + GetSpan + AstName,
+{
+    let (rw, suppressed) =
+        rewrite_suppressing(sess, old, new, comment_map, node_id_map, map_extra_ast, opts);
+    for sp in suppressed {
+        warn!(
+            "suppressed rewrite inside verbatim region: {}",
+            base::describe(sess, sp)
+        );
+    }
+    rw
+}
+
+/// Rewrite only the subtree rooted at a single node, for interactive tools that change one item at
+/// a time and don't want to rewrite the whole crate.  `old` is the node with ID `id`, and
+/// `old_nodes` is a table of the whole old AST built ahead of time, as with `map_ast_into`, so it
+/// can be reused across calls.  Text of nodes from anywhere in the table can be recycled into the
+/// new subtree.  The rewrites have the same absolute spans in the original files as those produced
+/// by `rewrite`.
+///
+/// No `node_id_map` is needed: each `NodeId` in `new` is taken to refer to the old node with the
+/// same ID, as it does when the AST is edited in place.  The root of `new` is always matched with
+/// `id`, even if its own ID differs.
+pub fn rewrite_node<'s, T>(
+    sess: &Session,
+    old_nodes: &AstMap<'s>,
+    comment_map: &CommentMap,
+    id: NodeId,
+    old: &'s T,
+    new: &T,
+) -> Vec<TextRewrite>
+where
+    T: Rewrite + Visit + GetSpan + AstName + ListNodeIds + MaybeGetNodeId,
+{
+    check_roots(old, new);
+
+    let mut node_id_map = new
+        .list_node_ids()
+        .into_iter()
+        .map(|id| (id, id))
+        .collect::<HashMap<_, _>>();
+    if <T as MaybeGetNodeId>::supported() {
+        node_id_map.insert(new.get_node_id(), id);
+    }
+
+    let opts = RewriteOptions::default();
+    let mut rw = TextRewrite::new(DUMMY_SP, old.get_span());
+    let mut rcx = RewriteCtxt::new(
+        sess,
+        Cow::Borrowed(old_nodes),
+        comment_map,
+        node_id_map,
+        opts,
+    );
+    let ok = Rewrite::rewrite(old, new, rcx.enter(&mut rw));
+    assert!(ok, "rewriting did not complete");
+    rw.rewrites
+}
+
+/// Rewrite `old` into `new`, as in `rewrite`, and apply the result to the source text in the same
+/// call.  Returns the new text of each changed file, as computed by `files::apply_rewrites` with
+/// the default `ApplyOptions`.  Callers that need the rewrites themselves, such as to save them
+/// or apply them with other options, should call `rewrite` instead.
+pub fn rewrite_and_apply<'s, T>(
+    sess: &Session,
+    old: &'s T,
+    new: &T,
+    comment_map: &CommentMap,
+    node_id_map: HashMap<NodeId, NodeId>,
+    map_extra_ast: impl FnOnce(&mut AstMap<'s>),
+) -> HashMap<FileName, String>
+where
+    T: Rewrite + Visit + GetSpan + AstName,
 {
+    let rw = rewrite(sess, old, new, comment_map, node_id_map, map_extra_ast);
+    files::apply_rewrites(sess.source_map(), rw, &files::ApplyOptions::default())
+}
+
+/// Implementation of `rewrite_with_options`.  Also returns the `old_span`s of any rewrites that
+/// were dropped because they fell inside a verbatim region.
+pub(crate) fn rewrite_suppressing<'s, T>(
+    sess: &Session,
+    old: &'s T,
+    new: &T,
+    comment_map: &CommentMap,
+    node_id_map: HashMap<NodeId, NodeId>,
+    map_extra_ast: impl FnOnce(&mut AstMap<'s>),
+    opts: RewriteOptions,
+) -> (TextRewrite, Vec<Span>)
+where
+    T: Rewrite + Visit + GetSpan + AstName,
+{
+    check_roots(old, new);
+
     let mut map = map_ast(old);
     map_extra_ast(&mut map);
 
+    let minimize_rewrites = opts.minimize;
+    let verbatim = opts.verbatim.clone();
     let mut rw = TextRewrite::new(DUMMY_SP, old.get_span());
-    let mut rcx = RewriteCtxt::new(sess, map, comment_map, node_id_map);
+    let mut rcx = RewriteCtxt::new(sess, Cow::Owned(map), comment_map, node_id_map, opts);
+    #[cfg(feature = "rewrite_profile")]
+    profile::start();
     let ok = Rewrite::rewrite(old, new, rcx.enter(&mut rw));
+    #[cfg(feature = "rewrite_profile")]
+    {
+        let profile = profile::finish();
+        info!("{}", profile);
+    }
     assert!(ok, "rewriting did not complete");
-    rw
+    let suppressed = suppress_verbatim(&mut rw.rewrites, &verbatim);
+    if minimize_rewrites {
+        rw.rewrites = minimize(rw.rewrites, sess);
+    }
+    (rw, suppressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use syntax_pos::hygiene::Mark;
+
+    use super::*;
+    use crate::rewrite::files::{apply_rewrites, ApplyOptions};
+    use crate::rewrite::test_util::{same_ids, span_of, with_crate};
+
+    /// Build a `RewriteCtxt` for rewriting `krate`, with default options.
+    fn new_rcx<'s>(
+        sess: &'s Session,
+        krate: &'s Crate,
+        comment_map: &'s CommentMap,
+    ) -> RewriteCtxt<'s> {
+        RewriteCtxt::new(
+            sess,
+            Cow::Owned(map_ast(krate)),
+            comment_map,
+            HashMap::new(),
+            RewriteOptions::default(),
+        )
+    }
+
+    /// Apply `rw` to the crate parsed by `with_crate`, and get the new text of `test.rs`.
+    fn apply(sess: &Session, rw: TextRewrite) -> String {
+        let texts = apply_rewrites(sess.source_map(), rw, &ApplyOptions::default());
+        texts[&FileName::Real("test.rs".into())].clone()
+    }
+
+    #[test]
+    fn can_recycle() {
+        let src = "fn f() -> i32 {\n    1 + 2\n}\n\n// Not part of any item.\n\nfn g() {}\n";
+        with_crate(src, |sess, krate| {
+            let sf = sess.source_map().lookup_byte_offset(krate.span.lo()).sf;
+            let span_of = |text: &str| {
+                let lo = sf.start_pos + BytePos::from_usize(src.find(text).unwrap());
+                Span::new(lo, lo + BytePos::from_usize(text.len()), SyntaxContext::empty())
+            };
+            let comment_map = CommentMap::default();
+            let rcx = RewriteCtxt::new(
+                sess,
+                Cow::Owned(map_ast(&krate)),
+                &comment_map,
+                HashMap::new(),
+                RewriteOptions::default(),
+            );
+
+            assert!(rcx.can_recycle(span_of("1 + 2")));
+            assert!(rcx.can_recycle(span_of("fn g() {}")));
+
+            let expanded = SyntaxContext::empty().apply_mark(Mark::fresh(Mark::root()));
+            assert!(!rcx.can_recycle(span_of("1 + 2").with_ctxt(expanded)));
+
+            // The comment is real source text, but no node covers it.
+            assert!(!rcx.can_recycle(span_of("// Not part of any item.")));
+            assert!(rcx.is_source_text(span_of("// Not part of any item.")));
+        })
+    }
+
+    #[test]
+    fn recovery_compares_structural_hashes() {
+        let src = "fn f() -> i32 { 1 + 2 }";
+        with_crate(src, |sess, krate| {
+            let comment_map = CommentMap::default();
+            let rcx = RewriteCtxt::new(
+                sess,
+                Cow::Owned(map_ast(&krate)),
+                &comment_map,
+                HashMap::new(),
+                RewriteOptions::default(),
+            );
+            let lo = krate.span.lo() + BytePos::from_usize(src.find("1 + 2").unwrap());
+            let span = Span::new(lo, lo + BytePos(5), SyntaxContext::empty());
+            let exprs = &rcx.old_nodes().exprs;
+            let (id, e) = exprs.get_by_span(span).unwrap();
+
+            // The old node's hash is ready as soon as the table is built.
+            let hash = structural_hash(e);
+            assert_eq!(exprs.structural_hash(id), Some(hash));
+
+            // The new node's hash is computed once and reused.
+            assert_eq!(rcx.new_node_hash(e), hash);
+            assert_eq!(rcx.new_node_hash(e), hash);
+            assert_eq!(rcx.new_hashes.borrow().len(), 1);
+
+            // A hash mismatch rejects the node without comparing the trees, even though the nodes
+            // here are in fact equivalent.
+            assert!(exprs.equiv(id, e, hash));
+            assert!(!exprs.equiv(id, e, hash ^ 1));
+        })
+    }
+
+    #[test]
+    fn span_to_range_counts_chars() {
+        let src = "// é\nfn f() { \"ü\"; 1 }\n";
+        with_crate(src, |sess, krate| {
+            // The crate's span starts at its first item, after the comment.
+            let sf = sess.source_map().lookup_byte_offset(krate.span.lo()).sf;
+            let span_of = |text: &str| {
+                let lo = sf.start_pos + BytePos::from_usize(src.find(text).unwrap());
+                Span::new(lo, lo + BytePos::from_usize(text.len()), SyntaxContext::empty())
+            };
+            let lc = |line, col| LineCol { line, col };
+
+            assert_eq!(span_to_range(sess, span_of("é")), (lc(1, 3), lc(1, 4)));
+            assert_eq!(span_to_range(sess, span_of("ü")), (lc(2, 10), lc(2, 11)));
+            // `1` is 15 bytes but only 14 characters into its line.
+            assert_eq!(span_to_range(sess, span_of("1")), (lc(2, 14), lc(2, 15)));
+            assert_eq!(span_to_range(sess, span_of("é\nfn")), (lc(1, 3), lc(2, 2)));
+        })
+    }
+
+    #[test]
+    fn record_delete() {
+        let src = "fn f() {\n    a;\n    (1, 2, 3);\n}\n";
+        with_crate(src, |sess, krate| {
+            let comment_map = CommentMap::default();
+            let mut rcx = new_rcx(sess, &krate, &comment_map);
+            let mut rw = TextRewrite::new(DUMMY_SP, DUMMY_SP);
+            {
+                let mut rcx = rcx.enter(&mut rw);
+                // The statement's line goes with it, and the list element takes its comma.
+                rcx.record_delete(span_of(sess, src, "a;"));
+                rcx.record_delete(span_of(sess, src, "2"));
+            }
+            assert_eq!(apply(sess, rw), "fn f() {\n    (1, 3);\n}\n");
+        })
+    }
+
+    #[test]
+    fn get_by_span() {
+        let src = "fn f() -> u8 { 1 + 5 }\n";
+        with_crate(src, |sess, krate| {
+            let map = map_ast(&krate);
+            let cursor = |text: &str| span_of(sess, src, text).shrink_to_lo();
+            let expr_at = |sp: Span| map.exprs.get_by_span(sp).map(|(_, e)| e.span);
+
+            // The innermost node containing the span is found.
+            assert_eq!(expr_at(cursor("5")), Some(span_of(sess, src, "5")));
+            assert_eq!(expr_at(cursor("+")), Some(span_of(sess, src, "1 + 5")));
+            assert_eq!(expr_at(span_of(sess, src, "1 + 5")), Some(span_of(sess, src, "1 + 5")));
+
+            // No expression contains the function's name, but the function itself does.
+            assert_eq!(expr_at(cursor("f()")), None);
+            let (id, item) = map.items.get_by_span(cursor("f()")).unwrap();
+            assert_eq!(id, krate.module.items[0].id);
+            assert_eq!(item.ident.name, "f");
+        })
+    }
+
+    #[test]
+    fn recycle_budget() {
+        let src = "fn f() { fn g() {} }\nfn h() {}\n";
+        with_crate(src, |sess, krate| {
+            let comment_map = CommentMap::default();
+            let mut rcx = new_rcx(sess, &krate, &comment_map);
+            rcx.set_recycle_budget(Some(2));
+
+            // Nodes outside of any item are free.
+            assert!(rcx.charge_recycle_budget());
+            assert!(!rcx.recycle_budget_exhausted());
+
+            rcx.enter_budget_scope(span_of(sess, src, "fn f() { fn g() {} }"));
+            assert!(rcx.charge_recycle_budget());
+            assert!(rcx.charge_recycle_budget());
+            assert!(!rcx.charge_recycle_budget());
+            assert!(rcx.recycle_budget_exhausted());
+
+            // A nested item shares the budget of the item around it, while the next item starts
+            // over.
+            rcx.enter_budget_scope(span_of(sess, src, "fn g() {}"));
+            assert!(rcx.recycle_budget_exhausted());
+            rcx.enter_budget_scope(span_of(sess, src, "fn h() {}"));
+            assert!(!rcx.recycle_budget_exhausted());
+            assert!(rcx.charge_recycle_budget());
+        });
+
+        // Once the budget runs out, the item is reprinted instead of recycled.
+        let src = "fn f() -> u8 {  1  +  5  }\n";
+        let rewrite_op = |opts: RewriteOptions| {
+            with_crate(src, |sess, old| {
+                let mut new = old.clone();
+                match new.module.items[0].node {
+                    ItemKind::Fn(_, _, _, ref mut block) => match block.stmts[0].node {
+                        StmtKind::Expr(ref mut e) => match e.node {
+                            ExprKind::Binary(ref mut op, _, _) => op.node = BinOpKind::Sub,
+                            _ => panic!("expected a binary expression"),
+                        },
+                        _ => panic!("expected an expression"),
+                    },
+                    _ => panic!("expected a function"),
+                }
+                let rw = rewrite_with_options(
+                    sess,
+                    &old,
+                    &new,
+                    &CommentMap::default(),
+                    same_ids(&old),
+                    |_| {},
+                    opts,
+                );
+                apply(sess, rw)
+            })
+        };
+        assert_eq!(rewrite_op(RewriteOptions::default()), "fn f() -> u8 {  1  -  5  }\n");
+        assert_eq!(
+            rewrite_op(RewriteOptions::default().recycle_budget(1)),
+            "fn f() -> u8 { 1 - 5 }\n"
+        );
+    }
+
+    #[test]
+    fn trailing_comma() {
+        // Each call loses its last argument, so the argument lists are rewritten element by
+        // element.
+        let src = "fn f() {\n    g(1, 2, 3,);\n    g(1, 2, 3);\n    \
+                   g(\n        1,\n        2\n    );\n}\n";
+        let rewrite_calls = |policy: TrailingComma| {
+            with_crate(src, |sess, old| {
+                let mut new = old.clone();
+                match new.module.items[0].node {
+                    ItemKind::Fn(_, _, _, ref mut block) => {
+                        for stmt in &mut block.stmts {
+                            match stmt.node {
+                                StmtKind::Semi(ref mut e) => match e.node {
+                                    ExprKind::Call(_, ref mut args) => {
+                                        args.pop();
+                                    }
+                                    _ => panic!("expected a call"),
+                                },
+                                _ => panic!("expected an expression statement"),
+                            }
+                        }
+                    }
+                    _ => panic!("expected a function"),
+                }
+                let rw = rewrite_with_options(
+                    sess,
+                    &old,
+                    &new,
+                    &CommentMap::default(),
+                    same_ids(&old),
+                    |_| {},
+                    RewriteOptions::default().trailing_comma(policy),
+                );
+                apply(sess, rw)
+            })
+        };
+        assert_eq!(
+            rewrite_calls(TrailingComma::Preserve),
+            "fn f() {\n    g(1, 2,);\n    g(1, 2);\n    g(\n        1\n    );\n}\n"
+        );
+        assert_eq!(
+            rewrite_calls(TrailingComma::Always),
+            "fn f() {\n    g(1, 2,);\n    g(1, 2,);\n    g(\n        1,\n    );\n}\n"
+        );
+        assert_eq!(
+            rewrite_calls(TrailingComma::Never),
+            "fn f() {\n    g(1, 2);\n    g(1, 2);\n    g(\n        1\n    );\n}\n"
+        );
+        assert_eq!(
+            rewrite_calls(TrailingComma::Vertical),
+            "fn f() {\n    g(1, 2);\n    g(1, 2);\n    g(\n        1,\n    );\n}\n"
+        );
+    }
+
+    #[test]
+    fn provenance_markers() {
+        let src = "fn f() {\n    a();\n}\n";
+        let rewrite_fresh = |opts: RewriteOptions| {
+            with_crate(src, |sess, old| {
+                // A fresh expression inside an old statement, and a fresh item.
+                let mut new = old.clone();
+                match new.module.items[0].node {
+                    ItemKind::Fn(_, _, _, ref mut block) => match block.stmts[0].node {
+                        StmtKind::Semi(ref mut e) => *e = driver::parse_expr(sess, "b(1)"),
+                        _ => panic!("expected an expression statement"),
+                    },
+                    _ => panic!("expected a function"),
+                }
+                new.module.items.extend(driver::parse_items(sess, "fn g() {}"));
+                let rw = rewrite_with_options(
+                    sess,
+                    &old,
+                    &new,
+                    &CommentMap::default(),
+                    same_ids(&old),
+                    |_| {},
+                    opts,
+                );
+                apply(sess, rw)
+            })
+        };
+        assert!(!rewrite_fresh(RewriteOptions::default()).contains("generated"));
+
+        // Only the item is marked, on lines of its own.  The expression is left alone.
+        let opts = RewriteOptions::default().provenance_markers(ProvenanceMarkers::default());
+        assert_eq!(
+            rewrite_fresh(opts),
+            "fn f() {\n    b(1);\n}\n// BEGIN generated\nfn g() { }\n// END generated\n"
+        );
+
+        let markers = ProvenanceMarkers {
+            begin: "/* { */".to_owned(),
+            end: "/* } */".to_owned(),
+        };
+        let opts = RewriteOptions::default().provenance_markers(markers);
+        assert_eq!(
+            rewrite_fresh(opts),
+            "fn f() {\n    b(1);\n}\n/* { */\nfn g() { }\n/* } */\n"
+        );
+    }
+
+    #[test]
+    fn new_text_sorts_nested_rewrites() {
+        let src = "trait T {\n    fn a(&self) -> u8 where Self :  Sized { 1 }\n}\n";
+        with_crate(src, |sess, old| {
+            let mut new = old.clone();
+            match new.module.items[0].node {
+                ItemKind::Trait(_, _, _, _, ref mut items) => match items[0].node {
+                    TraitItemKind::Method(ref mut sig, _) => sig.header.unsafety = Unsafety::Unsafe,
+                    _ => panic!("expected a method"),
+                },
+                _ => panic!("expected a trait"),
+            }
+
+            // The where clause is recycled before the arguments, so the nested rewrites of the
+            // method are out of order.
+            let rw = rewrite(sess, &old, &new, &CommentMap::default(), same_ids(&old), |_| {});
+            assert_eq!(
+                rw.rewrites[0].new_text(sess).unwrap(),
+                "unsafe fn a(&self) -> u8 where Self :  Sized { 1 }"
+            );
+        })
+    }
 }
@@ -0,0 +1,119 @@
+//! Output of rewrites as patches in the format produced by `git diff`, for applying with
+//! `git apply`.
+use rustc::session::Session;
+use std::env;
+use std::fmt::Write;
+use std::path::Path;
+use syntax_pos::FileName;
+
+use crate::rewrite::files::{apply_rewrites, git_diff_text, ApplyOptions};
+use crate::rewrite::TextRewrite;
+
+/// Apply `rw` to the source text and build a patch describing the changes, with a `diff --git`
+/// section for each changed file.  Files are listed in order of their paths.  Paths are made
+/// relative to the current directory when they lie inside it, so the patch applies from there,
+/// which should normally be the root of the repository.  Virtual files that don't correspond to
+/// any path are skipped.
+///
+/// The patch is computed from the text rustc loaded, so a file that starts with a byte order mark
+/// gets a patch that doesn't mention it.
+pub fn rewrites_to_patch(sess: &Session, rw: TextRewrite) -> String {
+    let cm = sess.source_map();
+    let cwd = env::current_dir().ok();
+
+    let mut files = apply_rewrites(cm, rw, &ApplyOptions::default())
+        .into_iter()
+        .filter_map(|(name, new_text)| {
+            let path = match name {
+                FileName::Real(ref path) => path.clone(),
+                _ => {
+                    warn!("can't write a patch for virtual file {:?}", name);
+                    return None;
+                }
+            };
+            let sf = cm.get_source_file(&name)?;
+            let old_text = sf.src.as_ref()?.to_string();
+            if old_text == new_text {
+                return None;
+            }
+            let path = match cwd {
+                Some(ref cwd) => path.strip_prefix(cwd).unwrap_or(&path).to_owned(),
+                None => path,
+            };
+            Some((path, old_text, new_text))
+        })
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = String::new();
+    for (path, old_text, new_text) in files {
+        let a = quote_path("a/", &path);
+        let b = quote_path("b/", &path);
+        writeln!(out, "diff --git {} {}", a, b).unwrap();
+        // `git apply` doesn't need the blob IDs, so they're left as zeros.
+        writeln!(out, "index 0000000..0000000").unwrap();
+        writeln!(out, "--- {}", a).unwrap();
+        writeln!(out, "+++ {}", b).unwrap();
+        out.push_str(&git_diff_text(&old_text, &new_text));
+    }
+    out
+}
+
+/// Format `path` with the given `a/` or `b/` prefix, quoting it the way git does when it contains
+/// special characters.
+fn quote_path(prefix: &str, path: &Path) -> String {
+    let s = format!("{}{}", prefix, path.to_string_lossy().replace('\\', "/"));
+    let needs_quotes = s
+        .bytes()
+        .any(|b| b == b'"' || b == b'\\' || b < 0x20 || b >= 0x7f);
+    if !needs_quotes {
+        return s;
+    }
+
+    let mut quoted = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            0x20..=0x7e => quoted.push(b as char),
+            _ => write!(quoted, "\\{:03o}", b).unwrap(),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::source_map::{Span, DUMMY_SP};
+    use syntax_pos::{BytePos, Pos, SyntaxContext};
+
+    use crate::rewrite::test_util::with_crate;
+
+    #[test]
+    fn patch_format() {
+        let src = "fn f() -> i32 { 1 }\nfn g() -> i32 { 2 }\n";
+        with_crate(src, |sess, krate| {
+            let span_of = |text: &str| {
+                let lo = krate.span.lo() + BytePos::from_usize(src.find(text).unwrap());
+                Span::new(lo, lo + BytePos::from_usize(text.len()), SyntaxContext::empty())
+            };
+            let mut rw = TextRewrite::new(DUMMY_SP, DUMMY_SP);
+            rw.rewrites.push(TextRewrite::new(span_of("1"), span_of("2")));
+            assert_eq!(
+                rewrites_to_patch(sess, rw),
+                "diff --git a/test.rs b/test.rs\n\
+                 index 0000000..0000000\n\
+                 --- a/test.rs\n\
+                 +++ b/test.rs\n\
+                 @@ -1,2 +1,2 @@\n\
+                 -fn f() -> i32 { 1 }\n\
+                 +fn f() -> i32 { 2 }\n \
+                 fn g() -> i32 { 2 }\n"
+            );
+        })
+    }
+}
@@ -0,0 +1,158 @@
+//! Timing of the rewrite traversal by node type, for finding out which node types dominate the
+//! time spent rewriting a large file.
+//!
+//! Each generated `Rewrite` impl starts a `Timer` for its node type, covering all the strategies
+//! it tries.  For each type, the profile counts the calls and sums up the total time spent in
+//! them, as well as the self time, which leaves out the time spent in nested `Rewrite` calls for
+//! child nodes.  Self time is the better guide to which impls are slow on their own, while total
+//! time shows which parts of the tree are expensive as a whole.
+//!
+//! Profiling is enabled by the `rewrite_profile` feature.  `rewrite` profiles each traversal and
+//! logs a summary at the `info` level when it completes.  The summary of the last traversal on the
+//! current thread is also available from `last_profile`.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Timing statistics for a single node type.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct NodeStats {
+    /// The number of `Rewrite` calls on nodes of this type.
+    pub count: usize,
+    /// The time spent in those calls, including nested calls for child nodes.
+    pub total: Duration,
+    /// The time spent in those calls, not counting nested calls for child nodes.
+    pub self_time: Duration,
+}
+
+/// The timing statistics collected during one rewrite traversal.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    /// Statistics for each node type, sorted by decreasing self time.
+    pub nodes: Vec<(&'static str, NodeStats)>,
+    /// The time spent in the whole traversal.
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "rewrite profile ({} ms total):", millis(self.elapsed))?;
+        writeln!(
+            f,
+            "  {:<24} {:>10} {:>12} {:>12}",
+            "node", "calls", "self ms", "total ms"
+        )?;
+        for &(name, ref stats) in &self.nodes {
+            writeln!(
+                f,
+                "  {:<24} {:>10} {:>12.3} {:>12.3}",
+                name,
+                stats.count,
+                millis(stats.self_time),
+                millis(stats.total)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
+}
+
+/// A `Rewrite` call in progress: the node type, the start time, and the time spent so far in
+/// nested calls.
+struct Frame {
+    name: &'static str,
+    start: Instant,
+    children: Duration,
+}
+
+#[derive(Default)]
+struct Profiler {
+    /// The start of the current traversal, if one is being profiled.
+    start: Option<Instant>,
+    stack: Vec<Frame>,
+    stats: HashMap<&'static str, NodeStats>,
+    last: Option<Profile>,
+}
+
+thread_local! {
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::default());
+}
+
+/// Times a single `Rewrite` call, which ends when the timer is dropped.
+pub struct Timer {
+    active: bool,
+}
+
+/// Start timing a `Rewrite` call on a node of type `name`.  Called by the generated `Rewrite`
+/// impls.  Outside of a profiled traversal, this does nothing.
+pub fn enter(name: &'static str) -> Timer {
+    PROFILER.with(|p| {
+        let mut p = p.borrow_mut();
+        if p.start.is_none() {
+            return Timer { active: false };
+        }
+        p.stack.push(Frame {
+            name,
+            start: Instant::now(),
+            children: Duration::default(),
+        });
+        Timer { active: true }
+    })
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        PROFILER.with(|p| {
+            let mut p = p.borrow_mut();
+            let frame = match p.stack.pop() {
+                Some(x) => x,
+                None => return,
+            };
+            let elapsed = frame.start.elapsed();
+            if let Some(parent) = p.stack.last_mut() {
+                parent.children += elapsed;
+            }
+            let stats = p.stats.entry(frame.name).or_insert_with(NodeStats::default);
+            stats.count += 1;
+            stats.total += elapsed;
+            stats.self_time += elapsed.checked_sub(frame.children).unwrap_or_default();
+        });
+    }
+}
+
+/// Start profiling a traversal, discarding the statistics of any earlier one.
+pub(super) fn start() {
+    PROFILER.with(|p| {
+        let mut p = p.borrow_mut();
+        p.start = Some(Instant::now());
+        p.stack.clear();
+        p.stats.clear();
+    });
+}
+
+/// Stop profiling the current traversal and get its statistics.  The result is also saved for
+/// `last_profile`.
+pub(super) fn finish() -> Profile {
+    PROFILER.with(|p| {
+        let mut p = p.borrow_mut();
+        let elapsed = p.start.take().map_or_else(Duration::default, |s| s.elapsed());
+        p.stack.clear();
+        let mut nodes = p.stats.drain().collect::<Vec<_>>();
+        nodes.sort_by(|a, b| b.1.self_time.cmp(&a.1.self_time).then(a.0.cmp(b.0)));
+        let profile = Profile { nodes, elapsed };
+        p.last = Some(profile.clone());
+        profile
+    })
+}
+
+/// Get the statistics of the last traversal profiled on the current thread, if there was one.
+pub fn last_profile() -> Option<Profile> {
+    PROFILER.with(|p| p.borrow().last.clone())
+}
@@ -0,0 +1,354 @@
+//! Summary reports describing the effect of a rewrite, for previewing rewrites before applying
+//! them.
+//!
+//! A `RewriteReport` combines, for each file touched by a `TextRewrite`, the unified diff of the
+//! changes, any conflicts between rewrites, diagnostics about source text the rewrite fails to
+//! preserve, and statistics on how much of the new text was recycled from existing source.
+use rustc::session::Session;
+use std::collections::HashMap;
+use syntax::ast::NodeId;
+use syntax::parse::lexer::StringReader;
+use syntax::parse::token::Token;
+use syntax::source_map::{SourceMap, Span, DUMMY_SP};
+use syntax_pos::FileName;
+
+use crate::ast_manip::ast_map::AstMap;
+use crate::ast_manip::{AstName, CommentMap, GetSpan, Visit};
+use crate::rewrite::cleanup::{cleanup_rewrites, find_conflicts};
+use crate::rewrite::files::{diff_text, rewrite_file_text, rewrites_by_file, ApplyOptions};
+use crate::rewrite::{rewrite_suppressing, span_len, Rewrite, RewriteOptions, TextRewrite};
+
+/// A part of the old source text that a rewrite doesn't carry over into the new text.
+#[derive(Clone, Debug)]
+pub enum Diagnostic {
+    /// A comment inside a rewritten region doesn't appear in the replacement text.
+    LostComment { span: Span, text: String },
+    /// A macro invocation inside a rewritten region doesn't appear in the replacement text.  This
+    /// usually means the invocation was printed in expanded form.
+    LostMacro { span: Span, name: String },
+    /// A rewrite of the text at `span` was dropped because it lies in one of the verbatim regions
+    /// given in `RewriteOptions::verbatim`.
+    Suppressed { span: Span },
+}
+
+/// Byte counts describing where the text of a rewritten file comes from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Coverage {
+    /// Bytes of the old text left untouched by the rewrite.
+    pub unchanged: usize,
+    /// Bytes of replacement text recycled from the original source.
+    pub recycled: usize,
+    /// Bytes of replacement text produced by the pretty-printer.
+    pub printed: usize,
+}
+
+impl Coverage {
+    fn add(&mut self, other: Coverage) {
+        self.unchanged += other.unchanged;
+        self.recycled += other.recycled;
+        self.printed += other.printed;
+    }
+
+    /// The fraction of the new text that was taken from the original source, either unchanged
+    /// or recycled.
+    pub fn fraction_preserved(&self) -> f64 {
+        let total = self.unchanged + self.recycled + self.printed;
+        if total == 0 {
+            return 1.0;
+        }
+        (self.unchanged + self.recycled) as f64 / total as f64
+    }
+}
+
+/// The effects of a rewrite on a single file.
+#[derive(Clone, Debug)]
+pub struct FileReport {
+    pub name: FileName,
+    /// The rewritten text of the file.  This is `None` if the file has conflicting rewrites,
+    /// which can't be applied.
+    pub new_text: Option<String>,
+    /// A unified diff between the old and new text.  Empty if `new_text` is `None`.
+    pub diff: String,
+    /// Pairs of overlapping rewrites that can't both be applied, as in `find_conflicts`.
+    pub conflicts: Vec<(Span, Span)>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub coverage: Coverage,
+}
+
+/// A summary of everything a rewrite would do, obtained without modifying any files.
+#[derive(Clone, Debug)]
+pub struct RewriteReport {
+    pub rewrite: TextRewrite,
+    pub files: Vec<FileReport>,
+}
+
+impl RewriteReport {
+    /// Build a report for the result of `rewrite`.
+    pub fn new(sess: &Session, rw: TextRewrite) -> RewriteReport {
+        let cm = sess.source_map();
+        let mut files = Vec::new();
+
+        for (rewrites, _nodes, sf) in rewrites_by_file(cm, &rw) {
+            // `rewrite_files_with` skips virtual files, so they never change.
+            match sf.name {
+                FileName::Real(_) => {}
+                _ => continue,
+            }
+
+            let conflicts = find_conflicts(cm, &rewrites);
+            let mut report = FileReport {
+                name: sf.name.clone(),
+                new_text: None,
+                diff: String::new(),
+                conflicts,
+                diagnostics: Vec::new(),
+                coverage: Coverage::default(),
+            };
+
+            if report.conflicts.is_empty() {
+                let old_text = sf.src.as_ref().map_or("", |s| s);
+                let new_text =
+                    rewrite_file_text(cm, &sf, rewrites.clone(), &ApplyOptions::default());
+                report.diff = diff_text(old_text, &new_text);
+                report.new_text = Some(new_text);
+
+                let rewrites = cleanup_rewrites(cm, rewrites);
+                let mut old_len = 0;
+                for rw in &rewrites {
+                    old_len += span_len(rw.old_span);
+                    add_coverage(cm, rw, &mut report.coverage);
+                    check_fidelity(sess, rw, &mut report.diagnostics);
+                }
+                report.coverage.unchanged = (sf.end_pos.0 - sf.start_pos.0) as usize - old_len;
+            }
+
+            files.push(report);
+        }
+
+        files.sort_by(|a, b| a.name.to_string().cmp(&b.name.to_string()));
+        RewriteReport { rewrite: rw, files }
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.files.iter().any(|f| !f.conflicts.is_empty())
+    }
+
+    /// The combined coverage of all files that could be rewritten.
+    pub fn coverage(&self) -> Coverage {
+        let mut cov = Coverage::default();
+        for f in &self.files {
+            cov.add(f.coverage);
+        }
+        cov
+    }
+
+    /// Print the report in human-readable form.
+    pub fn print(&self, sess: &Session) {
+        let cm = sess.source_map();
+        for f in &self.files {
+            println!("--- {}", f.name);
+            println!("+++ {}", f.name);
+            print!("{}", f.diff);
+
+            for &(sp1, sp2) in &f.conflicts {
+                println!(
+                    "conflict: {} overlaps {}",
+                    cm.span_to_string(sp2),
+                    cm.span_to_string(sp1)
+                );
+            }
+            for d in &f.diagnostics {
+                match *d {
+                    Diagnostic::LostComment { span, ref text } => println!(
+                        "warning: {}: comment lost: {}",
+                        cm.span_to_string(span),
+                        text
+                    ),
+                    Diagnostic::LostMacro { span, ref name } => println!(
+                        "warning: {}: macro invocation lost: {}!",
+                        cm.span_to_string(span),
+                        name
+                    ),
+                    Diagnostic::Suppressed { span } => println!(
+                        "warning: {}: rewrite suppressed in verbatim region",
+                        cm.span_to_string(span)
+                    ),
+                }
+            }
+            if f.new_text.is_some() {
+                let cov = f.coverage;
+                println!(
+                    "coverage: {} bytes unchanged, {} recycled, {} printed ({:.1}% preserved)",
+                    cov.unchanged,
+                    cov.recycled,
+                    cov.printed,
+                    cov.fraction_preserved() * 100.0
+                );
+            }
+        }
+    }
+}
+
+/// Run `rewrite` and build a report of the result.  The arguments are the same as for `rewrite`.
+pub fn rewrite_report<'s, T>(
+    sess: &Session,
+    old: &'s T,
+    new: &T,
+    comment_map: &CommentMap,
+    node_id_map: HashMap<NodeId, NodeId>,
+    map_extra_ast: impl FnOnce(&mut AstMap<'s>),
+) -> RewriteReport
+where
+    T: Rewrite + Visit + GetSpan + AstName,
+{
+    rewrite_report_with_options(
+        sess,
+        old,
+        new,
+        comment_map,
+        node_id_map,
+        map_extra_ast,
+        RewriteOptions::default(),
+    )
+}
+
+/// Like `rewrite_report`, but with non-default `RewriteOptions`.  Rewrites dropped because of
+/// `RewriteOptions::verbatim` are reported as `Diagnostic::Suppressed` in the file containing
+/// them.
+///
+/// `RewriteOptions::minimize` is ignored, since it panics on the conflicting rewrites that the
+/// report is meant to show.
+pub fn rewrite_report_with_options<'s, T>(
+    sess: &Session,
+    old: &'s T,
+    new: &T,
+    comment_map: &CommentMap,
+    node_id_map: HashMap<NodeId, NodeId>,
+    map_extra_ast: impl FnOnce(&mut AstMap<'s>),
+    opts: RewriteOptions,
+) -> RewriteReport
+where
+    T: Rewrite + Visit + GetSpan + AstName,
+{
+    let opts = opts.minimize(false);
+    let (rw, suppressed) =
+        rewrite_suppressing(sess, old, new, comment_map, node_id_map, map_extra_ast, opts);
+    let mut report = RewriteReport::new(sess, rw);
+    let cm = sess.source_map();
+    for span in suppressed {
+        let sf = cm.lookup_byte_offset(span.lo()).sf;
+        let idx = match report.files.iter().position(|f| f.name == sf.name) {
+            Some(idx) => idx,
+            None => {
+                // Every rewrite in this file was suppressed, so the file is unchanged.
+                let old_text = sf.src.as_ref().map_or("", |s| s);
+                report.files.push(FileReport {
+                    name: sf.name.clone(),
+                    new_text: Some(old_text.to_owned()),
+                    diff: String::new(),
+                    conflicts: Vec::new(),
+                    diagnostics: Vec::new(),
+                    coverage: Coverage {
+                        unchanged: old_text.len(),
+                        ..Coverage::default()
+                    },
+                });
+                report.files.len() - 1
+            }
+        };
+        report.files[idx]
+            .diagnostics
+            .push(Diagnostic::Suppressed { span });
+    }
+    report
+        .files
+        .sort_by(|a, b| a.name.to_string().cmp(&b.name.to_string()));
+    report
+}
+
+/// Add the bytes of new text produced by `rw` and its children to `cov`, as counted by
+/// `TextRewrite::byte_stats`.
+fn add_coverage(cm: &SourceMap, rw: &TextRewrite, cov: &mut Coverage) {
+    let stats = rw.byte_stats(cm);
+    cov.recycled += stats.recycled;
+    cov.printed += stats.fresh;
+}
+
+/// Check that the comments and macro invocations in the old text of `rw` survive in its new text.
+fn check_fidelity(sess: &Session, rw: &TextRewrite, diags: &mut Vec<Diagnostic>) {
+    let new_text = match rw.new_text(sess) {
+        Some(x) => x,
+        None => return,
+    };
+
+    let mut lexer = StringReader::retokenize(&sess.parse_sess, rw.old_span);
+    let mut prev: Option<(Token, Span)> = None;
+    loop {
+        let tok = match lexer.try_next_token() {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        match tok.tok {
+            Token::Eof => break,
+            Token::Whitespace => continue,
+            Token::Comment | Token::DocComment(_) => {
+                let text = match sess.source_map().span_to_snippet(tok.sp) {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                if !new_text.contains(text.trim()) {
+                    diags.push(Diagnostic::LostComment { span: tok.sp, text });
+                }
+                continue;
+            }
+            Token::Not => {
+                if let Some((Token::Ident(ident, false), sp)) = prev {
+                    let name = ident.to_string();
+                    if !new_text.contains(&format!("{}!", name)) {
+                        diags.push(Diagnostic::LostMacro {
+                            span: sp.to(tok.sp),
+                            name,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+        prev = Some((tok.tok, tok.sp));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rewrite::test_util::{same_ids, with_overlapping_edits};
+
+    fn report_overlapping(opts: RewriteOptions) -> RewriteReport {
+        with_overlapping_edits(|sess, old, new, span| {
+            let report = rewrite_report_with_options(
+                sess,
+                old,
+                new,
+                &CommentMap::default(),
+                same_ids(old),
+                |_| {},
+                opts,
+            );
+            assert_eq!(report.files[0].conflicts, vec![(span, span)]);
+            report
+        })
+    }
+
+    #[test]
+    fn conflicts() {
+        let report = report_overlapping(RewriteOptions::default());
+        assert!(report.has_conflicts());
+        assert!(report.files[0].new_text.is_none());
+    }
+
+    #[test]
+    fn conflicts_with_minimize() {
+        let report = report_overlapping(RewriteOptions::default().minimize(true));
+        assert!(report.has_conflicts());
+    }
+}
@@ -0,0 +1,265 @@
+//! Debugging dumps of the old and new ASTs that drive a rewrite.
+//!
+//! When a rewrite produces surprising output, the text diff only shows the effect.  The snapshot
+//! built here shows the cause: the structure of both ASTs, side by side, with the lines that
+//! differ marked.  The dump covers the same parts of each node that `AstEquiv` compares, so spans
+//! and `NodeId`s are left out, and two ASTs have identical dumps when they are equivalent.
+use rustc_target::spec::abi::Abi;
+use std::fmt::Write;
+use std::rc::Rc;
+use syntax::ast::*;
+use syntax::parse::token::{DelimToken, Nonterminal, Token};
+use syntax::ptr::P;
+use syntax::source_map::{Span, Spanned};
+use syntax::tokenstream::{DelimSpan, TokenStream, TokenTree};
+use syntax::ThinVec;
+use syntax_pos::hygiene::SyntaxContext;
+
+use crate::ast_manip::AstEquiv;
+
+/// Marker for a line present on both sides.
+const SAME: &str = "  ";
+/// Marker for a line that differs between the two sides.
+const CHANGED: &str = "! ";
+/// Marker for a line present only in the old AST.
+const REMOVED: &str = "- ";
+/// Marker for a line present only in the new AST.
+const ADDED: &str = "+ ";
+
+/// Build a side-by-side dump of `old` and `new`, with the old AST on the left and the new one on
+/// the right.  Each row starts with a marker: `!` for rows that differ, `-` and `+` for rows
+/// present on only one side, and blank for rows the two share.  The first line says whether the
+/// ASTs are equivalent as in `AstEquiv`.
+pub fn rewrite_debug_snapshot<T: AstEquiv + AstDump + ?Sized>(old: &T, new: &T) -> String {
+    let old_text = dump(old);
+    let new_text = dump(new);
+
+    let mut rows = Vec::new();
+    // Left-only and right-only lines since the last shared line.  These are paired up into
+    // `CHANGED` rows when the run ends.
+    let mut lefts = Vec::new();
+    let mut rights = Vec::new();
+    for r in diff::lines(&old_text, &new_text) {
+        match r {
+            diff::Result::Left(l) => lefts.push(l),
+            diff::Result::Right(r) => rights.push(r),
+            diff::Result::Both(l, r) => {
+                flush_changed(&mut rows, &mut lefts, &mut rights);
+                rows.push((SAME, l, r));
+            }
+        }
+    }
+    flush_changed(&mut rows, &mut lefts, &mut rights);
+
+    let width = rows
+        .iter()
+        .map(|&(_, l, _)| l.chars().count())
+        .chain(Some("old".len()))
+        .max()
+        .unwrap();
+
+    let mut out = String::new();
+    if old.ast_equiv(new) {
+        writeln!(out, "ASTs are equivalent").unwrap();
+    } else {
+        writeln!(out, "ASTs differ").unwrap();
+    }
+    writeln!(out, "{}{:width$} | {}", SAME, "old", "new", width = width).unwrap();
+    for (marker, l, r) in rows {
+        writeln!(out, "{}{:width$} | {}", marker, l, r, width = width).unwrap();
+    }
+    out
+}
+
+/// Move the pending one-sided lines into `rows`, pairing old and new lines in order.
+fn flush_changed<'a>(
+    rows: &mut Vec<(&'static str, &'a str, &'a str)>,
+    lefts: &mut Vec<&'a str>,
+    rights: &mut Vec<&'a str>,
+) {
+    let n = lefts.len().max(rights.len());
+    for i in 0..n {
+        let row = match (lefts.get(i), rights.get(i)) {
+            (Some(&l), Some(&r)) => (CHANGED, l, r),
+            (Some(&l), None) => (REMOVED, l, ""),
+            (None, Some(&r)) => (ADDED, "", r),
+            (None, None) => unreachable!(),
+        };
+        rows.push(row);
+    }
+    lefts.clear();
+    rights.clear();
+}
+
+/// Render the structure of `x`, one node or leaf per line, indented by depth.
+pub fn dump<T: AstDump + ?Sized>(x: &T) -> String {
+    let mut d = Dumper {
+        out: String::new(),
+        depth: 0,
+        label: None,
+    };
+    x.ast_dump(&mut d);
+    d.out
+}
+
+/// Trait for writing the structure of AST nodes into a `Dumper`.
+pub trait AstDump {
+    fn ast_dump(&self, d: &mut Dumper);
+
+    /// Whether values of this type are left out of the dump entirely.  This holds for the types
+    /// `AstEquiv` ignores, such as `Span` and `NodeId`.
+    fn is_ignored(&self) -> bool {
+        false
+    }
+}
+
+/// Output buffer for `AstDump`.
+pub struct Dumper {
+    out: String,
+    depth: usize,
+    /// Field name to print at the start of the next line.
+    label: Option<&'static str>,
+}
+
+impl Dumper {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+        if let Some(label) = self.label.take() {
+            self.out.push_str(label);
+            self.out.push_str(": ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Write a node with no children.
+    pub fn leaf(&mut self, text: &str) {
+        self.line(text);
+    }
+
+    /// Write a node named `name`, with the children written by `f` indented below it.
+    pub fn node<F: FnOnce(&mut Dumper)>(&mut self, name: &str, f: F) {
+        self.line(name);
+        self.depth += 1;
+        f(self);
+        self.depth -= 1;
+    }
+
+    /// Write a child node, labeled with its field name.
+    pub fn field<T: AstDump + ?Sized>(&mut self, name: &'static str, x: &T) {
+        if x.is_ignored() {
+            return;
+        }
+        self.label = Some(name);
+        x.ast_dump(self);
+        self.label = None;
+    }
+
+    /// Write an unlabeled child node, such as an element of a sequence.
+    pub fn item<T: AstDump + ?Sized>(&mut self, x: &T) {
+        if x.is_ignored() {
+            return;
+        }
+        self.label = None;
+        x.ast_dump(self);
+    }
+}
+
+impl<'a, T: AstDump + ?Sized> AstDump for &'a T {
+    fn ast_dump(&self, d: &mut Dumper) {
+        <T as AstDump>::ast_dump(*self, d)
+    }
+
+    fn is_ignored(&self) -> bool {
+        <T as AstDump>::is_ignored(*self)
+    }
+}
+
+impl<T: AstDump> AstDump for P<T> {
+    fn ast_dump(&self, d: &mut Dumper) {
+        <T as AstDump>::ast_dump(self, d)
+    }
+
+    fn is_ignored(&self) -> bool {
+        <T as AstDump>::is_ignored(self)
+    }
+}
+
+impl<T: AstDump> AstDump for Rc<T> {
+    fn ast_dump(&self, d: &mut Dumper) {
+        <T as AstDump>::ast_dump(self, d)
+    }
+
+    fn is_ignored(&self) -> bool {
+        <T as AstDump>::is_ignored(self)
+    }
+}
+
+impl<T: AstDump> AstDump for Spanned<T> {
+    fn ast_dump(&self, d: &mut Dumper) {
+        self.node.ast_dump(d)
+    }
+
+    fn is_ignored(&self) -> bool {
+        self.node.is_ignored()
+    }
+}
+
+impl<T: AstDump> AstDump for [T] {
+    fn ast_dump(&self, d: &mut Dumper) {
+        if self.is_empty() {
+            d.leaf("[]");
+            return;
+        }
+        d.node(&format!("[{}]", self.len()), |d| {
+            for x in self {
+                d.item(x);
+            }
+        });
+    }
+}
+
+impl<T: AstDump> AstDump for Vec<T> {
+    fn ast_dump(&self, d: &mut Dumper) {
+        <[T] as AstDump>::ast_dump(self, d)
+    }
+}
+
+impl<T: AstDump> AstDump for ThinVec<T> {
+    fn ast_dump(&self, d: &mut Dumper) {
+        <[T] as AstDump>::ast_dump(self, d)
+    }
+}
+
+impl<T: AstDump> AstDump for Option<T> {
+    fn ast_dump(&self, d: &mut Dumper) {
+        match *self {
+            Some(ref x) => x.ast_dump(d),
+            None => d.leaf("None"),
+        }
+    }
+}
+
+impl<A: AstDump, B: AstDump> AstDump for (A, B) {
+    fn ast_dump(&self, d: &mut Dumper) {
+        d.node("()", |d| {
+            d.item(&self.0);
+            d.item(&self.1);
+        });
+    }
+}
+
+impl<A: AstDump, B: AstDump, C: AstDump> AstDump for (A, B, C) {
+    fn ast_dump(&self, d: &mut Dumper) {
+        d.node("()", |d| {
+            d.item(&self.0);
+            d.item(&self.1);
+            d.item(&self.2);
+        });
+    }
+}
+
+// Implementations for specific AST types are auto-generated.
+include!(concat!(env!("OUT_DIR"), "/ast_dump_gen.inc.rs"));
@@ -0,0 +1,92 @@
+//! Predicates on the relative positions of spans, shared by the rewrite applier, conflict
+//! detection, and the handling of verbatim regions.
+//!
+//! Spans are treated as half-open byte ranges: `lo..hi` covers the bytes from `lo` up to, but not
+//! including, `hi`.  An empty span (`lo == hi`) covers no bytes and stands for a position, as the
+//! `old_span` of an insertion does.  The `SyntaxContext`s of the spans are ignored.
+//!
+//! All three predicates agree at the boundaries: two spans that meet at a single position, such as
+//! `0..5` and `5..9`, are `adjacent` but don't `overlap`, and each is `contained` only in itself
+//! and in spans that cover it.
+use syntax::source_map::Span;
+
+/// Check whether `inner` lies within `outer`.  An empty `inner` at either end of `outer` counts as
+/// inside it, and every span contains itself.
+pub fn contains(outer: Span, inner: Span) -> bool {
+    outer.lo() <= inner.lo() && inner.hi() <= outer.hi()
+}
+
+/// Check whether `a` and `b` share some of their text.
+///
+/// Two non-empty spans overlap if they have at least one byte in common.  An empty span overlaps a
+/// non-empty one only if it lies strictly inside it, where an insertion would split its text; at
+/// either end, it just touches it.  Two empty spans never overlap, so several insertions at the
+/// same position don't conflict.
+pub fn overlaps(a: Span, b: Span) -> bool {
+    a.lo() < b.hi() && b.lo() < a.hi()
+}
+
+/// Check whether `b` starts exactly where `a` ends, so the two can be joined into one span without
+/// a gap or an overlap.  This is ordered: `adjacent(a, b)` doesn't imply `adjacent(b, a)`.
+pub fn adjacent(a: Span, b: Span) -> bool {
+    a.hi() == b.lo()
+}
+
+#[cfg(test)]
+mod tests {
+    use syntax::source_map::SyntaxContext;
+    use syntax_pos::BytePos;
+
+    use super::*;
+
+    fn sp(lo: u32, hi: u32) -> Span {
+        Span::new(BytePos(lo), BytePos(hi), SyntaxContext::empty())
+    }
+
+    #[test]
+    fn identical_spans() {
+        assert!(contains(sp(2, 5), sp(2, 5)));
+        assert!(overlaps(sp(2, 5), sp(2, 5)));
+        assert!(!adjacent(sp(2, 5), sp(2, 5)));
+
+        assert!(contains(sp(3, 3), sp(3, 3)));
+        assert!(!overlaps(sp(3, 3), sp(3, 3)));
+        assert!(adjacent(sp(3, 3), sp(3, 3)));
+    }
+
+    #[test]
+    fn touching_spans() {
+        assert!(!contains(sp(0, 5), sp(5, 9)));
+        assert!(!overlaps(sp(0, 5), sp(5, 9)));
+        assert!(!overlaps(sp(5, 9), sp(0, 5)));
+        assert!(adjacent(sp(0, 5), sp(5, 9)));
+        assert!(!adjacent(sp(5, 9), sp(0, 5)));
+    }
+
+    #[test]
+    fn empty_spans() {
+        // At either end of a span, an empty span is contained in it and adjacent to it, but
+        // doesn't overlap it.
+        for &pos in &[2, 5] {
+            assert!(contains(sp(2, 5), sp(pos, pos)));
+            assert!(!overlaps(sp(2, 5), sp(pos, pos)));
+            assert!(!overlaps(sp(pos, pos), sp(2, 5)));
+        }
+        assert!(adjacent(sp(2, 2), sp(2, 5)));
+        assert!(adjacent(sp(2, 5), sp(5, 5)));
+
+        // Strictly inside, it overlaps.
+        assert!(contains(sp(2, 5), sp(3, 3)));
+        assert!(overlaps(sp(2, 5), sp(3, 3)));
+        assert!(overlaps(sp(3, 3), sp(2, 5)));
+        assert!(!adjacent(sp(2, 5), sp(3, 3)));
+
+        // Outside, it's unrelated.
+        assert!(!contains(sp(2, 5), sp(7, 7)));
+        assert!(!overlaps(sp(2, 5), sp(7, 7)));
+
+        // An empty span contains only empty spans at the same position.
+        assert!(!contains(sp(3, 3), sp(2, 5)));
+        assert!(!contains(sp(3, 3), sp(4, 4)));
+    }
+}
@@ -0,0 +1,89 @@
+//! Rewriting strategy for adding or removing alternatives or the guard of a `match` arm.
+//!
+//! The patterns of an arm like `A | B | C => ...` form a list, which `recursive` can rewrite only
+//! if it keeps the same length, changing each alternative on its own.  Adding or removing an
+//! alternative makes it fail, and the whole arm is then reprinted, body included.  This strategy
+//! rewrites the patterns with `rewrite_list` instead, so the remaining alternatives keep their
+//! text, along with the `|` separators between them and any leading `|` before the first one.
+//!
+//! This AST has alternation only at the top level of an arm, so there are no or-patterns nested
+//! inside struct or tuple patterns to handle here.
+//!
+//! The `if` guard is optional, so adding or removing one also makes `recursive` fail.  Here, only
+//! the ` if cond` part after the patterns is inserted or deleted, and the patterns and body keep
+//! their text.  A guard that is kept is rewritten as usual, so editing the body or the guard
+//! condition alone never touches the other.
+use syntax::ast::*;
+use syntax::ptr::P;
+use syntax::source_map::Span;
+
+use crate::rewrite::base::{describe, is_rewritable, rewrite_list, ListSep};
+use crate::rewrite::strategy::print::RewriteAt;
+use crate::rewrite::{Rewrite, RewriteCtxtRef};
+
+fn splice_pat(new: &P<Pat>, old_span: Span, rcx: RewriteCtxtRef) -> bool {
+    new.rewrite_at(old_span, rcx)
+}
+
+/// Rewrite the guard of an arm whose last pattern has the old span `pats_end`.
+fn rewrite_guard(old: &Arm, new: &Arm, pats_end: Span, mut rcx: RewriteCtxtRef) -> bool {
+    match (&old.guard, &new.guard) {
+        (&Some(Guard::If(ref cond1)), &None) => {
+            if !is_rewritable(cond1.span) {
+                return false;
+            }
+            let sp = pats_end.between(cond1.span.shrink_to_hi());
+            info!("DELETE (GUARD) {}", describe(rcx.session(), sp));
+            rcx.record_text(sp, "");
+            true
+        }
+        (&None, &Some(Guard::If(ref cond2))) => {
+            let at = pats_end.shrink_to_hi();
+            info!("INSERT (GUARD) {}", describe(rcx.session(), at));
+            rcx.record_text(at, " if ");
+            cond2.rewrite_at(at, rcx)
+        }
+        _ => Rewrite::rewrite(&old.guard, &new.guard, rcx),
+    }
+}
+
+pub fn rewrite(old: &Arm, new: &Arm, mut rcx: RewriteCtxtRef) -> bool {
+    let pats_changed = old.pats.len() != new.pats.len();
+    let guard_changed = old.guard.is_some() != new.guard.is_some();
+    if !(pats_changed || guard_changed) || old.pats.is_empty() || new.pats.is_empty() {
+        return false;
+    }
+
+    let old_spans = old.pats.iter().map(|p| p.span).collect::<Vec<_>>();
+    if !old_spans.iter().all(|&sp| is_rewritable(sp)) {
+        return false;
+    }
+
+    let ok = Rewrite::rewrite(&old.attrs, &new.attrs, rcx.borrow())
+        && rewrite_guard(old, new, old_spans[old_spans.len() - 1], rcx.borrow())
+        && Rewrite::rewrite(&old.body, &new.body, rcx.borrow());
+    if !ok {
+        return false;
+    }
+
+    if !pats_changed {
+        return Rewrite::rewrite(&old.pats, &new.pats, rcx);
+    }
+
+    let old_keys = old.pats.iter().map(|p| p.id).collect::<Vec<_>>();
+    let new_keys = new
+        .pats
+        .iter()
+        .map(|p| rcx.new_to_old_id(p.id))
+        .collect::<Vec<_>>();
+    rewrite_list(
+        &old.pats,
+        &new.pats,
+        &old_spans,
+        &old_keys,
+        &new_keys,
+        ListSep::Pipe,
+        splice_pat,
+        rcx,
+    )
+}
@@ -0,0 +1,54 @@
+//! Rewriting strategy for adding or removing the `unsafe` keyword of a `Block`.
+//!
+//! A block's span covers its `unsafe` keyword, which has no span of its own.  When only the
+//! keyword changes, this strategy inserts or deletes it and rewrites the statements as
+//! `recursive` would, so the body of the block keeps its original text.
+use syntax::ast::*;
+use syntax::symbol::keywords;
+use syntax::util::parser;
+
+use crate::rewrite::base::{describe, is_rewritable, span_tokens};
+use crate::rewrite::{ExprPrec, Rewrite, RewriteCtxtRef};
+
+pub fn rewrite(old: &Block, new: &Block, mut rcx: RewriteCtxtRef) -> bool {
+    if old.rules == new.rules || !is_rewritable(old.span) {
+        return false;
+    }
+
+    let old_prec = rcx.replace_expr_prec(ExprPrec::Normal(parser::PREC_RESET));
+    let ok = Rewrite::rewrite(&old.stmts, &new.stmts, rcx.borrow());
+    rcx.replace_expr_prec(old_prec);
+    if !ok {
+        return false;
+    }
+
+    match (old.rules, new.rules) {
+        (BlockCheckMode::Default, BlockCheckMode::Unsafe(_)) => {
+            let at = old.span.shrink_to_lo();
+            info!("INSERT (BLOCK) {}", describe(rcx.session(), at));
+            rcx.record_text(at, "unsafe ");
+            true
+        }
+        (BlockCheckMode::Unsafe(_), BlockCheckMode::Default) => {
+            // Delete the keyword along with the space separating it from the `{`.
+            let tokens = match span_tokens(rcx.session(), old.span) {
+                Some(x) => x,
+                None => return false,
+            };
+            match (tokens.get(0), tokens.get(1)) {
+                (Some(&(ref tok, kw_span)), Some(&(_, brace_span)))
+                    if tok.is_keyword(keywords::Unsafe) =>
+                {
+                    let sp = kw_span.until(brace_span);
+                    info!("DELETE (BLOCK) {}", describe(rcx.session(), sp));
+                    rcx.record_text(sp, "");
+                    true
+                }
+                _ => false,
+            }
+        }
+        // Both are `unsafe`, differing only in the `UnsafeSource`, which doesn't appear in the
+        // text.
+        (_, _) => true,
+    }
+}
@@ -0,0 +1,46 @@
+//! Rewriting strategy that leaves alone items disabled by `#[cfg]`.
+//!
+//! When `RewriteOptions::active_cfg` is set, any item whose `#[cfg]` attributes don't match the
+//! active configuration is treated as rewritten successfully without recording any rewrites, so
+//! its old text is kept byte-for-byte.  This is tried before all other strategies.
+use syntax::ast::*;
+use syntax::attr::{self, HasAttrs};
+
+use crate::rewrite::RewriteCtxtRef;
+
+/// Check whether `attrs` enable their node under the configuration `cfg`.  Attributes that aren't
+/// well-formed `#[cfg(...)]` attributes are ignored.
+fn is_active(attrs: &[Attribute], cfg: &CrateConfig, rcx: &RewriteCtxtRef) -> bool {
+    for a in attrs {
+        if !a.check_name("cfg") {
+            continue;
+        }
+        let meta = match a.meta() {
+            Some(x) => x,
+            None => continue,
+        };
+        let pred = match meta.meta_item_list() {
+            Some(l) if l.len() == 1 => match l[0].meta_item() {
+                Some(mi) => mi,
+                None => continue,
+            },
+            _ => continue,
+        };
+        let sess = &rcx.session().parse_sess;
+        let matches = attr::eval_condition(pred, sess, &mut |mi| match mi.ident() {
+            Some(ident) => cfg.contains(&(ident.name, mi.value_str())),
+            None => false,
+        });
+        if !matches {
+            return false;
+        }
+    }
+    true
+}
+
+pub fn rewrite<T: HasAttrs>(old: &T, _new: &T, rcx: RewriteCtxtRef) -> bool {
+    match rcx.options().active_cfg {
+        Some(ref cfg) => !is_active(old.attrs(), cfg, &rcx),
+        None => false,
+    }
+}
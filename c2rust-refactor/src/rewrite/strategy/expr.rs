@@ -0,0 +1,1382 @@
+//! Rewriting strategy for changes to the tokens of an `Expr` that aren't covered by any child node.
+//!
+//! Some parts of an expression, such as the `..` or `..=` of a range, have no span of their own in
+//! the AST.  When one of these parts changes, the `recursive` strategy fails and `print` reprints
+//! the whole expression, discarding the original formatting of its children (and any comments
+//! between them).
+//!
+//! This strategy lexes the old expression's source text to find the tokens in question, rewrites
+//! the children as `recursive` would, and splices in new text for only the tokens that changed.
+//! Children that were added or removed are inserted or deleted next to the tokens they attach to.
+//!
+//! Loop labels (`'a: loop { ... }`, `break 'a`) are handled the same way, so changing a label
+//! doesn't reprint the loop body, and changing the body or a `break` value leaves the label alone.
+//! Likewise, the operand of a `break`, `return`, or `yield` can be added or removed without
+//! reprinting the keyword.
+//!
+//! It also handles array and tuple literals whose element lists differ in length, which
+//! `recursive` can't match up.  Elements are matched by `NodeId` and rewritten individually, with
+//! commas inserted or deleted around any elements that were added or removed.  The arguments of a
+//! function call are handled the same way, separately from the callee.
+//!
+//! Adding or removing the `else` branch of an `if` or `if let` inserts or deletes just the branch,
+//! keeping the condition and the `then` block.
+//!
+//! The functional update base of a struct literal (`..base`) is handled the same way, so adding,
+//! removing, or changing the base doesn't reprint the fields.
+//!
+//! In field access and indexing expressions (`obj.f`, `t.0`, `arr[i]`), the base and the field or
+//! index are rewritten separately, so changing one doesn't reprint the other.  Method calls
+//! count as accessors too, so a link of a mixed chain like `a.b().c` can change between a field
+//! access and a method call without reprinting its base.
+//!
+//! The arms of a `match` are handled similarly, so editing, adding, or removing an arm doesn't
+//! reprint the scrutinee or the other arms.
+//!
+//! Changing the operator of a binary expression replaces only the operator token, adding
+//! parentheses around operands that need them under the new operator's precedence.
+//!
+//! Assignments are handled the same way: changing the operator of `x += y` (or turning it into a
+//! plain `x = y`) replaces only the operator token, keeping the text of both operands.
+//!
+//! Wrapping an expression in an `unsafe` block (or unwrapping one) recycles the expression's text
+//! and only adds or removes the block around it.  Adding, removing, or changing the `async`,
+//! `async move`, or `try` qualifier of a block expression replaces only the qualifier, and the
+//! block's statements are rewritten individually.
+//!
+//! The `static`, `async`, and `move` qualifiers of a closure are a separate region in front of its
+//! parameters.  Adding or removing any of them (including the combined `static move`) replaces
+//! only the qualifiers, and the parameters and body are rewritten as usual.
+//!
+//! Finally, wrapping an expression in a `?`, `as T`, or `: T` operator (or removing one) recycles
+//! the text of the operand, inserting or deleting just the operator and adding parentheses where
+//! precedence requires.
+use syntax::ast::*;
+use syntax::parse::token::{BinOpToken, DelimToken, Token};
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::source_map::{Span, DUMMY_SP};
+use syntax::ThinVec;
+use syntax_pos::{BytePos, Pos};
+use syntax::util::parser;
+
+use crate::ast_manip::{AstEquiv, GetNodeId, GetSpan};
+use crate::rewrite::base::{
+    binop_left_prec, binop_right_prec, describe, is_rewritable, rewrite_list, span_tokens, ListSep,
+};
+use crate::rewrite::strategy::print::RewriteAt;
+use crate::rewrite::{ExprPrec, Rewrite, RewriteCtxtRef, TextAdjust};
+
+/// Rewrite `old` into `new` with the expression precedence set to `prec`, as `recursive` does for
+/// fields marked with `#[prec]`.
+fn rewrite_with_prec<T: Rewrite>(
+    old: &T,
+    new: &T,
+    prec: ExprPrec,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let old_prec = rcx.replace_expr_prec(prec);
+    let ok = Rewrite::rewrite(old, new, rcx.borrow());
+    rcx.replace_expr_prec(old_prec);
+    ok
+}
+
+fn reset_prec() -> ExprPrec {
+    ExprPrec::Normal(parser::PREC_RESET)
+}
+
+fn cond_prec() -> ExprPrec {
+    ExprPrec::Cond(parser::PREC_RESET)
+}
+
+/// Insert `new` at the empty span `at`, with the expression precedence set to `prec`.
+fn insert_with_prec(new: &P<Expr>, at: Span, prec: ExprPrec, mut rcx: RewriteCtxtRef) -> bool {
+    info!("INSERT (EXPR) {}", describe(rcx.session(), at));
+    let old_prec = rcx.replace_expr_prec(prec);
+    let ok = new.rewrite_at(at, rcx.borrow());
+    rcx.replace_expr_prec(old_prec);
+    ok
+}
+
+fn record_delete(old_span: Span, mut rcx: RewriteCtxtRef) {
+    info!("DELETE (EXPR) {}", describe(rcx.session(), old_span));
+    rcx.record_text(old_span, "");
+}
+
+fn record_text_insert(at: Span, text: &str, mut rcx: RewriteCtxtRef) {
+    info!("INSERT (EXPR) {}", describe(rcx.session(), at));
+    info!("  TEXT (EXPR) {:?}", text);
+    rcx.record_text(at, text);
+}
+
+fn record_token_rewrite(old_span: Span, text: &str, mut rcx: RewriteCtxtRef) {
+    info!("REWRITE (EXPR) {}", describe(rcx.session(), old_span));
+    info!("   INTO (EXPR) {}", text);
+    rcx.record_text(old_span, text);
+}
+
+fn range_limits_text(limits: RangeLimits) -> &'static str {
+    match limits {
+        RangeLimits::HalfOpen => "..",
+        RangeLimits::Closed => "..=",
+    }
+}
+
+/// Find the span of the `..`/`..=` token of the range expression `old`.
+fn find_range_limits_span(old: &Expr, lo: &Option<P<Expr>>, rcx: &RewriteCtxtRef) -> Option<Span> {
+    let start = lo.as_ref().map_or(old.span.lo(), |lo| lo.span.hi());
+    let tokens = span_tokens(rcx.session(), old.span)?;
+    tokens
+        .into_iter()
+        .filter(|&(_, sp)| sp.lo() >= start)
+        .find(|&(ref tok, _)| match tok {
+            Token::DotDot | Token::DotDotDot | Token::DotDotEq => true,
+            _ => false,
+        })
+        .map(|(_, sp)| sp)
+}
+
+fn rewrite_range(
+    old: &Expr,
+    (lo1, hi1, limits1): (&Option<P<Expr>>, &Option<P<Expr>>, RangeLimits),
+    (lo2, hi2, limits2): (&Option<P<Expr>>, &Option<P<Expr>>, RangeLimits),
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let limits_span = match find_range_limits_span(old, lo1, &rcx) {
+        Some(sp) => sp,
+        None => return false,
+    };
+    let prec = ExprPrec::Normal(parser::AssocOp::LOr.precedence() as i8);
+
+    let ok = match (lo1, lo2) {
+        (Some(lo1), Some(lo2)) => rewrite_with_prec(lo1, lo2, prec, rcx.borrow()),
+        (None, None) => true,
+        (Some(_), None) => {
+            // Delete everything up to the `..`, which covers any parens around `lo1`.
+            record_delete(old.span.until(limits_span), rcx.borrow());
+            true
+        }
+        (None, Some(lo2)) => insert_with_prec(lo2, limits_span.shrink_to_lo(), prec, rcx.borrow()),
+    };
+    if !ok {
+        return false;
+    }
+
+    if limits1 != limits2 {
+        record_token_rewrite(limits_span, range_limits_text(limits2), rcx.borrow());
+    }
+
+    match (hi1, hi2) {
+        (Some(hi1), Some(hi2)) => rewrite_with_prec(hi1, hi2, prec, rcx.borrow()),
+        (None, None) => true,
+        (Some(_), None) => {
+            record_delete(limits_span.between(old.span.shrink_to_hi()), rcx.borrow());
+            true
+        }
+        (None, Some(hi2)) => insert_with_prec(hi2, limits_span.shrink_to_hi(), prec, rcx.borrow()),
+    }
+}
+
+fn label_text(label: &Label) -> String {
+    // The ident of a label includes the leading `'`.
+    label.ident.as_str().to_string()
+}
+
+/// Rewrite the label of the loop or block expression `old`, which appears before the expression
+/// as `'a:`.
+fn rewrite_loop_label(
+    old: &Expr,
+    label1: &Option<Label>,
+    label2: &Option<Label>,
+    rcx: RewriteCtxtRef,
+) -> bool {
+    match (label1, label2) {
+        (None, None) => true,
+        (Some(l1), Some(l2)) => {
+            if l1.ident.name != l2.ident.name {
+                record_token_rewrite(l1.ident.span, &label_text(l2), rcx);
+            }
+            true
+        }
+        (Some(l1), None) => {
+            // Delete the label, the `:`, and everything up to the loop keyword.
+            let tokens = match span_tokens(rcx.session(), old.span) {
+                Some(x) => x,
+                None => return false,
+            };
+            let mut after_label = tokens
+                .iter()
+                .filter(|&&(_, sp)| sp.lo() >= l1.ident.span.hi());
+            match (after_label.next(), after_label.next()) {
+                (Some(&(Token::Colon, _)), Some(&(_, keyword_span))) => {
+                    record_delete(l1.ident.span.until(keyword_span), rcx);
+                    true
+                }
+                _ => false,
+            }
+        }
+        (None, Some(l2)) => {
+            let text = format!("{}: ", label_text(l2));
+            record_text_insert(old.span.shrink_to_lo(), &text, rcx);
+            true
+        }
+    }
+}
+
+/// Rewrite the label of the `break` or `continue` expression `old`, which appears after the
+/// keyword.
+fn rewrite_jump_label(
+    old: &Expr,
+    label1: &Option<Label>,
+    label2: &Option<Label>,
+    rcx: RewriteCtxtRef,
+) -> bool {
+    match (label1, label2) {
+        (None, None) => true,
+        (Some(l1), Some(l2)) => {
+            if l1.ident.name != l2.ident.name {
+                record_token_rewrite(l1.ident.span, &label_text(l2), rcx);
+            }
+            true
+        }
+        (Some(l1), None) => {
+            // Delete the label along with the space separating it from the keyword.
+            let keyword_span = jump_keyword_span(old);
+            record_delete(keyword_span.between(l1.ident.span).to(l1.ident.span), rcx);
+            true
+        }
+        (None, Some(l2)) => {
+            let at = jump_keyword_span(old).shrink_to_hi();
+            record_text_insert(at, &format!(" {}", label_text(l2)), rcx);
+            true
+        }
+    }
+}
+
+/// Get the span of the `break`, `continue`, `return`, or `yield` keyword of `old`.
+fn jump_keyword_span(old: &Expr) -> Span {
+    let keyword = match old.node {
+        ExprKind::Continue(..) => "continue",
+        ExprKind::Ret(..) => "return",
+        ExprKind::Yield(..) => "yield",
+        _ => "break",
+    };
+    let lo = old.span.lo();
+    old.span.with_hi(lo + BytePos::from_usize(keyword.len()))
+}
+
+fn rewrite_break(
+    old: &Expr,
+    (label1, value1): (&Option<Label>, &Option<P<Expr>>),
+    (label2, value2): (&Option<Label>, &Option<P<Expr>>),
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    if !rewrite_jump_label(old, label1, label2, rcx.borrow()) {
+        return false;
+    }
+
+    let before = match label1 {
+        Some(l1) => l1.ident.span,
+        None => jump_keyword_span(old),
+    };
+    rewrite_jump_value(old, before, value1, value2, rcx)
+}
+
+/// Rewrite the operand of the `break`, `return`, or `yield` expression `old`, which follows the
+/// span `before` (the keyword, or the label of a `break`).  An operand that was added or removed
+/// is inserted or deleted after `before`, so the keyword is kept either way.
+fn rewrite_jump_value(
+    old: &Expr,
+    before: Span,
+    value1: &Option<P<Expr>>,
+    value2: &Option<P<Expr>>,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let prec = ExprPrec::Normal(parser::PREC_JUMP);
+    match (value1, value2) {
+        (Some(v1), Some(v2)) => rewrite_with_prec(v1, v2, prec, rcx),
+        (None, None) => true,
+        (Some(_), None) => {
+            // Delete everything following `before`, which covers any parens around the value.
+            record_delete(before.between(old.span.shrink_to_hi()), rcx);
+            true
+        }
+        (None, Some(v2)) => {
+            let at = old.span.shrink_to_hi();
+            record_text_insert(at, " ", rcx.borrow());
+            insert_with_prec(v2, at, prec, rcx)
+        }
+    }
+}
+
+/// Extend `sp` to cover any parentheses around it.  `tokens` should be the tokens of an enclosing
+/// expression.  Parens are represented only implicitly in the AST, so the span of `(x)` covers
+/// only the `x`.
+fn extend_span_parens(tokens: &[(Token, Span)], mut sp: Span) -> Span {
+    loop {
+        let before = tokens.iter().rev().find(|&&(_, tok_sp)| tok_sp.hi() <= sp.lo());
+        let after = tokens.iter().find(|&&(_, tok_sp)| tok_sp.lo() >= sp.hi());
+        match (before, after) {
+            (
+                Some(&(Token::OpenDelim(DelimToken::Paren), lo)),
+                Some(&(Token::CloseDelim(DelimToken::Paren), hi)),
+            ) => sp = lo.to(hi),
+            _ => return sp,
+        }
+    }
+}
+
+fn splice_elem(new: &P<Expr>, old_span: Span, rcx: RewriteCtxtRef) -> bool {
+    new.rewrite_at(old_span, rcx)
+}
+
+/// Rewrite the elements of an array or tuple literal.  This is only needed when the number of
+/// elements changes, since `recursive` handles the rest.
+fn rewrite_elems(
+    old: &Expr,
+    elems1: &[P<Expr>],
+    elems2: &[P<Expr>],
+    rcx: RewriteCtxtRef,
+) -> bool {
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+    // The span of the closing `]` or `)`.
+    let close_span = match tokens.last() {
+        Some(&(Token::CloseDelim(_), sp)) => sp,
+        _ => return false,
+    };
+    rewrite_expr_list(&tokens[1..tokens.len() - 1], close_span, elems1, elems2, rcx)
+}
+
+/// Rewrite the comma-separated list of expressions `elems1` into `elems2`.  `tokens` should be
+/// the tokens between the list's delimiters, and `close_span` the span of its closing delimiter.
+/// Elements are matched up by `NodeId`, so elements that were added or removed are inserted or
+/// deleted along with their commas, keeping the text (and the line breaks) of the rest.
+fn rewrite_expr_list(
+    tokens: &[(Token, Span)],
+    close_span: Span,
+    elems1: &[P<Expr>],
+    elems2: &[P<Expr>],
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    if elems1.is_empty() {
+        let at = close_span.shrink_to_lo();
+        for (i, e) in elems2.iter().enumerate() {
+            if i > 0 {
+                rcx.record_text(at, ", ");
+            }
+            if !insert_with_prec(e, at, reset_prec(), rcx.borrow()) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    let old_spans = elems1
+        .iter()
+        .map(|e| extend_span_parens(tokens, e.span))
+        .collect::<Vec<_>>();
+    if !old_spans.iter().all(|&sp| is_rewritable(sp)) {
+        return false;
+    }
+
+    if elems2.is_empty() {
+        // Also delete any trailing comma.
+        record_delete(old_spans[0].until(close_span), rcx);
+        return true;
+    }
+
+    let old_keys = elems1.iter().map(|e| e.id).collect::<Vec<_>>();
+    let new_keys = elems2
+        .iter()
+        .map(|e| rcx.new_to_old_id(e.id))
+        .collect::<Vec<_>>();
+    let old_prec = rcx.reset_expr_prec();
+    let ok = rewrite_list(
+        elems1,
+        elems2,
+        &old_spans,
+        &old_keys,
+        &new_keys,
+        ListSep::Comma,
+        splice_elem,
+        rcx.borrow(),
+    );
+    rcx.replace_expr_prec(old_prec);
+    ok
+}
+
+/// Rewrite the function call `old`.  The callee and each argument are rewritten separately, and
+/// arguments that were added or removed are spliced in or out of the argument list, so changing
+/// the arguments doesn't reprint the callee, and a multi-line argument list keeps its layout.
+fn rewrite_call(
+    old: &Expr,
+    (func1, args1): (&P<Expr>, &[P<Expr>]),
+    (func2, args2): (&P<Expr>, &[P<Expr>]),
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+    // The argument list starts at the first `(` after the callee, including any parens around the
+    // callee itself, as in `(x.f)(y)`.
+    let func_span = extend_span_parens(&tokens, func1.span);
+    let open_idx = match tokens.iter().position(|&(ref tok, sp)| {
+        *tok == Token::OpenDelim(DelimToken::Paren) && sp.lo() >= func_span.hi()
+    }) {
+        Some(x) => x,
+        None => return false,
+    };
+    let close_span = match tokens.last() {
+        Some(&(Token::CloseDelim(DelimToken::Paren), sp)) if tokens.len() > open_idx + 1 => sp,
+        _ => return false,
+    };
+
+    let callee_prec = ExprPrec::Callee(parser::PREC_POSTFIX);
+    rewrite_with_prec(func1, func2, callee_prec, rcx.borrow())
+        && rewrite_expr_list(
+            &tokens[open_idx + 1..tokens.len() - 1],
+            close_span,
+            args1,
+            args2,
+            rcx,
+        )
+}
+
+/// Get the span of the match arm `arm`, not including its trailing comma.  `tokens` should be the
+/// tokens of the enclosing `match`.
+fn arm_span(tokens: &[(Token, Span)], arm: &Arm) -> Span {
+    let lo = match arm.attrs.first() {
+        Some(attr) => attr.span,
+        None => arm.pats[0].span,
+    };
+    lo.to(extend_span_parens(tokens, arm.body.span))
+}
+
+fn splice_arm(new: &Arm, old_span: Span, mut rcx: RewriteCtxtRef) -> bool {
+    // The pretty-printer can't print an arm on its own, so print a `match` containing only this
+    // arm and extract the arm's text.
+    let e = Expr {
+        id: DUMMY_NODE_ID,
+        node: ExprKind::Match(
+            P(Expr {
+                id: DUMMY_NODE_ID,
+                node: ExprKind::Tup(Vec::new()),
+                span: DUMMY_SP,
+                attrs: ThinVec::new(),
+            }),
+            vec![new.clone()],
+        ),
+        span: DUMMY_SP,
+        attrs: ThinVec::new(),
+    };
+    let printed = pprust::expr_to_string(&e);
+    let printed = match (printed.find('{'), printed.rfind('}')) {
+        (Some(lo), Some(hi)) => &printed[lo + 1..hi],
+        _ => return false,
+    };
+    // The arms are handled as a comma-separated list, so leave off the printed arm's comma.
+    let text = printed.trim().trim_end_matches(',');
+    info!("REWRITE (EXPR) {}", describe(rcx.session(), old_span));
+    info!("   INTO (EXPR) {}", text);
+    rcx.record_text(old_span, text);
+    true
+}
+
+/// Rewrite the arms of a `match`.  Arms are matched up by the `NodeId`s of their bodies, and each
+/// is rewritten separately, so changing, adding, or removing one arm leaves the others (and the
+/// blank lines between them) alone.
+fn rewrite_arms(old: &Expr, arms1: &[Arm], arms2: &[Arm], mut rcx: RewriteCtxtRef) -> bool {
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+    let close_span = match tokens.last() {
+        Some(&(Token::CloseDelim(DelimToken::Brace), sp)) => sp,
+        _ => return false,
+    };
+
+    if arms1.is_empty() {
+        let at = close_span.shrink_to_lo();
+        for (i, arm) in arms2.iter().enumerate() {
+            if i > 0 {
+                record_text_insert(at, ", ", rcx.borrow());
+            }
+            if !splice_arm(arm, at, rcx.borrow()) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    let old_spans = arms1
+        .iter()
+        .map(|arm| arm_span(&tokens, arm))
+        .collect::<Vec<_>>();
+    if !old_spans.iter().all(|&sp| is_rewritable(sp)) {
+        return false;
+    }
+
+    if arms2.is_empty() {
+        record_delete(old_spans[0].until(close_span), rcx);
+        return true;
+    }
+
+    let old_keys = arms1.iter().map(|arm| arm.body.id).collect::<Vec<_>>();
+    let new_keys = arms2
+        .iter()
+        .map(|arm| rcx.new_to_old_id(arm.body.id))
+        .collect::<Vec<_>>();
+    let old_prec = rcx.reset_expr_prec();
+    let ok = rewrite_list(
+        arms1,
+        arms2,
+        &old_spans,
+        &old_keys,
+        &new_keys,
+        ListSep::Comma,
+        splice_arm,
+        rcx.borrow(),
+    );
+    rcx.replace_expr_prec(old_prec);
+    ok
+}
+
+/// Rewrite the functional update base (`..base`) of the struct literal `old`, whose last field is
+/// `last_field`.  A base that was added or removed is inserted or deleted along with its `..`,
+/// leaving the path and the fields alone.
+fn rewrite_struct_base(
+    old: &Expr,
+    last_field: Option<&Field>,
+    base1: &Option<P<Expr>>,
+    base2: &Option<P<Expr>>,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+    let (open_span, close_span) = match (
+        tokens
+            .iter()
+            .find(|&&(ref tok, _)| *tok == Token::OpenDelim(DelimToken::Brace)),
+        tokens.last(),
+    ) {
+        (Some(&(_, open)), Some(&(Token::CloseDelim(DelimToken::Brace), close))) => (open, close),
+        _ => return false,
+    };
+    // The comma following the last field, if there is one.
+    let comma_span = last_field.and_then(|f| {
+        match tokens.iter().find(|&&(_, sp)| sp.lo() >= f.span.hi()) {
+            Some(&(Token::Comma, sp)) => Some(sp),
+            _ => None,
+        }
+    });
+
+    match (base1, base2) {
+        (&Some(ref b1), &Some(ref b2)) => rewrite_with_prec(b1, b2, reset_prec(), rcx),
+        (&None, &None) => true,
+        (&Some(ref b1), &None) => {
+            let base_span = extend_span_parens(&tokens, b1.span);
+            let start = match (last_field, comma_span) {
+                (Some(f), Some(comma)) => {
+                    // When the `..` is on a line of its own, the comma stays behind as a trailing
+                    // comma.  Otherwise it goes along with the base.
+                    let gap = rcx.span_info(comma.between(base_span));
+                    match gap {
+                        Some(ref info) if info.text.contains('\n') => comma.shrink_to_hi(),
+                        _ => f.span.shrink_to_hi(),
+                    }
+                }
+                _ => open_span.shrink_to_hi(),
+            };
+            if !is_rewritable(start) {
+                return false;
+            }
+            record_delete(start.to(base_span), rcx);
+            true
+        }
+        (&None, &Some(ref b2)) => {
+            let (at, before, after) = match (last_field, comma_span) {
+                (Some(_), Some(comma)) => (comma.shrink_to_hi(), " ..", ""),
+                (Some(f), None) => (f.span.shrink_to_hi(), ", ..", ""),
+                (None, _) if open_span.hi() == close_span.lo() => {
+                    (close_span.shrink_to_lo(), " ..", " ")
+                }
+                (None, _) => (close_span.shrink_to_lo(), "..", " "),
+            };
+            if !is_rewritable(at) {
+                return false;
+            }
+            record_text_insert(at, before, rcx.borrow());
+            if !insert_with_prec(b2, at, reset_prec(), rcx.borrow()) {
+                return false;
+            }
+            if !after.is_empty() {
+                record_text_insert(at, after, rcx);
+            }
+            true
+        }
+    }
+}
+
+/// The accessor following the base of a field access, indexing, or method call expression.
+enum Accessor<'a> {
+    Field(Ident),
+    Index(&'a P<Expr>),
+    /// A method call, with its arguments other than the receiver.
+    Method(&'a PathSegment, &'a [P<Expr>]),
+}
+
+/// Rewrite the field access, indexing, or method call expression `old` into `new`.  The base
+/// (`obj` in `obj.f`, `obj[i]`, or `obj.f()`) is rewritten in place, and only the accessor
+/// following it is replaced when it changed, so changing the field name or index doesn't reprint
+/// the base, and vice versa.  In a chain like `a.b().c`, each link is its own accessor, so
+/// changing one link, even from a method call into a field access, leaves the others alone.
+fn rewrite_access(
+    old: &Expr,
+    (base1, acc1): (&P<Expr>, Accessor),
+    (base2, acc2): (&P<Expr>, Accessor),
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    match (&acc1, &acc2) {
+        // A change to the arguments of a method call, which `recursive` can't handle, is left to
+        // `print`.
+        (&Accessor::Method(..), &Accessor::Method(..)) => return false,
+        // A method call turning into a field access, or back, is the same link of a chain only if
+        // the base stayed the same.  Otherwise, a link was added or removed, and the new base
+        // matches some other link of the old chain, which `print` can recover.
+        (&Accessor::Method(..), _) | (_, &Accessor::Method(..)) => {
+            if !same_expr(base1, base2, &rcx) {
+                return false;
+            }
+        }
+        _ => {}
+    }
+
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+    // The accessor runs from its first token, following the base and any parens around it, to
+    // the end of the expression.  Comments and line breaks before the accessor are kept.
+    let base_end = extend_span_parens(&tokens, base1.span).hi();
+    let suffix_span = match tokens.iter().find(|&&(_, sp)| sp.lo() >= base_end) {
+        Some(&(_, sp)) => old.span.with_lo(sp.lo()),
+        None => return false,
+    };
+    if !is_rewritable(suffix_span) {
+        return false;
+    }
+
+    let prec = ExprPrec::Normal(parser::PREC_POSTFIX);
+    let old_prec = rcx.reset_expr_prec();
+    let ok = rewrite_operand(&tokens, base1, base2, prec, rcx.borrow());
+    rcx.replace_expr_prec(old_prec);
+    if !ok {
+        return false;
+    }
+
+    match (acc1, acc2) {
+        (Accessor::Field(ident1), Accessor::Field(ident2)) => {
+            if ident1.name == ident2.name {
+                return true;
+            }
+            // The span of a field ident doesn't always cover its text: in `t.0.1`, both fields
+            // come from the single literal token `0.1`.
+            match rcx.span_info(ident1.span) {
+                Some(ref info) if is_rewritable(ident1.span) && info.text == *ident1.as_str() => {}
+                _ => return false,
+            }
+            record_token_rewrite(ident1.span, &ident2.to_string(), rcx);
+            true
+        }
+        (Accessor::Index(idx1), Accessor::Index(idx2)) => {
+            rewrite_with_prec(idx1, idx2, reset_prec(), rcx)
+        }
+        (Accessor::Method(..), Accessor::Method(..)) => unreachable!(),
+        (_, Accessor::Field(ident2)) => {
+            record_token_rewrite(suffix_span, &format!(".{}", ident2), rcx);
+            true
+        }
+        (_, Accessor::Index(idx2)) => {
+            let at = old.span.shrink_to_hi();
+            record_token_rewrite(suffix_span, "[", rcx.borrow());
+            if !insert_with_prec(idx2, at, reset_prec(), rcx.borrow()) {
+                return false;
+            }
+            record_text_insert(at, "]", rcx);
+            true
+        }
+        (_, Accessor::Method(seg2, args2)) => {
+            if seg2.args.is_some() {
+                return false;
+            }
+            let at = old.span.shrink_to_hi();
+            record_token_rewrite(suffix_span, &format!(".{}(", seg2.ident), rcx.borrow());
+            for (i, arg) in args2.iter().enumerate() {
+                if i > 0 {
+                    record_text_insert(at, ", ", rcx.borrow());
+                }
+                if !insert_with_prec(arg, at, reset_prec(), rcx.borrow()) {
+                    return false;
+                }
+            }
+            record_text_insert(at, ")", rcx);
+            true
+        }
+    }
+}
+
+/// Get the base and accessor of `e`, if it's a field access, indexing, or method call expression.
+fn access_parts(e: &Expr) -> Option<(&P<Expr>, Accessor)> {
+    match e.node {
+        ExprKind::Field(ref base, ident) => Some((base, Accessor::Field(ident))),
+        ExprKind::Index(ref base, ref idx) => Some((base, Accessor::Index(idx))),
+        ExprKind::MethodCall(ref seg, ref args) if !args.is_empty() => {
+            Some((&args[0], Accessor::Method(seg, &args[1..])))
+        }
+        _ => None,
+    }
+}
+
+/// Check whether `new` is a rewritten version of `old`, either because it came from `old` or
+/// because the two are equivalent.
+fn same_expr(old: &Expr, new: &Expr, rcx: &RewriteCtxtRef) -> bool {
+    rcx.new_to_old_id(new.id) == old.id || old.ast_equiv(new)
+}
+
+/// Rewrite the `else` branch of the `if` or `if let` expression `old`, whose `then` block is
+/// `then1`.  An `else` branch that was added or removed is inserted or deleted after the block,
+/// leaving the condition and the block alone.  An `else if` is just an `if` in the `else` branch,
+/// so the links of an `else if` chain are each handled by their own `if`.
+fn rewrite_else(
+    old: &Expr,
+    then1: &Block,
+    els1: &Option<P<Expr>>,
+    els2: &Option<P<Expr>>,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    match (els1, els2) {
+        (&Some(ref e1), &Some(ref e2)) => rewrite_with_prec(e1, e2, reset_prec(), rcx),
+        (&None, &None) => true,
+        (&Some(_), &None) => {
+            record_delete(then1.span.between(old.span.shrink_to_hi()), rcx);
+            true
+        }
+        (&None, &Some(ref e2)) => {
+            if !is_rewritable(then1.span) {
+                return false;
+            }
+            let at = then1.span.shrink_to_hi();
+            record_text_insert(at, " else ", rcx.borrow());
+            insert_with_prec(e2, at, reset_prec(), rcx)
+        }
+    }
+}
+
+/// Rewrite the binary operand `old` into `new`, which appears in precedence context `prec`.  If
+/// `new` needs parens in that context and the old text isn't already parenthesized, they're added
+/// around the old text.
+fn rewrite_operand(
+    tokens: &[(Token, Span)],
+    old: &P<Expr>,
+    new: &P<Expr>,
+    prec: ExprPrec,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let has_parens = extend_span_parens(tokens, old.span) != old.span;
+    if has_parens || prec.adjustment(new) != TextAdjust::PARENTHESIZE {
+        return rewrite_with_prec(old, new, prec, rcx);
+    }
+
+    record_text_insert(old.span.shrink_to_lo(), "(", rcx.borrow());
+    if !rewrite_with_prec(old, new, reset_prec(), rcx.borrow()) {
+        return false;
+    }
+    record_text_insert(old.span.shrink_to_hi(), ")", rcx);
+    true
+}
+
+/// Rewrite a binary operator expression whose operator changed.  Only the operator token is
+/// replaced, and the operands are rewritten as `recursive` would, with parentheses added around
+/// any operand (or the whole expression) that now needs them.
+fn rewrite_binary(
+    old: &Expr,
+    new: &Expr,
+    (op1, lhs1, rhs1): (BinOp, &P<Expr>, &P<Expr>),
+    (op2, lhs2, rhs2): (BinOp, &P<Expr>, &P<Expr>),
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    if !is_rewritable(op1.span) {
+        return false;
+    }
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let outer_parens = rcx.expr_prec().adjustment(new) == TextAdjust::PARENTHESIZE;
+    if outer_parens {
+        record_text_insert(old.span.shrink_to_lo(), "(", rcx.borrow());
+    }
+    let old_prec = rcx.reset_expr_prec();
+
+    let ok = rewrite_operand(&tokens, lhs1, lhs2, binop_left_prec(&op2), rcx.borrow())
+        && rewrite_operand(&tokens, rhs1, rhs2, binop_right_prec(&op2), rcx.borrow());
+    rcx.replace_expr_prec(old_prec);
+    if !ok {
+        return false;
+    }
+    record_token_rewrite(op1.span, op2.node.to_string(), rcx.borrow());
+
+    if outer_parens {
+        record_text_insert(old.span.shrink_to_hi(), ")", rcx);
+    }
+    true
+}
+
+/// Get the operator and operands of `e`, if it's an assignment.  The operator is `None` for a
+/// plain `=`, and the binary operator of a compound assignment like `+=` otherwise.
+fn assign_parts(e: &Expr) -> Option<(Option<BinOp>, &P<Expr>, &P<Expr>)> {
+    match e.node {
+        ExprKind::Assign(ref lhs, ref rhs) => Some((None, lhs, rhs)),
+        ExprKind::AssignOp(op, ref lhs, ref rhs) => Some((Some(op), lhs, rhs)),
+        _ => None,
+    }
+}
+
+fn assign_op_text(op: Option<BinOp>) -> String {
+    match op {
+        Some(op) => format!("{}=", op.node.to_string()),
+        None => "=".to_owned(),
+    }
+}
+
+/// Rewrite an assignment or compound assignment expression.  The operands are rewritten in
+/// place, and the operator token is replaced only if it changed, so changing `x += y` into
+/// `x -= y` or `x = y` keeps the text of both operands.
+fn rewrite_assign(
+    old: &Expr,
+    (op1, lhs1, rhs1): (Option<BinOp>, &P<Expr>, &P<Expr>),
+    (op2, lhs2, rhs2): (Option<BinOp>, &P<Expr>, &P<Expr>),
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+    // The operator is the first token after the lhs, including any parens around it.
+    let lhs_span = extend_span_parens(&tokens, lhs1.span);
+    let op_span = match tokens.iter().find(|&&(_, sp)| sp.lo() >= lhs_span.hi()) {
+        Some(&(Token::Eq, sp)) | Some(&(Token::BinOpEq(_), sp)) => sp,
+        _ => return false,
+    };
+
+    let assign_prec = parser::AssocOp::Assign.precedence() as i8;
+    let lhs_prec = ExprPrec::Normal(assign_prec + 1);
+    let rhs_prec = ExprPrec::Normal(assign_prec);
+    let old_prec = rcx.reset_expr_prec();
+    let ok = rewrite_operand(&tokens, lhs1, lhs2, lhs_prec, rcx.borrow())
+        && rewrite_operand(&tokens, rhs1, rhs2, rhs_prec, rcx.borrow());
+    rcx.replace_expr_prec(old_prec);
+    if !ok {
+        return false;
+    }
+
+    let old_text = assign_op_text(op1);
+    let new_text = assign_op_text(op2);
+    if old_text != new_text {
+        record_token_rewrite(op_span, &new_text, rcx);
+    }
+    true
+}
+
+/// If `b` is an `unsafe` block containing only a single expression, get the expression.
+fn unsafe_block_expr(b: &Block) -> Option<&P<Expr>> {
+    match b.rules {
+        BlockCheckMode::Unsafe(_) => {}
+        BlockCheckMode::Default => return None,
+    }
+    match b.stmts[..] {
+        [Stmt {
+            node: StmtKind::Expr(ref e),
+            ..
+        }] => Some(e),
+        _ => None,
+    }
+}
+
+/// Rewrite `old` into `new`, an `unsafe` block whose only contents are `inner2`, a rewritten
+/// version of `old`.  The text of `old` is recycled as the body of the block, and only `unsafe {`
+/// and `}` are inserted around it.
+///
+/// The wrapper is added on the same lines as the old text, which keeps its original indentation.
+/// Reindenting a multi-line body would mean rewriting every line of the recycled text.
+fn rewrite_unsafe_wrap(old: &Expr, inner2: &P<Expr>, mut rcx: RewriteCtxtRef) -> bool {
+    record_text_insert(old.span.shrink_to_lo(), "unsafe { ", rcx.borrow());
+    if !rewrite_with_prec(old, &**inner2, reset_prec(), rcx.borrow()) {
+        return false;
+    }
+    record_text_insert(old.span.shrink_to_hi(), " }", rcx);
+    true
+}
+
+/// Rewrite `old`, an `unsafe` block whose only contents are `inner1`, into `new`, a rewritten
+/// version of `inner1`.  The text of `inner1` is recycled, and the rest of the block is deleted.
+fn rewrite_unsafe_unwrap(
+    old: &Expr,
+    inner1: &P<Expr>,
+    new: &Expr,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+    let inner_span = extend_span_parens(&tokens, inner1.span);
+    if !is_rewritable(inner_span) {
+        return false;
+    }
+    let before = old.span.until(inner_span);
+    let after = inner_span.between(old.span.shrink_to_hi());
+
+    // Unlike the block, the expression may need parens in the block's old context.
+    let parens = rcx.expr_prec().adjustment(new) == TextAdjust::PARENTHESIZE
+        && inner_span == inner1.span;
+
+    let ok = if parens {
+        rewrite_with_prec(&**inner1, new, reset_prec(), rcx.borrow())
+    } else {
+        Rewrite::rewrite(&**inner1, new, rcx.borrow())
+    };
+    if !ok {
+        return false;
+    }
+
+    if parens {
+        record_token_rewrite(before, "(", rcx.borrow());
+        record_token_rewrite(after, ")", rcx);
+    } else {
+        record_delete(before, rcx.borrow());
+        record_delete(after, rcx);
+    }
+    true
+}
+
+/// If `e` is a block expression without a label, get the block along with the qualifier text
+/// that comes before it: `async`, `async move`, `try`, or nothing for a plain block.  The block's
+/// own `unsafe` is part of the block.
+fn qualified_block(e: &Expr) -> Option<(&P<Block>, &'static str)> {
+    match e.node {
+        ExprKind::Block(ref b, None) => Some((b, "")),
+        ExprKind::Async(CaptureBy::Ref, _, ref b) => Some((b, "async ")),
+        ExprKind::Async(CaptureBy::Value, _, ref b) => Some((b, "async move ")),
+        ExprKind::TryBlock(ref b) => Some((b, "try ")),
+        _ => None,
+    }
+}
+
+/// Check whether `prec` is the context of the operand of a postfix operator, such as the receiver
+/// of a method call.
+fn is_postfix_context(prec: ExprPrec) -> bool {
+    match prec {
+        ExprPrec::Normal(p) | ExprPrec::Cond(p) | ExprPrec::Callee(p) | ExprPrec::LeftLess(p) => {
+            p >= parser::PREC_POSTFIX
+        }
+    }
+}
+
+/// Rewrite the block expression `old` into `new`, where the two differ in their `async` or `try`
+/// qualifier.  The qualifier is replaced as a separate region in front of the block, and the
+/// block itself is rewritten as usual, so its statements keep their text.
+///
+/// A plain block that is the operand of a postfix operator gets parenthesized: `async {}.await`
+/// can't become `{}.await`, which at the start of a statement parses as a block followed by a
+/// stray `.await`.
+fn rewrite_block_qualifier(
+    old: &Expr,
+    (block1, prefix1): (&P<Block>, &'static str),
+    (block2, prefix2): (&P<Block>, &'static str),
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    if !is_rewritable(block1.span) || !old.span.contains(block1.span) {
+        return false;
+    }
+    let prefix_span = old.span.until(block1.span);
+    let parens = prefix2.is_empty() && is_postfix_context(rcx.expr_prec());
+
+    if !rewrite_with_prec(block1, block2, reset_prec(), rcx.borrow()) {
+        return false;
+    }
+
+    if parens {
+        record_token_rewrite(prefix_span, "(", rcx.borrow());
+        record_text_insert(old.span.shrink_to_hi(), ")", rcx);
+    } else if prefix1.is_empty() {
+        record_text_insert(prefix_span, prefix2, rcx);
+    } else if prefix2.is_empty() {
+        record_delete(prefix_span, rcx);
+    } else {
+        record_token_rewrite(prefix_span, prefix2, rcx);
+    }
+    true
+}
+
+/// Get the qualifier text that comes before the parameters of a closure: `static`, `async`, and
+/// `move`, in the order the parser accepts them, each followed by a space.
+fn closure_qualifiers(cap: CaptureBy, is_async: &IsAsync, mov: Movability) -> String {
+    let mut s = String::new();
+    if let Movability::Static = mov {
+        s.push_str("static ");
+    }
+    if let IsAsync::Async { .. } = *is_async {
+        s.push_str("async ");
+    }
+    if let CaptureBy::Value = cap {
+        s.push_str("move ");
+    }
+    s
+}
+
+/// Find the span of the qualifiers of the old closure `old`, from the start of the closure up to
+/// the `|` or `||` that opens its parameter list.
+fn find_closure_prefix_span(old: &Expr, rcx: &RewriteCtxtRef) -> Option<Span> {
+    let tokens = span_tokens(rcx.session(), old.span)?;
+    tokens
+        .into_iter()
+        .find(|&(ref tok, _)| match tok {
+            Token::BinOp(BinOpToken::Or) | Token::OrOr => true,
+            _ => false,
+        })
+        .map(|(_, sp)| old.span.until(sp))
+}
+
+/// Rewrite the closure `old` into `new`, where the two differ in their qualifiers.  The
+/// qualifiers are replaced as a separate region in front of the parameters, which are rewritten
+/// along with the body as usual.
+fn rewrite_closure_qualifiers(
+    old: &Expr,
+    (decl1, body1, prefix1): (&P<FnDecl>, &P<Expr>, String),
+    (decl2, body2, prefix2): (&P<FnDecl>, &P<Expr>, String),
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let prefix_span = match find_closure_prefix_span(old, &rcx) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    if !Rewrite::rewrite(decl1, decl2, rcx.borrow())
+        || !rewrite_with_prec(body1, body2, reset_prec(), rcx.borrow())
+    {
+        return false;
+    }
+
+    if prefix1.is_empty() {
+        record_text_insert(prefix_span, &prefix2, rcx);
+    } else if prefix2.is_empty() {
+        record_delete(prefix_span, rcx);
+    } else {
+        record_token_rewrite(prefix_span, &prefix2, rcx);
+    }
+    true
+}
+
+/// If `e` applies a postfix operator (`?`, `as T`, or `: T`) to a single operand, get the operand
+/// along with the precedence context for the operand.
+fn postfix_operand(e: &Expr) -> Option<(&P<Expr>, ExprPrec)> {
+    match e.node {
+        ExprKind::Try(ref inner) => Some((inner, ExprPrec::Normal(parser::PREC_POSTFIX))),
+        ExprKind::Cast(ref inner, _) => Some((
+            inner,
+            ExprPrec::Normal(parser::AssocOp::As.precedence() as i8),
+        )),
+        ExprKind::Type(ref inner, _) => Some((
+            inner,
+            ExprPrec::Normal(parser::AssocOp::Colon.precedence() as i8),
+        )),
+        _ => None,
+    }
+}
+
+/// Rewrite `old` into `new`, where `new` applies a postfix operator to `inner2`, which is a
+/// rewritten version of `old`.  The text of `old` is recycled as the operand, and only the
+/// operator (along with any type it takes, and parentheses if needed) is inserted.
+fn rewrite_postfix_wrap(
+    old: &Expr,
+    new: &Expr,
+    inner2: &P<Expr>,
+    operand_prec: ExprPrec,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    // `new` itself may need parens in the current context, as when `x.f()` becomes `(x as T).f()`.
+    let outer_parens = rcx.expr_prec().adjustment(new) == TextAdjust::PARENTHESIZE;
+    let parens = operand_prec.adjustment(old) == TextAdjust::PARENTHESIZE;
+    let lo = old.span.shrink_to_lo();
+    let hi = old.span.shrink_to_hi();
+
+    if outer_parens {
+        record_text_insert(lo, "(", rcx.borrow());
+    }
+    if parens {
+        record_text_insert(lo, "(", rcx.borrow());
+    }
+    let prec = if parens { reset_prec() } else { operand_prec };
+    if !rewrite_with_prec(old, &**inner2, prec, rcx.borrow()) {
+        return false;
+    }
+    if parens {
+        record_text_insert(hi, ")", rcx.borrow());
+    }
+
+    let ok = match new.node {
+        ExprKind::Try(_) => {
+            record_text_insert(hi, "?", rcx.borrow());
+            true
+        }
+        ExprKind::Cast(_, ref ty) => {
+            record_text_insert(hi, " as ", rcx.borrow());
+            ty.rewrite_at(hi, rcx.borrow())
+        }
+        ExprKind::Type(_, ref ty) => {
+            record_text_insert(hi, ": ", rcx.borrow());
+            ty.rewrite_at(hi, rcx.borrow())
+        }
+        _ => false,
+    };
+    if !ok {
+        return false;
+    }
+
+    if outer_parens {
+        record_text_insert(hi, ")", rcx);
+    }
+    true
+}
+
+/// Rewrite `old`, which applies a postfix operator to `inner1`, into `new`, a rewritten version
+/// of the operand alone.  The operand's text is recycled and the operator is deleted.
+fn rewrite_postfix_unwrap(
+    old: &Expr,
+    inner1: &P<Expr>,
+    new: &Expr,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+    // Any parens around the operand belong to it, and remain after the operator is gone.
+    let operand_span = extend_span_parens(&tokens, inner1.span);
+    let op_span = operand_span.between(old.span.shrink_to_hi());
+
+    // The operand now appears directly in the old expression's context.  The operand binds at
+    // least as tightly as the operator, so this rarely matters, but in callee position `a.b?()`
+    // must become `(a.b)()`.
+    let parens = rcx.expr_prec().adjustment(new) == TextAdjust::PARENTHESIZE
+        && operand_span == inner1.span;
+
+    let ok = if parens {
+        record_text_insert(operand_span.shrink_to_lo(), "(", rcx.borrow());
+        rewrite_with_prec(&**inner1, new, reset_prec(), rcx.borrow())
+    } else {
+        Rewrite::rewrite(&**inner1, new, rcx.borrow())
+    };
+    if !ok {
+        return false;
+    }
+
+    if parens {
+        record_token_rewrite(op_span, ")", rcx);
+    } else {
+        record_delete(op_span, rcx);
+    }
+    true
+}
+
+pub fn rewrite(old: &Expr, new: &Expr, mut rcx: RewriteCtxtRef) -> bool {
+    if !is_rewritable(old.span) {
+        return false;
+    }
+
+    if !Rewrite::rewrite(&old.attrs, &new.attrs, rcx.borrow()) {
+        return false;
+    }
+
+    rcx.record_node_span(old.get_span(), new.get_node_id());
+
+    match (&old.node, &new.node) {
+        (
+            &ExprKind::Range(ref lo1, ref hi1, limits1),
+            &ExprKind::Range(ref lo2, ref hi2, limits2),
+        ) => rewrite_range(old, (lo1, hi1, limits1), (lo2, hi2, limits2), rcx),
+
+        (
+            &ExprKind::While(ref cond1, ref body1, ref label1),
+            &ExprKind::While(ref cond2, ref body2, ref label2),
+        ) => {
+            rewrite_loop_label(old, label1, label2, rcx.borrow())
+                && rewrite_with_prec(cond1, cond2, cond_prec(), rcx.borrow())
+                && rewrite_with_prec(body1, body2, reset_prec(), rcx)
+        }
+
+        (
+            &ExprKind::WhileLet(ref pats1, ref expr1, ref body1, ref label1),
+            &ExprKind::WhileLet(ref pats2, ref expr2, ref body2, ref label2),
+        ) => {
+            rewrite_loop_label(old, label1, label2, rcx.borrow())
+                && rewrite_with_prec(pats1, pats2, reset_prec(), rcx.borrow())
+                && rewrite_with_prec(expr1, expr2, cond_prec(), rcx.borrow())
+                && rewrite_with_prec(body1, body2, reset_prec(), rcx)
+        }
+
+        (
+            &ExprKind::ForLoop(ref pat1, ref iter1, ref body1, ref label1),
+            &ExprKind::ForLoop(ref pat2, ref iter2, ref body2, ref label2),
+        ) => {
+            rewrite_loop_label(old, label1, label2, rcx.borrow())
+                && rewrite_with_prec(pat1, pat2, reset_prec(), rcx.borrow())
+                && rewrite_with_prec(iter1, iter2, cond_prec(), rcx.borrow())
+                && rewrite_with_prec(body1, body2, reset_prec(), rcx)
+        }
+
+        (_, &ExprKind::Block(ref body2, None))
+            if unsafe_block_expr(body2).map_or(false, |e| same_expr(old, e, &rcx)) =>
+        {
+            rewrite_unsafe_wrap(old, unsafe_block_expr(body2).unwrap(), rcx)
+        }
+
+        (&ExprKind::Block(ref body1, None), _)
+            if unsafe_block_expr(body1).map_or(false, |e| same_expr(e, new, &rcx)) =>
+        {
+            rewrite_unsafe_unwrap(old, unsafe_block_expr(body1).unwrap(), new, rcx)
+        }
+
+        (_, _)
+            if qualified_block(old)
+                .and_then(|(_, p1)| qualified_block(new).map(|(_, p2)| p1 != p2))
+                .unwrap_or(false) =>
+        {
+            let parts1 = qualified_block(old).unwrap();
+            let parts2 = qualified_block(new).unwrap();
+            rewrite_block_qualifier(old, parts1, parts2, rcx)
+        }
+
+        (
+            &ExprKind::Closure(cap1, ref is_async1, mov1, ref decl1, ref body1, _),
+            &ExprKind::Closure(cap2, ref is_async2, mov2, ref decl2, ref body2, _),
+        ) if closure_qualifiers(cap1, is_async1, mov1)
+            != closure_qualifiers(cap2, is_async2, mov2) =>
+        {
+            rewrite_closure_qualifiers(
+                old,
+                (decl1, body1, closure_qualifiers(cap1, is_async1, mov1)),
+                (decl2, body2, closure_qualifiers(cap2, is_async2, mov2)),
+                rcx,
+            )
+        }
+
+        (&ExprKind::Loop(ref body1, ref label1), &ExprKind::Loop(ref body2, ref label2))
+        | (&ExprKind::Block(ref body1, ref label1), &ExprKind::Block(ref body2, ref label2)) => {
+            rewrite_loop_label(old, label1, label2, rcx.borrow())
+                && rewrite_with_prec(body1, body2, reset_prec(), rcx)
+        }
+
+        (&ExprKind::Break(ref label1, ref value1), &ExprKind::Break(ref label2, ref value2)) => {
+            rewrite_break(old, (label1, value1), (label2, value2), rcx)
+        }
+
+        (&ExprKind::Ret(ref value1), &ExprKind::Ret(ref value2))
+        | (&ExprKind::Yield(ref value1), &ExprKind::Yield(ref value2)) => {
+            rewrite_jump_value(old, jump_keyword_span(old), value1, value2, rcx)
+        }
+
+        (&ExprKind::Continue(ref label1), &ExprKind::Continue(ref label2)) => {
+            rewrite_jump_label(old, label1, label2, rcx)
+        }
+
+        (&ExprKind::Array(ref elems1), &ExprKind::Array(ref elems2)) => {
+            rewrite_elems(old, elems1, elems2, rcx)
+        }
+
+        (&ExprKind::Call(ref func1, ref args1), &ExprKind::Call(ref func2, ref args2)) => {
+            rewrite_call(old, (func1, args1), (func2, args2), rcx)
+        }
+
+        // A one-element tuple needs a trailing comma, which the old text might not have.
+        (&ExprKind::Tup(ref elems1), &ExprKind::Tup(ref elems2)) if elems2.len() != 1 => {
+            rewrite_elems(old, elems1, elems2, rcx)
+        }
+
+        (
+            &ExprKind::If(ref cond1, ref then1, ref els1),
+            &ExprKind::If(ref cond2, ref then2, ref els2),
+        ) => {
+            rewrite_with_prec(cond1, cond2, cond_prec(), rcx.borrow())
+                && rewrite_with_prec(then1, then2, reset_prec(), rcx.borrow())
+                && rewrite_else(old, then1, els1, els2, rcx)
+        }
+
+        (
+            &ExprKind::IfLet(ref pats1, ref expr1, ref then1, ref els1),
+            &ExprKind::IfLet(ref pats2, ref expr2, ref then2, ref els2),
+        ) => {
+            rewrite_with_prec(pats1, pats2, reset_prec(), rcx.borrow())
+                && rewrite_with_prec(expr1, expr2, cond_prec(), rcx.borrow())
+                && rewrite_with_prec(then1, then2, reset_prec(), rcx.borrow())
+                && rewrite_else(old, then1, els1, els2, rcx)
+        }
+
+        (
+            &ExprKind::Struct(ref path1, ref fields1, ref base1),
+            &ExprKind::Struct(ref path2, ref fields2, ref base2),
+        ) => {
+            Rewrite::rewrite(path1, path2, rcx.borrow())
+                && rewrite_with_prec(fields1, fields2, reset_prec(), rcx.borrow())
+                && rewrite_struct_base(old, fields1.last(), base1, base2, rcx)
+        }
+
+        (&ExprKind::Match(ref target1, ref arms1), &ExprKind::Match(ref target2, ref arms2)) => {
+            rewrite_with_prec(target1, target2, cond_prec(), rcx.borrow())
+                && rewrite_arms(old, arms1, arms2, rcx)
+        }
+
+        (
+            &ExprKind::Binary(op1, ref lhs1, ref rhs1),
+            &ExprKind::Binary(op2, ref lhs2, ref rhs2),
+        ) if op1.node != op2.node => {
+            rewrite_binary(old, new, (op1, lhs1, rhs1), (op2, lhs2, rhs2), rcx)
+        }
+
+        (&ExprKind::Field(..), _) | (&ExprKind::Index(..), _) | (&ExprKind::MethodCall(..), _)
+            if access_parts(new).is_some() =>
+        {
+            let (base1, acc1) = access_parts(old).unwrap();
+            let (base2, acc2) = access_parts(new).unwrap();
+            rewrite_access(old, (base1, acc1), (base2, acc2), rcx)
+        }
+
+        (&ExprKind::Assign(..), _) | (&ExprKind::AssignOp(..), _)
+            if assign_parts(new).is_some() =>
+        {
+            let (op1, lhs1, rhs1) = assign_parts(old).unwrap();
+            let (op2, lhs2, rhs2) = assign_parts(new).unwrap();
+            rewrite_assign(old, (op1, lhs1, rhs1), (op2, lhs2, rhs2), rcx)
+        }
+
+        (_, _) if postfix_operand(new).map_or(false, |(e, _)| same_expr(old, e, &rcx)) => {
+            let (inner2, operand_prec) = postfix_operand(new).unwrap();
+            rewrite_postfix_wrap(old, new, inner2, operand_prec, rcx)
+        }
+
+        (_, _) if postfix_operand(old).map_or(false, |(e, _)| same_expr(e, new, &rcx)) => {
+            let (inner1, _) = postfix_operand(old).unwrap();
+            rewrite_postfix_unwrap(old, inner1, new, rcx)
+        }
+
+        (_, _) => false,
+    }
+}
@@ -0,0 +1,26 @@
+//! Rewriting strategy for adding attributes to a struct field that had none.
+//!
+//! `recursive` rewrites a field's attributes as a sequence, which needs an old attribute to find
+//! the place for new ones.  When a field without attributes gains some, like a new doc comment,
+//! it fails, and the whole struct is reprinted.  This strategy rewrites the field as `recursive`
+//! would, but inserts the new attributes at the start of the field, with doc comments on lines of
+//! their own.
+use syntax::ast::*;
+
+use crate::rewrite::base::{is_rewritable, MaybeRewriteSeq};
+use crate::rewrite::{Rewrite, RewriteCtxtRef};
+
+pub fn rewrite(old: &StructField, new: &StructField, mut rcx: RewriteCtxtRef) -> bool {
+    if !is_rewritable(old.span) {
+        return false;
+    }
+
+    MaybeRewriteSeq::maybe_rewrite_seq(
+        &old.attrs,
+        &new.attrs,
+        old.span.shrink_to_lo(),
+        rcx.borrow(),
+    ) && Rewrite::rewrite(&old.ident, &new.ident, rcx.borrow())
+        && Rewrite::rewrite(&old.vis, &new.vis, rcx.borrow())
+        && Rewrite::rewrite(&old.ty, &new.ty, rcx)
+}
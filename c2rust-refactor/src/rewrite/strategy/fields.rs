@@ -0,0 +1,78 @@
+//! Rewriting strategy for adding or removing fields of a struct, union, or enum variant.
+//!
+//! `recursive` can rewrite a field list only if it keeps the same length.  Adding or removing a
+//! field makes it fail, and `print` then reprints the whole item.  This strategy rewrites the list
+//! element-wise with `rewrite_list` instead, so the remaining fields keep their text, including
+//! their visibility and attributes, and only the added fields are printed.  This works the same
+//! for named fields (`struct S { x: i32 }`) and for the unnamed fields of tuple structs and
+//! newtypes (`struct S(pub i32)`).
+//!
+//! Lists that become empty, or that were empty before, are left to `print`, since there is no
+//! field span to find the delimiters from.
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::source_map::Span;
+
+use crate::ast_manip::GetSpan;
+use crate::rewrite::base::{describe, is_rewritable, line_indent, rewrite_list, ListSep};
+use crate::rewrite::strategy::print::RewriteAt;
+use crate::rewrite::RewriteCtxtRef;
+
+/// Splice the field `new` in place of `old_span`.  `StructField` can't be printed and reparsed on
+/// its own, so the attributes, visibility, and name are printed here, and only the type goes
+/// through `rewrite_at`.  Doc comments can't share a line with the field, so each one is followed
+/// by a line break and the indentation of the line where the field goes.
+fn splice_field(new: &StructField, old_span: Span, mut rcx: RewriteCtxtRef) -> bool {
+    let mut text = String::new();
+    for attr in &new.attrs {
+        text.push_str(pprust::attribute_to_string(attr).trim_end());
+        if attr.is_sugared_doc {
+            text.push('\n');
+            text.push_str(&line_indent(rcx.session(), old_span));
+        } else {
+            text.push(' ');
+        }
+    }
+    text.push_str(&pprust::vis_to_string(&new.vis));
+    if let Some(ident) = new.ident {
+        text.push_str(&format!("{}: ", ident));
+    }
+
+    info!("REWRITE (FIELDS) {}", describe(rcx.session(), old_span));
+    info!("   INTO (FIELDS) {}", text);
+    rcx.record_text(old_span, &text);
+    new.ty.rewrite_at(old_span.shrink_to_hi(), rcx)
+}
+
+pub fn rewrite(old: &VariantData, new: &VariantData, rcx: RewriteCtxtRef) -> bool {
+    let (fields1, fields2) = match (old, new) {
+        (&VariantData::Struct(ref fields1, _), &VariantData::Struct(ref fields2, _))
+        | (&VariantData::Tuple(ref fields1, _), &VariantData::Tuple(ref fields2, _)) => {
+            (fields1, fields2)
+        }
+        _ => return false,
+    };
+    if fields1.len() == fields2.len() || fields1.is_empty() || fields2.is_empty() {
+        return false;
+    }
+
+    let old_spans = fields1.iter().map(|f| f.get_span()).collect::<Vec<_>>();
+    if !old_spans.iter().all(|&sp| is_rewritable(sp)) {
+        return false;
+    }
+    let old_keys = fields1.iter().map(|f| f.id).collect::<Vec<_>>();
+    let new_keys = fields2
+        .iter()
+        .map(|f| rcx.new_to_old_id(f.id))
+        .collect::<Vec<_>>();
+    rewrite_list(
+        fields1,
+        fields2,
+        &old_spans,
+        &old_keys,
+        &new_keys,
+        ListSep::Comma,
+        splice_field,
+        rcx,
+    )
+}
@@ -0,0 +1,64 @@
+//! Rewriting strategy for adding or removing the default of a type parameter.
+//!
+//! `recursive` handles a default that changes into another type, but not one that appears or
+//! disappears, like `T` becoming `T = u32`.  Without this strategy, the whole param would be
+//! reprinted by `generics`, losing the text of its bounds.  This strategy recycles the rest of the
+//! param and inserts or deletes only the ` = Default` part.
+//!
+//! The other parts of a param, including the type of a const param, are rewritten by `recursive`
+//! as usual.
+use syntax::ast::*;
+use syntax::print::pprust;
+
+use crate::rewrite::base::{describe, is_rewritable};
+use crate::rewrite::strategy::generics::param_head_span;
+use crate::rewrite::{Rewrite, RewriteCtxtRef};
+
+pub fn rewrite(old: &GenericParam, new: &GenericParam, mut rcx: RewriteCtxtRef) -> bool {
+    let (old_default, new_default) = match (&old.kind, &new.kind) {
+        (
+            &GenericParamKind::Type { default: ref a },
+            &GenericParamKind::Type { default: ref b },
+        ) => (a, b),
+        _ => return false,
+    };
+    if old_default.is_some() == new_default.is_some() {
+        return false;
+    }
+
+    let head = match param_head_span(rcx.session(), old) {
+        Some(x) => x,
+        None => return false,
+    };
+    if !is_rewritable(head) {
+        return false;
+    }
+
+    let ok = Rewrite::rewrite(&old.ident, &new.ident, rcx.borrow())
+        && Rewrite::rewrite(&old.attrs, &new.attrs, rcx.borrow())
+        && Rewrite::rewrite(&old.bounds, &new.bounds, rcx.borrow());
+    if !ok {
+        return false;
+    }
+
+    match (old_default, new_default) {
+        (&None, &Some(ref ty)) => {
+            let at = head.shrink_to_hi();
+            let text = format!(" = {}", pprust::ty_to_string(ty));
+            info!("INSERT (DEFAULT) {}", describe(rcx.session(), at));
+            info!("  TEXT (DEFAULT) {:?}", text);
+            rcx.record_text(at, &text);
+        }
+        (&Some(ref ty), &None) => {
+            if !is_rewritable(ty.span) {
+                return false;
+            }
+            // Delete everything from the end of the bounds, including the `=`.
+            let old_span = ty.span.with_lo(head.hi());
+            info!("DELETE (DEFAULT) {}", describe(rcx.session(), old_span));
+            rcx.record_text(old_span, "");
+        }
+        _ => unreachable!(),
+    }
+    true
+}
@@ -0,0 +1,255 @@
+//! Rewriting strategy for `Generics`.
+//!
+//! A `Generics` node covers two separate pieces of syntax: the `<...>` parameter list following
+//! the item's name, and the `where` clause near the end of the item header.  `recursive` can only
+//! handle changes that leave the number of params and predicates unchanged, and `Generics` has no
+//! `print` strategy, so any other change normally forces a reprint of the entire item.
+//!
+//! This strategy rewrites each of the two lists as a comma-separated sequence, using
+//! `base::rewrite_list`.  Params are matched up by `NodeId`, and predicates (which mostly lack IDs)
+//! by structural equality.  Matched elements are recycled, with params that fail to rewrite
+//! reprinted individually.  New elements are inserted using the old list's own separator, so a
+//! multi-line `where` clause keeps one predicate per line.  Together, this handles transforms like
+//! moving a bound from `<T: Clone>` to `where T: Clone`.
+//!
+//! Adding a parameter list or `where` clause where there was none requires knowing where it should
+//! go, which only the enclosing item knows.  `item_header` calls `rewrite_generics` with those
+//! insertion points; the plain `Rewrite` impl handles only the cases that don't need them.
+use rustc::session::Session;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::source_map::{Span, DUMMY_SP};
+use syntax_pos::{BytePos, Pos};
+
+use crate::rewrite::base::{
+    describe, extend_span_back_whitespace, is_rewritable, rewrite_list, EquivKey, ListSep,
+};
+use crate::rewrite::{Rewrite, RewriteCtxtRef};
+
+/// Places to insert the parameter list and `where` clause, for use when the old `Generics` has
+/// none.  Either may be `DUMMY_SP` if no suitable place is known.
+pub struct GenericsInsertPoints {
+    /// Empty span just after the item's name.
+    pub params: Span,
+    /// Empty span at the end of the item header, just before the body.
+    pub where_clause: Span,
+}
+
+fn record_delete(old_span: Span, mut rcx: RewriteCtxtRef) {
+    info!("DELETE (GENERICS) {}", describe(rcx.session(), old_span));
+    rcx.record_text(old_span, "");
+}
+
+fn record_insert(at: Span, text: &str, mut rcx: RewriteCtxtRef) {
+    info!("INSERT (GENERICS) {}", describe(rcx.session(), at));
+    info!("  TEXT (GENERICS) {:?}", text);
+    rcx.record_text(at, text);
+}
+
+/// Find the `const` keyword preceding `ident`, the name of a const param.  The keyword has no span
+/// in the AST, so we look for it in the source text.
+fn const_keyword_span(sess: &Session, ident: Span) -> Option<Span> {
+    let before = sess.source_map().span_to_prev_source(ident).ok()?;
+    let trimmed = before.trim_end();
+    if !trimmed.ends_with("const") {
+        return None;
+    }
+    let start = trimmed.len() - "const".len();
+    if trimmed[..start]
+        .chars()
+        .next_back()
+        .map_or(false, |c| c.is_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+    let lo = ident.lo() - BytePos::from_usize(before.len() - start);
+    Some(ident.with_lo(lo).with_hi(lo + BytePos::from_usize("const".len())))
+}
+
+/// Compute the span of a generic param, leaving out the default of a type param.  `GenericParam`
+/// has no span of its own, so we combine the spans of its parts.
+pub fn param_head_span(sess: &Session, p: &GenericParam) -> Option<Span> {
+    let mut sp = p.ident.span;
+    if let GenericParamKind::Const { ref ty } = p.kind {
+        sp = const_keyword_span(sess, sp)?.to(ty.span);
+    }
+    if let Some(attr) = p.attrs.first() {
+        sp = attr.span.to(sp);
+    }
+    for bound in &p.bounds {
+        sp = sp.to(bound.span());
+    }
+    Some(sp)
+}
+
+/// Compute the span of a generic param, including any default.
+fn param_span(sess: &Session, p: &GenericParam) -> Option<Span> {
+    let sp = param_head_span(sess, p)?;
+    match p.kind {
+        GenericParamKind::Type {
+            default: Some(ref ty),
+        } => Some(sp.to(ty.span)),
+        _ => Some(sp),
+    }
+}
+
+fn print_param(p: &GenericParam) -> String {
+    // Strip the `<` and `>`.
+    let s = pprust::generic_params_to_string(&[p.clone()]);
+    s[1..s.len() - 1].to_owned()
+}
+
+fn print_predicate(p: &WherePredicate) -> String {
+    let wc = WhereClause {
+        id: DUMMY_NODE_ID,
+        predicates: vec![p.clone()],
+        span: DUMMY_SP,
+    };
+    // Strip the leading ` where `.
+    let s = pprust::to_string(|s| s.print_where_clause(&wc));
+    s.trim_start()
+        .trim_start_matches("where")
+        .trim_start()
+        .to_owned()
+}
+
+fn splice_param(p: &GenericParam, old_span: Span, mut rcx: RewriteCtxtRef) -> bool {
+    rcx.record_text(old_span, &print_param(p));
+    true
+}
+
+fn splice_predicate(p: &WherePredicate, old_span: Span, mut rcx: RewriteCtxtRef) -> bool {
+    rcx.record_text(old_span, &print_predicate(p));
+    true
+}
+
+fn rewrite_params(old: &Generics, new: &Generics, insert_at: Span, rcx: RewriteCtxtRef) -> bool {
+    match (old.params.is_empty(), new.params.is_empty()) {
+        (true, true) => true,
+
+        (false, true) => {
+            if !is_rewritable(old.span) {
+                return false;
+            }
+            record_delete(old.span, rcx);
+            true
+        }
+
+        (true, false) => {
+            if !is_rewritable(insert_at) {
+                return false;
+            }
+            let text = pprust::generic_params_to_string(&new.params);
+            record_insert(insert_at, &text, rcx);
+            true
+        }
+
+        (false, false) => {
+            let old_spans = match old
+                .params
+                .iter()
+                .map(|p| param_span(rcx.session(), p))
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(x) => x,
+                None => return false,
+            };
+            if !old_spans.iter().all(|&sp| is_rewritable(sp)) {
+                return false;
+            }
+
+            let old_keys = old.params.iter().map(|p| p.id).collect::<Vec<_>>();
+            let new_keys = new
+                .params
+                .iter()
+                .map(|p| rcx.new_to_old_id(p.id))
+                .collect::<Vec<_>>();
+            rewrite_list(
+                &old.params,
+                &new.params,
+                &old_spans,
+                &old_keys,
+                &new_keys,
+                ListSep::Comma,
+                splice_param,
+                rcx,
+            )
+        }
+    }
+}
+
+fn rewrite_where_clause(
+    old: &WhereClause,
+    new: &WhereClause,
+    insert_at: Span,
+    rcx: RewriteCtxtRef,
+) -> bool {
+    match (old.predicates.is_empty(), new.predicates.is_empty()) {
+        (true, true) => true,
+
+        (false, true) => {
+            if !is_rewritable(old.span) {
+                return false;
+            }
+            // Also delete the whitespace separating the `where` from the rest of the header.
+            let old_span = extend_span_back_whitespace(rcx.session(), old.span);
+            record_delete(old_span, rcx);
+            true
+        }
+
+        (true, false) => {
+            if !is_rewritable(insert_at) {
+                return false;
+            }
+            // The printed `where` clause starts with a space.
+            let text = pprust::to_string(|s| s.print_where_clause(new));
+            record_insert(insert_at, &text, rcx);
+            true
+        }
+
+        (false, false) => {
+            let old_spans = old.predicates.iter().map(|p| p.span()).collect::<Vec<_>>();
+            if !old_spans.iter().all(|&sp| is_rewritable(sp)) {
+                return false;
+            }
+
+            let old_keys = old.predicates.iter().map(EquivKey).collect::<Vec<_>>();
+            let new_keys = new.predicates.iter().map(EquivKey).collect::<Vec<_>>();
+            rewrite_list(
+                &old.predicates,
+                &new.predicates,
+                &old_spans,
+                &old_keys,
+                &new_keys,
+                ListSep::Comma,
+                splice_predicate,
+                rcx,
+            )
+        }
+    }
+}
+
+/// Rewrite `old` into `new`, using the positions in `at` to insert a parameter list or `where`
+/// clause if `old` lacks one.
+pub fn rewrite_generics(
+    old: &Generics,
+    new: &Generics,
+    at: &GenericsInsertPoints,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    rewrite_params(old, new, at.params, rcx.borrow())
+        && rewrite_where_clause(
+            &old.where_clause,
+            &new.where_clause,
+            at.where_clause,
+            rcx.borrow(),
+        )
+}
+
+pub fn rewrite(old: &Generics, new: &Generics, rcx: RewriteCtxtRef) -> bool {
+    let at = GenericsInsertPoints {
+        params: DUMMY_SP,
+        where_clause: DUMMY_SP,
+    };
+    rewrite_generics(old, new, &at, rcx)
+}
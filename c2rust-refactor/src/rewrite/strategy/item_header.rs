@@ -7,27 +7,30 @@
 //!
 //! This rewrite strategy does its own parsing of the `Item`'s tokens to find source locations for
 //! any qualifiers that are present, and also finds valid locations to insert any qualifiers that
-//! are absent from the original `Item`.
+//! are absent from the original `Item`.  Visibility is the exception: its AST node does carry a
+//! span, so it is rewritten by the `vis` strategy instead.
 //!
 //! Aside from the special handling of qualifiers, this strategy works the same as `recursive`.
+use rustc::session::Session;
 use syntax::ast::*;
 use syntax::parse::parser::Parser;
 use syntax::parse::token::{DelimToken, Token};
 use syntax::parse::PResult;
-use syntax::source_map::{BytePos, Span};
+use syntax::source_map::{BytePos, Span, DUMMY_SP};
 use syntax::symbol::keywords;
 use syntax::tokenstream::{TokenStream, TokenTree};
 
 use crate::ast_manip::AstEquiv;
 use crate::driver;
-use crate::rewrite::base::{describe, rewrite_seq_comma_sep};
+use crate::rewrite::base::{describe, extend_span_spaces, rewrite_seq_comma_sep, span_tokens};
+use crate::rewrite::strategy::generics::{rewrite_generics, GenericsInsertPoints};
 use crate::rewrite::strategy::print::PrintParse;
 use crate::rewrite::{Rewrite, RewriteCtxtRef, TextRewrite};
 
 struct FnHeaderSpans {
-    vis: Span,
     constness: Span,
     unsafety: Span,
+    asyncness: Span,
     abi: Span,
     ident: Span,
 }
@@ -40,7 +43,9 @@ fn span_empty(sp: Span) -> bool {
     sp.lo() == sp.hi()
 }
 
-fn find_fn_header_spans<'a>(p: &mut Parser<'a>) -> PResult<'a, FnHeaderSpans> {
+/// Skip over any leading attributes and the item's visibility.  Visibility is rewritten separately
+/// (see `strategy::vis`), so we don't need its span here.
+fn skip_attrs_and_vis<'a>(p: &mut Parser<'a>) -> PResult<'a, ()> {
     // Skip over any attributes that were included in the token stream.
     loop {
         if matches!([p.token] Token::DocComment(..)) {
@@ -54,14 +59,12 @@ fn find_fn_header_spans<'a>(p: &mut Parser<'a>) -> PResult<'a, FnHeaderSpans> {
         }
     }
 
-    let spanned_vis = p.parse_visibility(false)?;
-    let vis = if !spanned_vis.node.ast_equiv(&VisibilityKind::Inherited) {
-        spanned_vis.span
-    } else {
-        // `Inherited` visibility is implicit - there are no actual tokens.  Insert visibility just
-        // before the next token.
-        start_point(p.span)
-    };
+    p.parse_visibility(false)?;
+    Ok(())
+}
+
+fn find_fn_header_spans<'a>(p: &mut Parser<'a>) -> PResult<'a, FnHeaderSpans> {
+    skip_attrs_and_vis(p)?;
 
     let constness = if p.eat_keyword(keywords::Const) {
         p.prev_span
@@ -69,12 +72,30 @@ fn find_fn_header_spans<'a>(p: &mut Parser<'a>) -> PResult<'a, FnHeaderSpans> {
         start_point(p.span)
     };
 
+    // The parser accepts `async` only after `unsafe`, but pprust prints it before.  Accept both
+    // orders, so that we can handle both the original source and a printed item.  If there's no
+    // `async`, it should be inserted just after the `unsafe`.
+    let mut asyncness = if p.eat_keyword(keywords::Async) {
+        Some(p.prev_span)
+    } else {
+        None
+    };
+
     let unsafety = if p.eat_keyword(keywords::Unsafe) {
         p.prev_span
     } else {
         start_point(p.span)
     };
 
+    if asyncness.is_none() {
+        asyncness = Some(if p.eat_keyword(keywords::Async) {
+            p.prev_span
+        } else {
+            start_point(p.span)
+        });
+    }
+    let asyncness = asyncness.unwrap();
+
     let abi = if p.eat_keyword(keywords::Extern) {
         let extern_span = p.prev_span;
         if matches!([p.token] Token::Literal(..)) {
@@ -96,42 +117,21 @@ fn find_fn_header_spans<'a>(p: &mut Parser<'a>) -> PResult<'a, FnHeaderSpans> {
     let ident = p.prev_span;
 
     Ok(FnHeaderSpans {
-        vis,
         constness,
         unsafety,
+        asyncness,
         abi,
         ident,
     })
 }
 
 struct ItemHeaderSpans {
-    vis: Span,
     ident: Span,
 }
 
 /// Generic parsing function for item headers of the form "<vis> <struct/enum/etc> <ident>".
 fn find_item_header_spans<'a>(p: &mut Parser<'a>) -> PResult<'a, ItemHeaderSpans> {
-    // Skip over any attributes that were included in the token stream.
-    loop {
-        if matches!([p.token] Token::DocComment(..)) {
-            p.bump();
-        } else if matches!([p.token] Token::Pound) {
-            // I don't think we should ever see inner attributes inside `item.tokens`, but allow
-            // them just in case.
-            p.parse_attribute(true)?;
-        } else {
-            break;
-        }
-    }
-
-    let spanned_vis = p.parse_visibility(false)?;
-    let vis = if !spanned_vis.node.ast_equiv(&VisibilityKind::Inherited) {
-        spanned_vis.span
-    } else {
-        // `Inherited` visibility is implicit - there are no actual tokens.  Insert visibility just
-        // before the next token.
-        start_point(p.span)
-    };
+    skip_attrs_and_vis(p)?;
 
     let kws = &[
         keywords::Static,
@@ -160,7 +160,33 @@ fn find_item_header_spans<'a>(p: &mut Parser<'a>) -> PResult<'a, ItemHeaderSpans
     p.parse_ident()?;
     let ident = p.prev_span;
 
-    Ok(ItemHeaderSpans { vis, ident })
+    Ok(ItemHeaderSpans { ident })
+}
+
+struct StaticHeaderSpans {
+    mutability: Span,
+    ident: Span,
+}
+
+fn find_static_header_spans<'a>(p: &mut Parser<'a>) -> PResult<'a, StaticHeaderSpans> {
+    skip_attrs_and_vis(p)?;
+
+    p.expect(&Token::Ident(keywords::Static.ident(), false))?;
+
+    let mutability = if p.eat_keyword(keywords::Mut) {
+        p.prev_span
+    } else {
+        // Insert `mut` just before the identifier.
+        start_point(p.span)
+    };
+
+    p.parse_ident()?;
+    let ident = p.prev_span;
+
+    Ok(StaticHeaderSpans {
+        mutability,
+        ident,
+    })
 }
 
 fn find_fn_header_arg_list(ts: TokenStream, generics_span: Span) -> Option<(TokenStream, Span)> {
@@ -179,6 +205,15 @@ fn find_fn_header_arg_list(ts: TokenStream, generics_span: Span) -> Option<(Toke
         .next()
 }
 
+/// Find the end of the last token in `sp`, which should cover an item header.  Returns `DUMMY_SP`
+/// if the header can't be lexed.
+fn header_end(sess: &Session, sp: Span) -> Span {
+    match span_tokens(sess, sp).and_then(|tokens| tokens.last().cloned()) {
+        Some((_, tok_sp)) => tok_sp.shrink_to_hi(),
+        None => DUMMY_SP,
+    }
+}
+
 /// Record a rewrite of a qualifier, such as `unsafe`.  We make two assumptions:
 ///  1. If `old_span` is empty, then it is placed at the start of the next token after the place
 ///     the new qualifier should go.
@@ -193,6 +228,14 @@ fn record_qualifier_rewrite(old_span: Span, new_span: Span, mut rcx: RewriteCtxt
         new_span
     };
 
+    let old_span = if !span_empty(old_span) && span_empty(new_span) {
+        // We are deleting the qualifier.  Also delete the space that separated it from the
+        // following token.
+        extend_span_spaces(rcx.session(), old_span)
+    } else {
+        old_span
+    };
+
     if span_empty(old_span) {
         info!("INSERT (QUAL) {}", describe(rcx.session(), old_span));
         info!("    AT (QUAL) {}", describe(rcx.session(), src_span));
@@ -323,6 +366,20 @@ pub fn rewrite(old: &Item, new: &Item, mut rcx: RewriteCtxtRef) -> bool {
                 find_fn_header_arg_list(tokens1.as_ref().unwrap().clone(), generics1.span)
                     .expect("failed to find arg list in item tokens");
 
+            // We use the parser to find spans for all the old qualifiers, and for the ident.
+            //
+            // We could recover from parse errors by bailing on the rewrite (returning `true`), but
+            // it's easier to just panic.
+            let tts1 = tokens1.as_ref().unwrap().trees().collect::<Vec<_>>();
+            let spans1 = driver::run_parser_tts(rcx.session(), tts1, find_fn_header_spans);
+
+            // If the old fn has no generics, a param list goes right after the ident, and a
+            // `where` clause goes after the last token before the body.
+            let generics_at = GenericsInsertPoints {
+                params: spans1.ident.shrink_to_hi(),
+                where_clause: header_end(rcx.session(), span1.until(block1.span)),
+            };
+
             // First, try rewriting all the things we don't have special handling for.  If any of
             // these fails, bail out.
             let ok =
@@ -330,8 +387,9 @@ pub fn rewrite(old: &Item, new: &Item, mut rcx: RewriteCtxtRef) -> bool {
                 Rewrite::rewrite(attrs1, attrs2, rcx.borrow()) &&
                 Rewrite::rewrite(id1, id2, rcx.borrow()) &&
                 Rewrite::rewrite(span1, span2, rcx.borrow()) &&
+                Rewrite::rewrite(vis1, vis2, rcx.borrow()) &&
                 // ItemKind::Fn parts
-                Rewrite::rewrite(generics1, generics2, rcx.borrow()) &&
+                rewrite_generics(generics1, generics2, &generics_at, rcx.borrow()) &&
                 Rewrite::rewrite(block1, block2, rcx.borrow()) &&
                 // FnDecl parts
                 rewrite_arg_list_with_tokens(
@@ -343,23 +401,13 @@ pub fn rewrite(old: &Item, new: &Item, mut rcx: RewriteCtxtRef) -> bool {
                 return false;
             }
 
-            // Now try to splice changes to vis, constness, unsafety, abi, and ident.  We use the
-            // parser to find spans for all the old stuff.
-            //
-            // We could recover from parse errors by bailing on the rewrite (returning `true`), but
-            // it's easier to just panic.
-            let tts1 = tokens1.as_ref().unwrap().trees().collect::<Vec<_>>();
-            let spans1 = driver::run_parser_tts(rcx.session(), tts1, find_fn_header_spans);
-
+            // Now try to splice changes to constness, unsafety, abi, and ident.
             let src2: String = <Item as PrintParse>::to_string(new);
             let spans2 = driver::run_parser(rcx.session(), &src2, find_fn_header_spans);
 
             // The first four go in a specific order.  If multiple qualifiers are added (for
-            // example, both `unsafe` and `extern`), we need to add them in the right order.
-
-            if !vis1.node.ast_equiv(&vis2.node) {
-                record_qualifier_rewrite(spans1.vis, spans2.vis, rcx.borrow());
-            }
+            // example, both `unsafe` and `extern`), we need to add them in the right order.  The
+            // visibility always comes first, and was already rewritten above.
 
             if header1.constness.node != header2.constness.node {
                 record_qualifier_rewrite(spans1.constness, spans2.constness, rcx.borrow());
@@ -369,6 +417,10 @@ pub fn rewrite(old: &Item, new: &Item, mut rcx: RewriteCtxtRef) -> bool {
                 record_qualifier_rewrite(spans1.unsafety, spans2.unsafety, rcx.borrow());
             }
 
+            if header1.asyncness.node.is_async() != header2.asyncness.node.is_async() {
+                record_qualifier_rewrite(spans1.asyncness, spans2.asyncness, rcx.borrow());
+            }
+
             if header1.abi != header2.abi {
                 record_qualifier_rewrite(spans1.abi, spans2.abi, rcx.borrow());
             }
@@ -380,12 +432,56 @@ pub fn rewrite(old: &Item, new: &Item, mut rcx: RewriteCtxtRef) -> bool {
             true
         }
 
+        (
+            &ItemKind::Static(ref ty1, mutbl1, ref expr1),
+            &ItemKind::Static(ref ty2, mutbl2, ref expr2),
+        ) => {
+            // The generic case below handles `static`s as long as the mutability is unchanged.
+            // Here we also find the span of the `mut` keyword, so that it can be added or removed
+            // without reprinting the type and initializer.
+            let ok = Rewrite::rewrite(attrs1, attrs2, rcx.borrow())
+                && Rewrite::rewrite(id1, id2, rcx.borrow())
+                && Rewrite::rewrite(span1, span2, rcx.borrow())
+                && Rewrite::rewrite(vis1, vis2, rcx.borrow())
+                && Rewrite::rewrite(ty1, ty2, rcx.borrow())
+                && Rewrite::rewrite(expr1, expr2, rcx.borrow())
+                && true;
+            if !ok {
+                return false;
+            }
+
+            let tts1 = tokens1.as_ref().unwrap().trees().collect::<Vec<_>>();
+            let spans1 =
+                match driver::try_run_parser_tts(rcx.session(), tts1, find_static_header_spans) {
+                    Some(x) => x,
+                    None => return false,
+                };
+
+            let src2: String = <Item as PrintParse>::to_string(new);
+            let spans2 =
+                match driver::try_run_parser(rcx.session(), &src2, find_static_header_spans) {
+                    Some(x) => x,
+                    None => return false,
+                };
+
+            if mutbl1 != mutbl2 {
+                record_qualifier_rewrite(spans1.mutability, spans2.mutability, rcx.borrow());
+            }
+
+            if ident1 != ident2 {
+                record_qualifier_rewrite(spans1.ident, spans2.ident, rcx.borrow());
+            }
+
+            true
+        }
+
         (_, _) => {
             // Generic case, for items of the form "<vis> <struct/enum/etc> <ident>".
             let ok = Rewrite::rewrite(attrs1, attrs2, rcx.borrow())
                 && Rewrite::rewrite(id1, id2, rcx.borrow())
                 && Rewrite::rewrite(node1, node2, rcx.borrow())
                 && Rewrite::rewrite(span1, span2, rcx.borrow())
+                && Rewrite::rewrite(vis1, vis2, rcx.borrow())
                 && true;
             if !ok {
                 return false;
@@ -405,10 +501,6 @@ pub fn rewrite(old: &Item, new: &Item, mut rcx: RewriteCtxtRef) -> bool {
                 None => return false,
             };
 
-            if !vis1.node.ast_equiv(&vis2.node) {
-                record_qualifier_rewrite(spans1.vis, spans2.vis, rcx.borrow());
-            }
-
             if ident1 != ident2 {
                 record_qualifier_rewrite(spans1.ident, spans2.ident, rcx.borrow());
             }
@@ -0,0 +1,22 @@
+//! Rewriting strategy for renaming a `Lifetime`.
+//!
+//! A lifetime is a single token, but it has no `print` strategy of its own, so without this a
+//! renamed lifetime makes `print` reprint the enclosing type, path, or bound.  This strategy
+//! replaces only the lifetime's token.
+use syntax::ast::*;
+
+use crate::rewrite::base::{describe, is_rewritable};
+use crate::rewrite::RewriteCtxtRef;
+
+pub fn rewrite(old: &Lifetime, new: &Lifetime, mut rcx: RewriteCtxtRef) -> bool {
+    if !is_rewritable(old.ident.span) {
+        return false;
+    }
+
+    // The ident of a lifetime includes the leading `'`.
+    let text = new.ident.as_str().to_string();
+    info!("REWRITE (LIFETIME) {}", describe(rcx.session(), old.ident.span));
+    info!("   INTO (LIFETIME) {}", text);
+    rcx.record_text(old.ident.span, &text);
+    true
+}
@@ -0,0 +1,59 @@
+//! Rewriting strategy that keeps the old text of lint attributes whose meaning didn't change.
+//!
+//! Lint attributes like `#[allow(dead_code, unused_mut)]` are everywhere in translated code, and
+//! their exact text is worth keeping.  With `RewriteOptions::preserve_lint_attrs` set, a lint
+//! attribute whose new version has the same level and the same set of lints as the old one keeps
+//! its old text verbatim, even if the lints are listed in a different order or the attribute was
+//! rebuilt from scratch.  This is tried before all other strategies.  Only changing the level or
+//! the set of lints makes the attribute get rewritten.
+//!
+//! The same equivalence is used to match up rebuilt lint attributes with their old versions in a
+//! sequence of attributes (see `MaybeRewriteSeq for Attribute`), and to recycle their old text
+//! when the enclosing node gets reprinted.
+use syntax::ast::*;
+use syntax::print::pprust;
+
+use crate::rewrite::RewriteCtxtRef;
+
+const LINT_LEVELS: &[&str] = &["allow", "warn", "deny", "forbid"];
+
+/// Get the level and the sorted lint names of a lint attribute.  Returns `None` for other
+/// attributes, and for lint attributes with arguments other than plain lint names, such as a
+/// `reason = "..."`.
+fn lint_set(attr: &Attribute) -> Option<(String, Vec<String>)> {
+    if attr.is_sugared_doc || attr.path.segments.len() != 1 {
+        return None;
+    }
+    let level = attr.path.segments[0].ident.as_str().to_string();
+    if !LINT_LEVELS.contains(&&level[..]) {
+        return None;
+    }
+
+    let meta = attr.meta()?;
+    let mut lints = meta
+        .meta_item_list()?
+        .iter()
+        .map(|nested| match nested.meta_item() {
+            Some(mi) if mi.is_word() => Some(pprust::path_to_string(&mi.path)),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    lints.sort();
+    Some((level, lints))
+}
+
+/// Check whether `a` and `b` are lint attributes of the same style with the same level and the
+/// same lints, in any order.
+pub fn lint_equiv(a: &Attribute, b: &Attribute) -> bool {
+    if a.style != b.style {
+        return false;
+    }
+    match (lint_set(a), lint_set(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => false,
+    }
+}
+
+pub fn rewrite(old: &Attribute, new: &Attribute, rcx: RewriteCtxtRef) -> bool {
+    rcx.options().preserve_lint_attrs && lint_equiv(old, new)
+}
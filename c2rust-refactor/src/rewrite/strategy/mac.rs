@@ -0,0 +1,102 @@
+//! Rewriting strategy for arguments of formatting macros like `println!`.
+//!
+//! After expansion and collapsing, a macro invocation whose arguments were changed has new tokens
+//! consisting of the old invocation's tokens, with each changed argument replaced by a single
+//! `Interpolated` token holding the new argument.  The token stream has no `print` strategy of its
+//! own, so normally any change to an argument reprints the entire invocation, including the format
+//! string.
+//!
+//! This strategy walks the old and new token streams side by side.  Tokens copied from the old
+//! invocation are left alone, and each interpolated expression is spliced in place of the old
+//! tokens it covers.  For now this is limited to the common formatting macros, whose arguments are
+//! all expressions.
+use syntax::ast::*;
+use syntax::parse::token::{Nonterminal, Token};
+use syntax::tokenstream::{TokenStream, TokenTree};
+
+use crate::rewrite::base::{describe, is_rewritable};
+use crate::rewrite::strategy::print::RewriteAt;
+use crate::rewrite::{Rewrite, RewriteCtxtRef};
+
+/// Macros whose arguments we know how to rewrite.
+const FORMAT_MACROS: &[&str] = &[
+    "format",
+    "format_args",
+    "print",
+    "println",
+    "eprint",
+    "eprintln",
+    "write",
+    "writeln",
+    "panic",
+];
+
+fn is_format_macro(path: &Path) -> bool {
+    match path.segments.last() {
+        Some(seg) => FORMAT_MACROS.contains(&&*seg.ident.as_str()),
+        None => false,
+    }
+}
+
+fn rewrite_tts(old: TokenStream, new: TokenStream, mut rcx: RewriteCtxtRef) -> bool {
+    let mut old_trees = old.trees().peekable();
+
+    for new_tt in new.trees() {
+        match new_tt {
+            TokenTree::Token(sp, Token::Interpolated(nt)) => {
+                // Skip the old tokens that the new argument replaces.
+                match old_trees.peek() {
+                    Some(old_tt) if old_tt.span().lo() == sp.lo() => {}
+                    _ => return false,
+                }
+                while old_trees.peek().map_or(false, |tt| tt.span().lo() < sp.hi()) {
+                    old_trees.next();
+                }
+
+                let e = match *nt {
+                    Nonterminal::NtExpr(ref e) => e,
+                    _ => return false,
+                };
+                if !is_rewritable(sp) {
+                    return false;
+                }
+                info!("REWRITE (MAC) {}", describe(rcx.session(), sp));
+                let old_prec = rcx.reset_expr_prec();
+                let ok = e.rewrite_at(sp, rcx.borrow());
+                rcx.replace_expr_prec(old_prec);
+                if !ok {
+                    return false;
+                }
+            }
+
+            TokenTree::Token(sp, tok) => match old_trees.next() {
+                Some(TokenTree::Token(old_sp, ref old_tok)) if old_sp == sp && *old_tok == tok => {}
+                _ => return false,
+            },
+
+            TokenTree::Delimited(sp, delim, tts) => match old_trees.next() {
+                Some(TokenTree::Delimited(old_sp, old_delim, old_tts)) => {
+                    if old_sp != sp || old_delim != delim {
+                        return false;
+                    }
+                    if !rewrite_tts(old_tts.into(), tts.into(), rcx.borrow()) {
+                        return false;
+                    }
+                }
+                _ => return false,
+            },
+        }
+    }
+
+    old_trees.next().is_none()
+}
+
+pub fn rewrite(old: &Mac_, new: &Mac_, mut rcx: RewriteCtxtRef) -> bool {
+    if !is_format_macro(&old.path) || old.delim != new.delim {
+        return false;
+    }
+    if !Rewrite::rewrite(&old.path, &new.path, rcx.borrow()) {
+        return false;
+    }
+    rewrite_tts(old.tts.clone().into(), new.tts.clone().into(), rcx)
+}
@@ -0,0 +1,40 @@
+//! Rewriting strategy that keeps the text of `macro_rules!` definitions.
+//!
+//! The body of a macro definition is a token stream, which `AstEquiv` ignores and `recursive`
+//! doesn't look into, and which the pretty-printer mangles when it has to reprint the item: the
+//! matchers and transcribers lose their line breaks and spacing.  This strategy runs before all
+//! others on macro definition items.  If the tokens of the definition are unchanged, the old text
+//! is kept, and only the attributes and the macro's name are rewritten.  If the tokens changed,
+//! the definition is reprinted, since `recursive` would otherwise report success without applying
+//! the change.
+use syntax::ast::*;
+
+use crate::rewrite::base::{describe, is_rewritable};
+use crate::rewrite::strategy::print;
+use crate::rewrite::{Rewrite, RewriteCtxtRef};
+
+pub fn rewrite(old: &Item, new: &Item, mut rcx: RewriteCtxtRef) -> bool {
+    let (def1, def2) = match (&old.node, &new.node) {
+        (&ItemKind::MacroDef(ref def1), &ItemKind::MacroDef(ref def2)) => (def1, def2),
+        _ => return false,
+    };
+
+    if def1.legacy != def2.legacy || !def1.stream().eq_unspanned(&def2.stream()) {
+        return print::rewrite(old, new, rcx);
+    }
+
+    if !is_rewritable(old.span) {
+        return false;
+    }
+
+    if !Rewrite::rewrite(&old.attrs, &new.attrs, rcx.borrow()) {
+        return false;
+    }
+
+    if old.ident.name != new.ident.name {
+        info!("REWRITE (MACRO DEF) {}", describe(rcx.session(), old.ident.span));
+        info!("   INTO (MACRO DEF) {}", new.ident);
+        rcx.record_text(old.ident.span, &new.ident.to_string());
+    }
+    true
+}
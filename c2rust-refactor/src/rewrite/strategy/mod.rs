@@ -1,4 +1,21 @@
+pub mod arm;
+pub mod block;
+pub mod cfg;
 pub mod equal;
+pub mod expr;
+pub mod field_attrs;
+pub mod fields;
+pub mod generic_param;
+pub mod generics;
 pub mod item_header;
+pub mod lifetime;
+pub mod lint_attr;
+pub mod mac;
+pub mod macro_def;
+pub mod partial;
+pub mod pat;
 pub mod print;
 pub mod recursive;
+pub mod ty;
+pub mod variant;
+pub mod vis;
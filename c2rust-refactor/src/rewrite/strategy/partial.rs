@@ -0,0 +1,133 @@
+//! Rewriting strategy that reprints only the parts of a node that can't be recycled.
+//!
+//! When `recursive` fails somewhere inside a node, the failure normally propagates up to the
+//! nearest enclosing node that supports `print`, which is then reprinted as a whole.  Recovery
+//! brings back the old text of the printable nodes inside it, such as expressions and types, but
+//! everything in between, like the path of a struct literal or the signature of a trait method,
+//! gets the pretty-printer's formatting.
+//!
+//! This strategy is tried just before `print`, and narrows the fresh region down.  It reruns
+//! `recursive` in a mode where a node that no strategy can rewrite is "deferred" instead of
+//! failing, as long as it has a span.  A deferred node counts as rewritten, so its siblings are
+//! still recycled as usual.  Deferral picks the smallest nodes it can: a node whose failing
+//! children can be deferred defers those instead of itself.  If everything else succeeds, the
+//! whole node is printed and reparsed, as `print` would do, but only the text of each deferred
+//! node is taken from the printed output and spliced in at the node's old span, with the usual
+//! recovery inside it.
+//!
+//! A node that supports `print` never fails, so deferral only reaches the nodes between the node
+//! being rewritten and its nearest printable descendants.  Failures inside those descendants are
+//! handled by their own strategies.
+use std::any::TypeId;
+use syntax::source_map::Span;
+
+use crate::ast_manip::{AstDeref, AstName, GetSpan};
+use crate::rewrite::base::{describe, is_rewritable};
+use crate::rewrite::strategy::print::{PrintParse, RecoverChildren, Splice};
+use crate::rewrite::strategy::recursive::{self, Recursive};
+use crate::rewrite::{RewriteCtxtRef, TextRewrite, Transition};
+
+/// A node left for the enclosing `partial` rewrite to reprint.  The new node is identified by its
+/// type and address, since the same `new` AST is walked again when the reprinted text is
+/// recovered.
+#[derive(Clone, Debug)]
+pub struct Deferred {
+    key: (TypeId, usize),
+    old_span: Span,
+}
+
+fn key<T: 'static>(new: &T) -> (TypeId, usize) {
+    (TypeId::of::<T>(), new as *const T as usize)
+}
+
+/// Defer the rewrite of `old` into `new`, after every strategy for it has failed.  Called by the
+/// generated `Rewrite` impls, but only while `partial` is collecting deferred nodes.  Fails if the
+/// old text of `old` can't be replaced.
+pub fn defer<T: GetSpan + 'static>(old: &T, new: &T, mut rcx: RewriteCtxtRef) -> bool {
+    let old_span = old.get_span();
+    if !is_rewritable(old_span) || old_span.lo() == old_span.hi() {
+        return false;
+    }
+    info!("DEFER {}", describe(rcx.session(), old_span));
+    rcx.push_deferred(Deferred {
+        key: key(new),
+        old_span,
+    });
+    true
+}
+
+pub fn rewrite<T>(old: &T, new: &T, mut rcx: RewriteCtxtRef) -> bool
+where
+    T: Recursive + PrintParse + RecoverChildren + Splice,
+{
+    if !is_rewritable(old.splice_span()) {
+        return false;
+    }
+
+    let start = rcx.deferred_len();
+    let defer = rcx.replace_defer_failures(true);
+    let ok = recursive::rewrite(old, new, rcx.borrow());
+    rcx.replace_defer_failures(defer);
+    let deferred = rcx.take_deferred(start);
+    if !ok || deferred.is_empty() {
+        return false;
+    }
+
+    rcx.enter_fresh();
+    let printed = rcx.print(new);
+    let reparsed = rcx.reparse::<T>(&printed);
+    let reparsed = reparsed.ast_deref();
+
+    // Walk the reparsed AST in "locating" mode, looking only for the deferred nodes.  Their
+    // rewrites are recorded into `found`, while nothing else is recovered.
+    let mut found = TextRewrite::new(old.splice_span(), reparsed.splice_span());
+    let outer = rcx.replace_locating(Some(deferred));
+    RecoverChildren::recover_children(reparsed, new, rcx.enter(&mut found));
+    let missing = rcx.replace_locating(outer).map_or(0, |v| v.len());
+    rcx.leave_fresh();
+
+    if missing > 0 {
+        // Some deferred node has no counterpart in the reparsed AST, so we have no text for it.
+        info!("partial: {} deferred nodes not found in printed text", missing);
+        return false;
+    }
+    for rw in found.rewrites {
+        rcx.record(rw);
+    }
+    true
+}
+
+/// If `new` is one of the nodes deferred by the current `partial` rewrite, splice in the text of
+/// `reparsed` at the node's old span, recovering the node's children, and return `true`.  Called
+/// by the generated `RecoverChildren` impls while `partial` is locating deferred nodes.
+pub fn recover_deferred<T>(reparsed: &T, new: &T, mut rcx: RewriteCtxtRef) -> bool
+where
+    T: GetSpan + RecoverChildren + AstName + 'static,
+{
+    let mut pending = match rcx.replace_locating(None) {
+        Some(x) => x,
+        None => return false,
+    };
+    let idx = match pending.iter().position(|d| d.key == key(new)) {
+        Some(x) => x,
+        None => {
+            rcx.replace_locating(Some(pending));
+            return false;
+        }
+    };
+    let old_span = pending.swap_remove(idx).old_span;
+    let new_span = reparsed.get_span();
+
+    info!("REWRITE {}", describe(rcx.session(), old_span));
+    info!("   INTO {}", describe(rcx.session(), new_span));
+    rcx.transition(Transition::Fresh, new, old_span, new_span);
+
+    // Deferred nodes never overlap, so there's nothing left to locate inside this one, and its
+    // children are recovered normally.
+    let mut rw = TextRewrite::new(old_span, new_span);
+    RecoverChildren::recover_children(reparsed, new, rcx.enter(&mut rw));
+    rcx.record(rw);
+
+    rcx.replace_locating(Some(pending));
+    true
+}
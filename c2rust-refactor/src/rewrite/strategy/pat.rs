@@ -0,0 +1,49 @@
+//! Rewriting strategy for changing the binding mode of an identifier pattern.
+//!
+//! The binding mode of a pattern like `x`, `mut x`, `ref x`, or `ref mut x` is a flag, so
+//! `recursive` fails when it changes, and the enclosing node, such as a whole `match` arm, gets
+//! reprinted.  This strategy replaces only the `ref` and `mut` keywords before the name, and
+//! rewrites the name and any `@` subpattern (as in `ref x @ Some(_)`) as `recursive` would, so they
+//! keep their text.  Adding or removing a subpattern is left to the other strategies.
+use syntax::ast::*;
+
+use crate::rewrite::base::{describe, is_rewritable};
+use crate::rewrite::{Rewrite, RewriteCtxtRef};
+
+fn mode_prefix(mode: BindingMode) -> &'static str {
+    match mode {
+        BindingMode::ByValue(Mutability::Immutable) => "",
+        BindingMode::ByValue(Mutability::Mutable) => "mut ",
+        BindingMode::ByRef(Mutability::Immutable) => "ref ",
+        BindingMode::ByRef(Mutability::Mutable) => "ref mut ",
+    }
+}
+
+pub fn rewrite(old: &Pat, new: &Pat, mut rcx: RewriteCtxtRef) -> bool {
+    let (mode1, ident1, sub1, mode2, ident2, sub2) = match (&old.node, &new.node) {
+        (
+            &PatKind::Ident(mode1, ident1, ref sub1),
+            &PatKind::Ident(mode2, ident2, ref sub2),
+        ) => (mode1, ident1, sub1, mode2, ident2, sub2),
+        _ => return false,
+    };
+    if mode1 == mode2 || !is_rewritable(old.span) || !is_rewritable(ident1.span) {
+        return false;
+    }
+    if ident1.span.lo() < old.span.lo() {
+        return false;
+    }
+
+    if !Rewrite::rewrite(&ident1, &ident2, rcx.borrow())
+        || !Rewrite::rewrite(sub1, sub2, rcx.borrow())
+    {
+        return false;
+    }
+
+    // The keywords, along with the space after them, run from the start of the pattern up to the
+    // name.
+    let prefix = old.span.with_hi(ident1.span.lo());
+    info!("REWRITE (BINDING MODE) {}", describe(rcx.session(), prefix));
+    rcx.record_text(prefix, mode_prefix(mode2));
+    true
+}
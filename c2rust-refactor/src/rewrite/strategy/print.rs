@@ -12,7 +12,7 @@
 use rustc::session::Session;
 use rustc_data_structures::sync::Lrc;
 use rustc_target::spec::abi::Abi;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::path;
 use std::rc::Rc;
 use syntax::ast::*;
@@ -23,18 +23,22 @@ use syntax::parse::token::{DelimToken, Nonterminal, Token};
 use syntax::print::pprust::{self, PrintState};
 use syntax::ptr::P;
 use syntax::source_map::{BytePos, DUMMY_SP, FileName, SourceFile, Span, Spanned, dummy_spanned};
-use syntax::symbol::Symbol;
+use syntax::symbol::{keywords, Symbol};
 use syntax::tokenstream::{DelimSpan, TokenStream, TokenTree};
 use syntax::util::parser;
+use syntax::visit::{self, Visitor};
 use syntax::ThinVec;
 
 use crate::ast_manip::ast_map::NodeTable;
 use crate::ast_manip::util::extend_span_attrs;
-use crate::ast_manip::{AstDeref, GetSpan, MaybeGetNodeId};
+use crate::ast_manip::{AstDeref, AstEquiv, AstHash, AstName, GetSpan, MaybeGetNodeId, Visit};
 use crate::driver;
 use crate::rewrite::base::{binop_left_prec, binop_right_prec};
-use crate::rewrite::base::{describe, extend_span_comments, is_rewritable};
-use crate::rewrite::{ExprPrec, Rewrite, RewriteCtxt, RewriteCtxtRef, TextAdjust, TextRewrite};
+use crate::rewrite::base::{describe, extend_span_comments, is_rewritable, next_line_start};
+use crate::rewrite::strategy::{lint_attr, partial};
+use crate::rewrite::{
+    ExprPrec, Rewrite, RewriteCtxt, RewriteCtxtRef, TextAdjust, TextRewrite, Transition,
+};
 use crate::util::Lone;
 
 
@@ -46,20 +50,85 @@ use crate::util::Lone;
 /// this module to a more general location), but right everything we care to print is also pretty
 /// easy to parse.
 pub trait PrintParse {
-    /// Pretty print this node.
+    /// Pretty print this node with the built-in pretty-printer.
     fn to_string(&self) -> String;
 
+    /// Print this node with `printer`, by calling the `FreshPrinter` method for this node type.
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String;
+
     /// The result type of `Self::parse`.
-    type Parsed: AstDeref<Target = Self>;
+    type Parsed: AstDeref<Target = Self> + 'static;
     /// Parse a string to a node of this type.  Panics if parsing fails.
     fn parse(sess: &Session, src: &str) -> Self::Parsed;
 }
 
+/// Trait for printers of fresh code: new nodes that have no old source text to recycle.  By
+/// default, fresh code is printed with the built-in pretty-printer (`PrintParse::to_string`), but
+/// callers can substitute their own printer with `RewriteOptions::printer`, for example to match
+/// a house style.  Each method prints one type of node, and the printers of child nodes aren't
+/// called separately, so overriding `print_item` controls the whole text of printed items.
+///
+/// The printed text is reparsed, and must parse to a node equivalent to the one printed.
+/// Otherwise, recycling old text into the printed node may fail or misplace text.
+pub trait FreshPrinter {
+    fn print_expr(&self, x: &Expr) -> String {
+        x.to_string()
+    }
+
+    fn print_pat(&self, x: &Pat) -> String {
+        x.to_string()
+    }
+
+    fn print_ty(&self, x: &Ty) -> String {
+        x.to_string()
+    }
+
+    fn print_stmt(&self, x: &Stmt) -> String {
+        x.to_string()
+    }
+
+    fn print_item(&self, x: &Item) -> String {
+        x.to_string()
+    }
+
+    fn print_foreign_item(&self, x: &ForeignItem) -> String {
+        x.to_string()
+    }
+
+    fn print_block(&self, x: &Block) -> String {
+        x.to_string()
+    }
+
+    fn print_arg(&self, x: &Arg) -> String {
+        x.to_string()
+    }
+
+    fn print_attr(&self, x: &Attribute) -> String {
+        x.to_string()
+    }
+}
+
+impl fmt::Debug for dyn FreshPrinter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<FreshPrinter>")
+    }
+}
+
+/// The default `FreshPrinter`, which uses the built-in pretty-printer for everything.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultPrinter;
+
+impl FreshPrinter for DefaultPrinter {}
+
 impl PrintParse for Expr {
     fn to_string(&self) -> String {
         pprust::expr_to_string(self)
     }
 
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String {
+        printer.print_expr(self)
+    }
+
     type Parsed = P<Expr>;
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::parse_expr(sess, src)
@@ -71,6 +140,10 @@ impl PrintParse for Pat {
         pprust::pat_to_string(self)
     }
 
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String {
+        printer.print_pat(self)
+    }
+
     type Parsed = P<Pat>;
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::parse_pat(sess, src)
@@ -82,6 +155,10 @@ impl PrintParse for Ty {
         pprust::ty_to_string(self)
     }
 
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String {
+        printer.print_ty(self)
+    }
+
     type Parsed = P<Ty>;
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::parse_ty(sess, src)
@@ -99,6 +176,10 @@ impl PrintParse for Stmt {
         }
     }
 
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String {
+        printer.print_stmt(self)
+    }
+
     type Parsed = Stmt;
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::parse_stmts(sess, src).lone()
@@ -110,6 +191,10 @@ impl PrintParse for Item {
         pprust::item_to_string(self)
     }
 
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String {
+        printer.print_item(self)
+    }
+
     type Parsed = P<Item>;
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::parse_items(sess, src).lone()
@@ -123,6 +208,10 @@ impl PrintParse for ForeignItem {
         pprust::to_string(|s| s.print_foreign_item(self))
     }
 
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String {
+        printer.print_foreign_item(self)
+    }
+
     type Parsed = ForeignItem;
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::parse_foreign_items(sess, src).lone()
@@ -134,6 +223,10 @@ impl PrintParse for Block {
         pprust::block_to_string(self)
     }
 
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String {
+        printer.print_block(self)
+    }
+
     type Parsed = P<Block>;
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::parse_block(sess, src)
@@ -145,6 +238,10 @@ impl PrintParse for Arg {
         pprust::arg_to_string(self)
     }
 
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String {
+        printer.print_arg(self)
+    }
+
     type Parsed = Arg;
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::parse_arg(sess, src)
@@ -156,6 +253,10 @@ impl PrintParse for Attribute {
         pprust::attr_to_string(self)
     }
 
+    fn print_with(&self, printer: &dyn FreshPrinter) -> String {
+        printer.print_attr(self)
+    }
+
     type Parsed = Attribute;
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::run_parser(sess, src, |p| {
@@ -188,7 +289,7 @@ pub trait Splice {
     /// splicing it in.  This relies on the `RewriteCtxt` accurately tracking the `ExprPrec`s of
     /// the parent nodes of the destination location.
     fn get_adjustment(&self, _rcx: &RewriteCtxt) -> TextAdjust {
-        TextAdjust::None
+        TextAdjust::NONE
     }
 }
 
@@ -198,28 +299,7 @@ impl Splice for Expr {
     }
 
     fn get_adjustment(&self, rcx: &RewriteCtxt) -> TextAdjust {
-        // Check for cases where we can safely omit parentheses.
-        let prec = self.precedence();
-        let need_parens = match rcx.expr_prec() {
-            ExprPrec::Normal(min_prec) => prec.order() < min_prec,
-            ExprPrec::Cond(min_prec) => {
-                prec.order() < min_prec || parser::contains_exterior_struct_lit(self)
-            }
-            ExprPrec::Callee(min_prec) => match self.node {
-                ExprKind::Field(..) => true,
-                _ => prec.order() < min_prec,
-            },
-            ExprPrec::LeftLess(min_prec) => match self.node {
-                ExprKind::Cast(..) | ExprKind::Type(..) => true,
-                _ => prec.order() < min_prec,
-            },
-        };
-
-        if need_parens {
-            TextAdjust::Parenthesize
-        } else {
-            TextAdjust::None
-        }
+        rcx.expr_prec().adjustment(self)
     }
 }
 
@@ -277,12 +357,106 @@ impl Splice for Attribute {
 pub trait Recover {
     /// Obtain from the `RewriteCtxt` the table of old nodes of this type.
     fn node_table<'a, 's>(rcx: &'a RewriteCtxt<'s>) -> &'a NodeTable<'s, Self>;
+
+    /// Whether blank lines around the old text of this node should be recovered along with it.
+    /// See `extend_spans_blank_lines`.
+    fn keep_blank_lines(&self) -> bool {
+        false
+    }
+
+    /// Get a span of old source text that can stand in for the printed text of this node when
+    /// there is no old node to recover.
+    fn fallback_old_span(&self, _rcx: &RewriteCtxt) -> Option<Span> {
+        None
+    }
 }
 
 impl Recover for Expr {
     fn node_table<'a, 's>(rcx: &'a RewriteCtxt<'s>) -> &'a NodeTable<'s, Self> {
         &rcx.old_nodes().exprs
     }
+
+    fn fallback_old_span(&self, rcx: &RewriteCtxt) -> Option<Span> {
+        let lit = match self.node {
+            ExprKind::Lit(ref lit) => lit,
+            _ => return None,
+        };
+        match lit.node {
+            LitKind::Int(..) if rcx.options().preserve_int_bases => int_lit_old_span(lit, rcx),
+            LitKind::Str(..) | LitKind::ByteStr(..) => raw_str_lit_old_span(lit, rcx),
+            _ => None,
+        }
+    }
+}
+
+/// If `lit` is a string or byte string literal whose span points at an old raw literal with the
+/// same contents, get that span.  The printer picks its own delimiters, and always prints byte
+/// strings in escaped form, so the old text is the only way to keep the `r#"..."#` spelling with
+/// its exact number of `#`s.
+fn raw_str_lit_old_span(lit: &Lit, rcx: &RewriteCtxt) -> Option<Span> {
+    if !rcx.is_source_text(lit.span) {
+        return None;
+    }
+    let text = rcx.session().source_map().span_to_snippet(lit.span).ok()?;
+
+    let (is_byte, rest) = match text.get(..2) {
+        Some("br") => (true, &text[2..]),
+        _ if text.starts_with('r') => (false, &text[1..]),
+        _ => return None,
+    };
+    let hashes = rest.len() - rest.trim_start_matches('#').len();
+    let delim = &rest[..hashes];
+    let rest = &rest[hashes..];
+    let closing = format!("\"{}", delim);
+    if rest.len() < 2 + hashes || !rest.starts_with('"') || !rest.ends_with(&closing) {
+        return None;
+    }
+    let contents = &rest[1..rest.len() - 1 - hashes];
+
+    let same = match lit.node {
+        LitKind::Str(sym, _) => !is_byte && *sym.as_str() == *contents,
+        LitKind::ByteStr(ref bytes) => is_byte && bytes.as_slice() == contents.as_bytes(),
+        _ => false,
+    };
+    if same {
+        Some(lit.span)
+    } else {
+        None
+    }
+}
+
+/// If `lit` is an integer literal whose span points at old source text spelling the same value
+/// and suffix, get that span.  The printer writes every integer in decimal, so the old text keeps
+/// a hex, octal, or binary base, and any `_` separators, that the printed text would lose.
+fn int_lit_old_span(lit: &Lit, rcx: &RewriteCtxt) -> Option<Span> {
+    let (value, ty) = match lit.node {
+        LitKind::Int(value, ty) => (value, ty),
+        _ => return None,
+    };
+    if !rcx.is_source_text(lit.span) {
+        return None;
+    }
+    let text = rcx.session().source_map().span_to_snippet(lit.span).ok()?;
+
+    let suffix = match ty {
+        LitIntType::Signed(t) => t.ty_to_string(),
+        LitIntType::Unsigned(t) => t.ty_to_string(),
+        LitIntType::Unsuffixed => "",
+    };
+    if !text.ends_with(suffix) {
+        return None;
+    }
+    let digits = text[..text.len() - suffix.len()].replace('_', "");
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") => (16, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        _ => (10, &digits[..]),
+    };
+    match u128::from_str_radix(digits, radix) {
+        Ok(old_value) if old_value == value => Some(lit.span),
+        _ => None,
+    }
 }
 
 impl Recover for Pat {
@@ -301,12 +475,23 @@ impl Recover for Stmt {
     fn node_table<'a, 's>(rcx: &'a RewriteCtxt<'s>) -> &'a NodeTable<'s, Self> {
         &rcx.old_nodes().stmts
     }
+
+    fn keep_blank_lines(&self) -> bool {
+        match self.node {
+            StmtKind::Item(_) => true,
+            _ => false,
+        }
+    }
 }
 
 impl Recover for Item {
     fn node_table<'a, 's>(rcx: &'a RewriteCtxt<'s>) -> &'a NodeTable<'s, Self> {
         &rcx.old_nodes().items
     }
+
+    fn keep_blank_lines(&self) -> bool {
+        true
+    }
 }
 
 impl Recover for ForeignItem {
@@ -499,6 +684,136 @@ impl<T: RecoverChildren> RecoverChildren for ThinVec<T> {
     }
 }
 
+/// Attributes have no `NodeId`, so `recover` can't find their old text.  Instead, they're looked
+/// up by `AttrId`, and an attribute that is unchanged from the old AST gets its old text back
+/// verbatim.  This keeps the original formatting of `#[derive(...)]` lists and attribute macro
+/// arguments when the item they're attached to has to be reprinted.  Lint attributes only need to
+/// be equivalent to the old ones, as in `strategy::lint_attr`.
+impl RecoverChildren for Attribute {
+    fn recover_children(_reparsed: &Self, _new: &Self, _rcx: RewriteCtxtRef) {}
+
+    fn recover_node_and_children(reparsed: &Self, new: &Self, rcx: RewriteCtxtRef) {
+        recover_attr(None, reparsed, new, rcx);
+    }
+
+    fn recover_node_restricted(old_span: Span, reparsed: &Self, new: &Self, rcx: RewriteCtxtRef) {
+        recover_attr(Some(old_span), reparsed, new, rcx);
+    }
+}
+
+fn recover_attr(
+    maybe_restricted_span: Option<Span>,
+    reparsed: &Attribute,
+    new: &Attribute,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    if rcx.locating_deferred() {
+        return false;
+    }
+    let old = match rcx.old_nodes().attrs.get(&new.id) {
+        Some(&x) => x,
+        None => return false,
+    };
+    let lint_equiv = rcx.options().preserve_lint_attrs && lint_attr::lint_equiv(old, new);
+    if !(old.ast_equiv(new) || lint_equiv) || !rcx.is_source_text(old.span) {
+        return false;
+    }
+    if maybe_restricted_span == Some(old.span) {
+        return false;
+    }
+
+    // A doc comment parsed on its own has the trailing newline in its span (see `PrintParse for
+    // Attribute`), so the old text has to take its newline along too.
+    let mut old_span = old.span;
+    if reparsed.is_sugared_doc {
+        let cm = rcx.session().source_map();
+        if cm.span_to_snippet(reparsed.span).ok().map_or(false, |s| s.ends_with('\n')) {
+            match next_line_start(rcx.session(), old.span) {
+                Some((next, _)) => old_span = old_span.with_hi(next.hi()),
+                None => return false,
+            }
+        }
+    }
+
+    info!("REVERT {}", describe(rcx.session(), reparsed.span));
+    info!("    TO {}", describe(rcx.session(), old_span));
+    rcx.transition(Transition::Recycle, new, old_span, reparsed.span);
+    rcx.record(TextRewrite::new(reparsed.span, old_span));
+    true
+}
+
+/// Bounds on `Self` in a `where` clause, such as the `where Self: Sized` that keeps a trait method
+/// object safe, get their old text back verbatim when the enclosing item has to be reprinted.
+/// Since predicates have no `NodeId`, the old predicate is found by looking for an equivalent one
+/// in the old `WhereClause` with the matching ID.  Other predicates are recovered as usual.
+impl RecoverChildren for WhereClause {
+    fn recover_children(reparsed: &Self, new: &Self, mut rcx: RewriteCtxtRef) {
+        assert!(
+            reparsed.predicates.len() == new.predicates.len(),
+            "new and reprinted ASTs don't match"
+        );
+        let old_id = rcx.new_to_old_id(new.id);
+        let old = rcx.old_nodes().where_clauses.get(old_id);
+        for (reparsed_pred, new_pred) in reparsed.predicates.iter().zip(&new.predicates) {
+            if let Some(old) = old {
+                if recover_self_predicate(reparsed_pred, new_pred, old, rcx.borrow()) {
+                    continue;
+                }
+            }
+            RecoverChildren::recover_node_and_children(reparsed_pred, new_pred, rcx.borrow());
+        }
+    }
+
+    fn recover_node_and_children(reparsed: &Self, new: &Self, rcx: RewriteCtxtRef) {
+        // `WhereClause` doesn't implement `Recover`, so just call `recover_children`.
+        RecoverChildren::recover_children(reparsed, new, rcx);
+    }
+
+    fn recover_node_restricted(_old_span: Span, reparsed: &Self, new: &Self, rcx: RewriteCtxtRef) {
+        RecoverChildren::recover_children(reparsed, new, rcx);
+    }
+}
+
+/// Check if `pred` constrains `Self` or one of its associated types, as in `Self: Sized` or
+/// `Self::Item: Clone`.
+fn is_self_predicate(pred: &WherePredicate) -> bool {
+    let bounded_ty = match *pred {
+        WherePredicate::BoundPredicate(ref pred) => &pred.bounded_ty,
+        _ => return false,
+    };
+    match bounded_ty.node {
+        TyKind::Path(None, ref path) => path
+            .segments
+            .first()
+            .map_or(false, |seg| seg.ident.name == keywords::SelfUpper.name()),
+        _ => false,
+    }
+}
+
+fn recover_self_predicate(
+    reparsed: &WherePredicate,
+    new: &WherePredicate,
+    old_clause: &WhereClause,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    if rcx.locating_deferred() || !is_self_predicate(new) {
+        return false;
+    }
+    let old = match old_clause.predicates.iter().find(|&old| old.ast_equiv(new)) {
+        Some(x) => x,
+        None => return false,
+    };
+    if !rcx.is_source_text(old.span()) {
+        return false;
+    }
+
+    info!("REVERT {}", describe(rcx.session(), reparsed.span()));
+    info!("    TO {}", describe(rcx.session(), old.span()));
+    rcx.transition(Transition::Recycle, new, old.span(), reparsed.span());
+    rcx.record(TextRewrite::new(reparsed.span(), old.span()));
+    true
+}
+
 include!(concat!(
     env!("OUT_DIR"),
     "/rewrite_recover_children_gen.inc.rs"
@@ -513,6 +828,13 @@ include!(concat!(
 ///     the `NodeId` may differ between the old and new ASTs.
 ///  3. Splice the rewritten text in place of the text for `reparsed`.
 ///
+/// When the old node is structurally equivalent to `new`, step 2 has nothing to do, so it's
+/// skipped.  The structural hashes of the old nodes are computed when their `NodeTable` is built,
+/// and those of new nodes are cached in the `RewriteCtxt`, so this check usually costs a single
+/// comparison of hashes.  Structural equivalence ignores token streams, so the
+/// step is skipped only if the token streams of the two nodes are the same as well (see
+/// `same_token_streams`).
+///
 /// Returns `true` if all steps succeed.  Returns `false` if it fails to find an old node or if
 /// it fails to rewrite the old node to match `new`.
 fn recover<'s, T>(
@@ -522,30 +844,38 @@ fn recover<'s, T>(
     mut rcx: RewriteCtxtRef<'s, '_>,
 ) -> bool
 where
-    T: MaybeGetNodeId + Recover + Rewrite + Splice + 's,
+    T: MaybeGetNodeId
+        + Recover
+        + Rewrite
+        + Splice
+        + AstEquiv
+        + AstHash
+        + AstName
+        + Visit
+        + 'static,
 {
+    if rcx.locating_deferred() {
+        // `partial` is only looking for its deferred nodes.  See `partial::recover_deferred`.
+        return false;
+    }
+    if rcx.recycle_budget_exhausted() {
+        // Recovery would mean more comparisons, so the printed text is kept as it is.
+        return false;
+    }
+
     // Find a node with ID matching `new.id`, after accounting for renumbering of NodeIds.
     let old_id = rcx.new_to_old_id(new.get_node_id());
     let old = match <T as Recover>::node_table(&mut rcx).get(old_id) {
         Some(x) => x,
         None => {
-            return false;
+            return recover_fallback(reparsed, new, rcx);
         }
     };
 
     let old_span = old.splice_span();
     let old_span = extend_span_comments(&old_id, old_span, &rcx);
 
-    if !is_rewritable(old_span) {
-        return false;
-    }
-
-    let sf = rcx
-        .session()
-        .source_map()
-        .lookup_byte_offset(old_span.lo())
-        .sf;
-    if let FileName::Macros(..) = sf.name {
+    if !rcx.is_source_text(old_span) {
         return false;
     }
 
@@ -559,14 +889,25 @@ where
         }
     }
 
-    info!("REVERT {}", describe(rcx.session(), reparsed.splice_span()));
+    let (old_span, reparsed_span) = if old.keep_blank_lines() {
+        extend_spans_blank_lines(old_span, reparsed.splice_span(), &rcx)
+    } else {
+        (old_span, reparsed.splice_span())
+    };
+
+    info!("REVERT {}", describe(rcx.session(), reparsed_span));
     info!("    TO {}", describe(rcx.session(), old_span));
+    rcx.transition(Transition::Recycle, new, old_span, reparsed_span);
+
+    let new_hash = rcx.new_node_hash(new);
+    let unchanged = <T as Recover>::node_table(&rcx).equiv(old_id, new, new_hash)
+        && same_token_streams(old, new);
+    let mut rw = rcx.adjusted_rewrite(reparsed_span, old_span, new);
+    if unchanged {
+        rcx.record(rw);
+        return true;
+    }
 
-    let mut rw = TextRewrite::adjusted(
-        reparsed.splice_span(),
-        old_span,
-        new.get_adjustment(&rcx),
-    );
     let mark = rcx.mark();
     let ok = Rewrite::rewrite(old, new, rcx.enter(&mut rw));
     if !ok {
@@ -578,9 +919,133 @@ where
     true
 }
 
+/// Collects the token streams in a node: the arguments of attributes and macro invocations, and
+/// the bodies of `macro_rules!` definitions.
+struct TokenStreams(Vec<TokenStream>);
+
+impl<'ast> Visitor<'ast> for TokenStreams {
+    fn visit_tts(&mut self, tts: TokenStream) {
+        self.0.push(tts);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        self.0.push(mac.node.tts.clone());
+        visit::walk_mac(self, mac);
+    }
+
+    fn visit_mac_def(&mut self, def: &'ast MacroDef, _id: NodeId) {
+        self.0.push(def.stream());
+    }
+}
+
+/// Check whether `a` and `b` contain the same token streams, ignoring spans.  `AstEquiv` ignores
+/// token streams entirely, so two nodes that differ only in the arguments of an attribute or a
+/// macro are structurally equivalent.
+fn same_token_streams<T: Visit>(a: &T, b: &T) -> bool {
+    let mut tts1 = TokenStreams(Vec::new());
+    a.visit(&mut tts1);
+    let mut tts2 = TokenStreams(Vec::new());
+    b.visit(&mut tts2);
+    tts1.0.len() == tts2.0.len()
+        && tts1.0.iter().zip(&tts2.0).all(|(x, y)| x.eq_unspanned(y))
+}
+
+/// Replace the printed text of `new`, which has no matching old node, with the old text given by
+/// `Recover::fallback_old_span`, if there is any.
+fn recover_fallback<T>(reparsed: &T, new: &T, mut rcx: RewriteCtxtRef) -> bool
+where
+    T: Recover + Splice + AstName,
+{
+    let old_span = match new.fallback_old_span(&rcx) {
+        Some(x) => x,
+        None => return false,
+    };
+    let reparsed_span = reparsed.splice_span();
+    info!("REVERT {}", describe(rcx.session(), reparsed_span));
+    info!("    TO {}", describe(rcx.session(), old_span));
+    rcx.transition(Transition::Recycle, new, old_span, reparsed_span);
+    let rw = rcx.adjusted_rewrite(reparsed_span, old_span, new);
+    rcx.record(rw);
+    true
+}
+
+/// Keywords that can begin an item.  Used by `extend_spans_blank_lines` to guess whether a line
+/// of old text starts an item.
+const ITEM_START_KEYWORDS: &[&str] = &[
+    "#", "pub", "fn", "const", "static", "struct", "enum", "union", "trait", "impl", "type",
+    "mod", "use", "extern", "macro_rules!",
+];
+
+/// Get the line of `src` starting at `start`, not including the newline, or `None` if `start` is
+/// past the end of `src`.
+fn line_at(src: &str, start: usize) -> Option<&str> {
+    if start > src.len() {
+        return None;
+    }
+    let rest = &src[start..];
+    Some(&rest[..rest.find('\n').unwrap_or(rest.len())])
+}
+
+/// Extend the spans of a recovered node to take along blank lines that separate its old text from
+/// the surrounding code.  `old_span` covers the old text and `new_span` the node in the printed
+/// text.  The pretty-printer never emits blank lines, so without this, the blank lines around
+/// nested items and other items of a reprinted block or module would be lost.
+///
+/// A blank line before the node is kept if the node starts its line in both the old and the
+/// printed text.  The old span is extended back over the blank line and the node's indentation,
+/// and the new span back to the start of its line.  A blank line after the node is kept if the
+/// node ends its line in both texts, and the old span is extended forward to the end of the blank
+/// line.  If the line following the blank line starts another item, the blank line is left for
+/// that item to take instead, so a blank line between two items isn't doubled.
+fn extend_spans_blank_lines(old_span: Span, new_span: Span, rcx: &RewriteCtxt) -> (Span, Span) {
+    let cm = rcx.session().source_map();
+    let old_pos = cm.lookup_byte_offset(old_span.lo());
+    let new_pos = cm.lookup_byte_offset(new_span.lo());
+    let (old_src, new_src) = match (old_pos.sf.src.as_ref(), new_pos.sf.src.as_ref()) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return (old_span, new_span),
+    };
+    let old_lo = old_pos.pos.0 as usize;
+    let old_hi = old_lo + (old_span.hi().0 - old_span.lo().0) as usize;
+    let new_lo = new_pos.pos.0 as usize;
+    let new_hi = new_lo + (new_span.hi().0 - new_span.lo().0) as usize;
+    let (mut old_span, mut new_span) = (old_span, new_span);
+
+    let line_start = |src: &str, pos: usize| src[..pos].rfind('\n').map(|i| i + 1);
+    let old_line = line_start(old_src, old_lo).filter(|&i| old_src[i..old_lo].trim().is_empty());
+    let new_line = line_start(new_src, new_lo).filter(|&i| new_src[i..new_lo].trim().is_empty());
+    if let (Some(old_line), Some(new_line)) = (old_line, new_line) {
+        if old_line > 0 {
+            let blank = line_start(old_src, old_line - 1).unwrap_or(0);
+            if old_src[blank..old_line].trim().is_empty() {
+                old_span = old_span.with_lo(old_span.lo() - BytePos((old_lo - blank) as u32));
+                new_span = new_span.with_lo(new_span.lo() - BytePos((new_lo - new_line) as u32));
+            }
+        }
+    }
+
+    let old_rest = line_at(old_src, old_hi).filter(|s| s.trim().is_empty());
+    let new_rest = line_at(new_src, new_hi).filter(|s| s.trim().is_empty());
+    if let (Some(old_rest), Some(_)) = (old_rest, new_rest) {
+        let blank_start = old_hi + old_rest.len() + 1;
+        let blank = line_at(old_src, blank_start).filter(|s| s.trim().is_empty());
+        if let Some(blank) = blank {
+            let next = line_at(old_src, blank_start + blank.len() + 1).unwrap_or("");
+            let next = next.trim_start();
+            let next_is_item = ITEM_START_KEYWORDS.iter().any(|kw| next.starts_with(kw));
+            if !next_is_item {
+                let end = blank_start + blank.len();
+                old_span = old_span.with_hi(old_span.hi() + BytePos((end - old_hi) as u32));
+            }
+        }
+    }
+
+    (old_span, new_span)
+}
+
 pub fn rewrite<T>(old: &T, new: &T, rcx: RewriteCtxtRef) -> bool
 where
-    T: PrintParse + RecoverChildren + Splice + Debug + MaybeGetNodeId,
+    T: PrintParse + RecoverChildren + Splice + Debug + MaybeGetNodeId + AstName,
 {
     if !is_rewritable(old.splice_span()) {
         // If we got here, it means rewriting failed somewhere inside macro-generated code, and
@@ -644,17 +1109,68 @@ fn add_comments<T>(s: String, node: &T, rcx: &RewriteCtxt) -> String
     s
 }
 
+/// Node types whose fresh text gets provenance markers.  See `ProvenanceMarkers`.
+const MARKED_NODES: &[&str] = &["Item", "ImplItem", "TraitItem", "ForeignItem", "Stmt"];
+
+/// Get the text to insert before and after the fresh text of `new` at `old_span`, if `new` needs
+/// provenance markers.  Called after entering fresh mode for `new`.
+fn provenance_markers<T: AstName>(
+    old_span: Span,
+    new: &T,
+    rcx: &RewriteCtxt,
+) -> Option<(String, String)> {
+    let markers = rcx.options().provenance_markers.as_ref()?;
+    if rcx.fresh_depth() != 1 {
+        return None;
+    }
+    let name = new.ast_name();
+    let kind = name.split(':').next().unwrap_or("");
+    if !MARKED_NODES.contains(&kind) {
+        return None;
+    }
+
+    // The markers go on lines of their own, indented like the line where the node starts.  A node
+    // inserted after other code on the same line, like a statement inserted after the last one,
+    // gets line breaks before and after it, so the end marker can't comment out any code.
+    let cm = rcx.session().source_map();
+    let lo = cm.lookup_byte_offset(old_span.lo());
+    let hi = cm.lookup_byte_offset(old_span.hi());
+    let src = lo.sf.src.as_ref()?;
+    let before = &src[..lo.pos.0 as usize];
+    let line = &before[before.rfind('\n').map_or(0, |i| i + 1)..];
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let after = &src[hi.pos.0 as usize..];
+    let rest = &after[..after.find('\n').unwrap_or(after.len())];
+
+    let mut begin = String::new();
+    if !line.trim().is_empty() {
+        begin.push_str(&format!("\n{}", indent));
+    }
+    begin.push_str(&format!("{}\n{}", markers.begin, indent));
+    let mut end = format!("\n{}{}", indent, markers.end);
+    if !rest.trim().is_empty() {
+        end.push_str(&format!("\n{}", indent));
+    }
+    Some((begin, end))
+}
+
 fn rewrite_at_impl<T>(old_span: Span, new: &T, mut rcx: RewriteCtxtRef) -> bool
 where
-    T: PrintParse + RecoverChildren + Splice + Debug + MaybeGetNodeId,
+    T: PrintParse + RecoverChildren + Splice + Debug + MaybeGetNodeId + AstName,
 {
-    let printed = add_comments(new.to_string(), new, &rcx);
-    let reparsed = T::parse(rcx.session(), &printed);
+    rcx.enter_fresh();
+    let markers = provenance_markers(old_span, new, &rcx);
+    if let Some((ref begin, _)) = markers {
+        rcx.record_text(old_span.shrink_to_lo(), begin);
+    }
+    let printed = add_comments(rcx.print(new), new, &rcx);
+    let reparsed = rcx.reparse::<T>(&printed);
     let reparsed = reparsed.ast_deref();
 
     describe_rewrite(old_span, reparsed.splice_span(), &rcx);
+    rcx.transition(Transition::Fresh, new, old_span, reparsed.splice_span());
 
-    let mut rw = TextRewrite::adjusted(old_span, reparsed.splice_span(), new.get_adjustment(&rcx));
+    let mut rw = rcx.adjusted_rewrite(old_span, reparsed.splice_span(), new);
     // Try recovery, starting in "restricted mode" to avoid infinite recursion.
     // The guarantee of `recover_node_restricted` is that if it calls into
     // `Rewrite::rewrite(old2, new2, ...)`, then `old2.splice_span() !=
@@ -663,6 +1179,10 @@ where
     RecoverChildren::recover_node_restricted(old_span, reparsed, new, rcx.enter(&mut rw));
 
     rcx.record(rw);
+    if let Some((_, ref end)) = markers {
+        rcx.record_text(old_span.shrink_to_hi(), end);
+    }
+    rcx.leave_fresh();
     true
 }
 
@@ -671,7 +1191,7 @@ pub trait RewriteAt {
 }
 
 impl<T> RewriteAt for T
-    where T: PrintParse + RecoverChildren + Splice + Debug + MaybeGetNodeId
+    where T: PrintParse + RecoverChildren + Splice + Debug + MaybeGetNodeId + AstName
 {
     default fn rewrite_at(&self, old_span: Span, rcx: RewriteCtxtRef) -> bool {
         rewrite_at_impl(old_span, self, rcx)
@@ -756,24 +1276,22 @@ impl RewriteAt for Item {
                     module.inner
                 };
 
+                rcx.enter_fresh();
+
                 // Print the module (mod foo;) in the parent
-                let printed = add_comments(item.to_string(), &item, &rcx);
-                let reparsed = Self::parse(rcx.session(), &printed);
+                let printed = add_comments(rcx.print(&item), &item, &rcx);
+                let reparsed = rcx.reparse::<Self>(&printed);
                 let reparsed = reparsed.ast_deref();
 
                 describe_rewrite(old_span, reparsed.splice_span(), &rcx);
+                rcx.transition(Transition::Fresh, self, old_span, reparsed.splice_span());
 
-                let rw = TextRewrite::adjusted(
-                    old_span,
-                    reparsed.splice_span(),
-                    self.get_adjustment(&rcx),
-                );
-                rcx.record(rw);
+                rcx.record_adjusted(old_span, reparsed.splice_span(), self);
 
                 // Print the module items in the external file
                 let mut printed = pprust::to_string(|s| s.print_inner_attributes(&self.attrs));
                 for item in &module.items {
-                    printed.push_str(&add_comments(item.to_string(), item, &rcx));
+                    printed.push_str(&add_comments(rcx.print(&**item), item, &rcx));
                 }
                 let reparsed = driver::parse_items(rcx.session(), &printed);
 
@@ -785,14 +1303,12 @@ impl RewriteAt for Item {
                 let reparsed_span = first_span.with_hi(last_span.hi());
 
                 describe_rewrite(inner_span, reparsed_span, &rcx);
-                let mut rw = TextRewrite::adjusted(
-                    inner_span,
-                    reparsed_span,
-                    self.get_adjustment(&rcx),
-                );
+                rcx.transition(Transition::Fresh, self, inner_span, reparsed_span);
+                let mut rw = rcx.adjusted_rewrite(inner_span, reparsed_span, self);
                 RecoverChildren::recover_children(&reparsed, &module.items, rcx.enter(&mut rw));
                 rcx.record(rw);
 
+                rcx.leave_fresh();
                 return true;
             }
         }
@@ -0,0 +1,586 @@
+//! Rewriting strategy for function pointer, trait object, reference, array, tuple, and qualified
+//! path types.
+//!
+//! `recursive` can rewrite the components of a `fn(A, B) -> C`, `dyn Foo + Send`, or
+//! `impl Foo + Send` type only if the type keeps the same shape.  Adding or removing an argument,
+//! a return type, a bound, or the `dyn` keyword makes it fail, and `print` then reprints the whole
+//! type.  The same goes for making the lifetime of a reference explicit (`&T` to `&'a T`) or
+//! eliding it again, for changing the mutability of a reference, for turning a raw pointer into a
+//! reference (`*mut T` to `&mut T`) or back, for turning an array type `[T; N]` into a slice type
+//! `[T]` or back, for adding or removing elements of a tuple type, and for renaming the trait or
+//! associated type of a qualified path type `<T as Trait>::Assoc`.
+//!
+//! This strategy handles those changes directly.  Argument, bound, and tuple element lists are
+//! rewritten element-wise with `rewrite_list`, recycling the elements that remain, and the `-> C`,
+//! `dyn`, `'a`, `mut`, and `; N` parts are inserted or deleted on their own.  The `*mut ` or
+//! `&mut ` before a pointee type is replaced as a whole when switching between raw pointers and
+//! references, leaving the pointee's text alone.  A changed bound that
+//! `recursive` can't handle is reprinted alone, leaving the `dyn` or `impl` keyword and the other
+//! bounds, and a bound list whose bounds are only reordered (`Send + Sync` to `Sync + Send`) keeps
+//! its old order.  The segment names of a qualified path are replaced one token at a time.
+//! Everything else, such as the `for<'a>` of a higher-ranked function type, is left alone.
+use syntax::ast::*;
+use syntax::parse::token::{BinOpToken, DelimToken, Token};
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::source_map::Span;
+use syntax::symbol::keywords;
+use syntax_pos::BytePos;
+
+use crate::ast_manip::AstEquiv;
+use crate::rewrite::base::{
+    describe, extend_span_spaces, is_rewritable, rewrite_list, span_tokens, EquivKey, ListSep,
+};
+use crate::rewrite::strategy::print::RewriteAt;
+use crate::rewrite::{Rewrite, RewriteCtxtRef};
+
+fn record_delete(old_span: Span, mut rcx: RewriteCtxtRef) {
+    info!("DELETE (TY) {}", describe(rcx.session(), old_span));
+    rcx.record_text(old_span, "");
+}
+
+fn record_insert(at: Span, text: &str, mut rcx: RewriteCtxtRef) {
+    info!("INSERT (TY) {}", describe(rcx.session(), at));
+    info!("  TEXT (TY) {:?}", text);
+    rcx.record_text(at, text);
+}
+
+/// Find the spans of the parens around the argument list of the function pointer type `old`.
+fn find_arg_parens(old: &Ty, rcx: &RewriteCtxtRef) -> Option<(Span, Span)> {
+    let tokens = span_tokens(rcx.session(), old.span)?;
+    // Nothing before the argument list (`for<'a>`, `unsafe`, `extern "C"`, `fn`) contains parens.
+    let open_idx = tokens
+        .iter()
+        .position(|&(ref tok, _)| *tok == Token::OpenDelim(DelimToken::Paren))?;
+
+    let mut depth = 0;
+    for &(ref tok, sp) in &tokens[open_idx..] {
+        match *tok {
+            Token::OpenDelim(DelimToken::Paren) => depth += 1,
+            Token::CloseDelim(DelimToken::Paren) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((tokens[open_idx].1, sp));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Check whether `arg` is an argument of a function pointer type that has no name, as in
+/// `fn(i32)`.  The parser gives these an ident pattern with an invalid name.
+fn is_unnamed(arg: &Arg) -> bool {
+    match arg.pat.node {
+        PatKind::Ident(_, ident, None) => ident.name == keywords::Invalid.name(),
+        _ => false,
+    }
+}
+
+fn arg_span(arg: &Arg) -> Span {
+    arg.pat.span.to(arg.ty.span)
+}
+
+fn splice_arg(new: &Arg, old_span: Span, rcx: RewriteCtxtRef) -> bool {
+    // An unnamed arg is just a type, which can't be parsed as an `Arg`.
+    if is_unnamed(new) {
+        new.ty.rewrite_at(old_span, rcx)
+    } else {
+        new.rewrite_at(old_span, rcx)
+    }
+}
+
+fn rewrite_args(
+    old: &[Arg],
+    new: &[Arg],
+    (open, close): (Span, Span),
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    if old.len() == new.len() {
+        return Rewrite::rewrite(old, new, rcx);
+    }
+
+    if old.is_empty() {
+        let at = close.shrink_to_lo();
+        for (i, arg) in new.iter().enumerate() {
+            if i > 0 {
+                record_insert(at, ", ", rcx.borrow());
+            }
+            if !splice_arg(arg, at, rcx.borrow()) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    if new.is_empty() {
+        record_delete(open.between(close), rcx);
+        return true;
+    }
+
+    let old_spans = old.iter().map(arg_span).collect::<Vec<_>>();
+    if !old_spans.iter().all(|&sp| is_rewritable(sp)) {
+        return false;
+    }
+    let old_keys = old.iter().map(|a| a.id).collect::<Vec<_>>();
+    let new_keys = new
+        .iter()
+        .map(|a| rcx.new_to_old_id(a.id))
+        .collect::<Vec<_>>();
+    rewrite_list(
+        old,
+        new,
+        &old_spans,
+        &old_keys,
+        &new_keys,
+        ListSep::Comma,
+        splice_arg,
+        rcx,
+    )
+}
+
+fn rewrite_bare_fn(old: &Ty, fn1: &BareFnTy, fn2: &BareFnTy, mut rcx: RewriteCtxtRef) -> bool {
+    if fn1.unsafety != fn2.unsafety
+        || fn1.abi != fn2.abi
+        || fn1.decl.c_variadic != fn2.decl.c_variadic
+    {
+        return false;
+    }
+    if !Rewrite::rewrite(&fn1.generic_params, &fn2.generic_params, rcx.borrow()) {
+        return false;
+    }
+
+    let parens = match find_arg_parens(old, &rcx) {
+        Some(x) => x,
+        None => return false,
+    };
+    if !rewrite_args(&fn1.decl.inputs, &fn2.decl.inputs, parens, rcx.borrow()) {
+        return false;
+    }
+
+    let (_, close) = parens;
+    match (&fn1.decl.output, &fn2.decl.output) {
+        (FunctionRetTy::Ty(ty1), FunctionRetTy::Ty(ty2)) => Rewrite::rewrite(ty1, ty2, rcx),
+        (FunctionRetTy::Default(_), FunctionRetTy::Default(_)) => true,
+        (FunctionRetTy::Ty(_), FunctionRetTy::Default(_)) => {
+            record_delete(close.between(old.span.shrink_to_hi()), rcx);
+            true
+        }
+        (FunctionRetTy::Default(_), FunctionRetTy::Ty(ty2)) => {
+            let at = close.shrink_to_hi();
+            record_insert(at, " -> ", rcx.borrow());
+            ty2.rewrite_at(at, rcx)
+        }
+    }
+}
+
+fn bound_span(b: &GenericBound) -> Span {
+    b.span()
+}
+
+fn splice_bound(new: &GenericBound, old_span: Span, mut rcx: RewriteCtxtRef) -> bool {
+    let text = pprust::bounds_to_string(&[new.clone()]);
+    info!("REWRITE (TY) {}", describe(rcx.session(), old_span));
+    info!("   INTO (TY) {:?}", text);
+    rcx.record_text(old_span, &text);
+    true
+}
+
+/// Check whether `new` contains the same bounds as `old`, in a different order.
+fn is_reordering(old: &[GenericBound], new: &[GenericBound]) -> bool {
+    if old.len() != new.len() {
+        return false;
+    }
+    let mut used = vec![false; old.len()];
+    new.iter().all(|b2| {
+        let idx = old
+            .iter()
+            .zip(&used)
+            .position(|(b1, &u)| !u && b1.ast_equiv(b2));
+        match idx {
+            Some(i) => {
+                used[i] = true;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+fn rewrite_bounds(old: &GenericBounds, new: &GenericBounds, mut rcx: RewriteCtxtRef) -> bool {
+    if old.is_empty() || new.is_empty() {
+        return Rewrite::rewrite(old, new, rcx);
+    }
+
+    let old_spans = old.iter().map(bound_span).collect::<Vec<_>>();
+    if !old_spans.iter().all(|&sp| is_rewritable(sp)) {
+        return false;
+    }
+
+    // The order of bounds has no meaning, and transforms that rebuild a bound list don't always
+    // keep it, so a list that only moves bounds around keeps its old text.
+    if is_reordering(old, new) {
+        return true;
+    }
+
+    if old.len() == new.len() {
+        // Each bound is rewritten on its own, so a bound that can't be rewritten in place, as when
+        // `Iterator` becomes `Iterator<Item = u32>`, is reprinted without reprinting the others.
+        for ((b1, b2), &sp) in old.iter().zip(new.iter()).zip(&old_spans) {
+            if !rcx.with_temp_rewrites(|rcx| Rewrite::rewrite(b1, b2, rcx))
+                && !splice_bound(b2, sp, rcx.borrow())
+            {
+                return false;
+            }
+        }
+        return true;
+    }
+    let old_keys = old.iter().map(EquivKey).collect::<Vec<_>>();
+    let new_keys = new.iter().map(EquivKey).collect::<Vec<_>>();
+    rewrite_list(
+        old,
+        new,
+        &old_spans,
+        &old_keys,
+        &new_keys,
+        ListSep::Plus,
+        splice_bound,
+        rcx,
+    )
+}
+
+/// Add or remove the `dyn` keyword of the trait object type `old`.
+fn rewrite_dyn(
+    old: &Ty,
+    syntax1: TraitObjectSyntax,
+    syntax2: TraitObjectSyntax,
+    rcx: RewriteCtxtRef,
+) -> bool {
+    match (syntax1, syntax2) {
+        (TraitObjectSyntax::Dyn, TraitObjectSyntax::None) => {
+            let tokens = match span_tokens(rcx.session(), old.span) {
+                Some(x) => x,
+                None => return false,
+            };
+            match tokens.first() {
+                Some(&(Token::Ident(ident, false), sp)) if ident.name == keywords::Dyn.name() => {
+                    let sp = extend_span_spaces(rcx.session(), sp);
+                    record_delete(sp, rcx);
+                    true
+                }
+                _ => false,
+            }
+        }
+        (TraitObjectSyntax::None, TraitObjectSyntax::Dyn) => {
+            record_insert(old.span.shrink_to_lo(), "dyn ", rcx);
+            true
+        }
+        _ => true,
+    }
+}
+
+/// Make the lifetime of the reference type `old` explicit, or elide it.
+fn rewrite_ref_lifetime(
+    old: &Ty,
+    lt1: &Option<Lifetime>,
+    lt2: &Option<Lifetime>,
+    rcx: RewriteCtxtRef,
+) -> bool {
+    match (lt1, lt2) {
+        (Some(lt1), Some(lt2)) => Rewrite::rewrite(lt1, lt2, rcx),
+        (None, None) => true,
+        (Some(lt1), None) => {
+            if !is_rewritable(lt1.ident.span) {
+                return false;
+            }
+            let sp = extend_span_spaces(rcx.session(), lt1.ident.span);
+            record_delete(sp, rcx);
+            true
+        }
+        (None, Some(lt2)) => {
+            let tokens = match span_tokens(rcx.session(), old.span) {
+                Some(x) => x,
+                None => return false,
+            };
+            // In `&&T`, the outer reference starts with a single `&&` token.  Either way, the
+            // lifetime goes right after the first `&`.
+            let at = match tokens.first() {
+                Some(&(Token::BinOp(BinOpToken::And), sp)) | Some(&(Token::AndAnd, sp)) => {
+                    sp.with_hi(sp.lo() + BytePos(1)).shrink_to_hi()
+                }
+                _ => return false,
+            };
+            record_insert(at, &format!("{} ", lt2.ident), rcx);
+            true
+        }
+    }
+}
+
+/// Add or remove the `mut` of the reference type `old`.
+fn rewrite_ref_mutbl(old: &Ty, mt1: &MutTy, mutbl2: Mutability, rcx: RewriteCtxtRef) -> bool {
+    match (mt1.mutbl, mutbl2) {
+        (Mutability::Immutable, Mutability::Mutable) => {
+            record_insert(mt1.ty.span.shrink_to_lo(), "mut ", rcx);
+            true
+        }
+        (Mutability::Mutable, Mutability::Immutable) => {
+            let tokens = match span_tokens(rcx.session(), old.span.until(mt1.ty.span)) {
+                Some(x) => x,
+                None => return false,
+            };
+            match tokens.iter().find(|&&(ref tok, _)| tok.is_keyword(keywords::Mut)) {
+                Some(&(_, mut_span)) => {
+                    // Delete the keyword along with the space separating it from the pointee.
+                    record_delete(mut_span.until(mt1.ty.span), rcx);
+                    true
+                }
+                None => false,
+            }
+        }
+        (_, _) => true,
+    }
+}
+
+/// Get the text of the raw pointer or reference type `ty` that comes before the pointee type,
+/// such as `*const ` or `&'a mut `.
+fn pointer_prefix(ty: &Ty) -> Option<String> {
+    match ty.node {
+        TyKind::Ptr(ref mt) => Some(match mt.mutbl {
+            Mutability::Mutable => "*mut ".to_owned(),
+            Mutability::Immutable => "*const ".to_owned(),
+        }),
+        TyKind::Rptr(ref lt, ref mt) => {
+            let mut s = "&".to_owned();
+            if let Some(ref lt) = *lt {
+                s.push_str(&format!("{} ", lt.ident));
+            }
+            if mt.mutbl == Mutability::Mutable {
+                s.push_str("mut ");
+            }
+            Some(s)
+        }
+        _ => None,
+    }
+}
+
+/// Rewrite the raw pointer or reference type `old` into `new`, a raw pointer when `old` is a
+/// reference or vice versa, or a raw pointer of the other mutability.  The pointee type is
+/// rewritten on its own, and only the text before it, such as the `*mut ` of `*mut T`, is
+/// replaced.
+fn rewrite_pointer_prefix(
+    old: &Ty,
+    mt1: &MutTy,
+    new: &Ty,
+    mt2: &MutTy,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    if !is_rewritable(mt1.ty.span) || !old.span.contains(mt1.ty.span) {
+        return false;
+    }
+    let text = match pointer_prefix(new) {
+        Some(x) => x,
+        None => return false,
+    };
+    if !Rewrite::rewrite(&mt1.ty, &mt2.ty, rcx.borrow()) {
+        return false;
+    }
+
+    if pointer_prefix(old).as_ref() != Some(&text) {
+        let mut prefix = old.span.until(mt1.ty.span);
+        let mut text = &text[..];
+        // Keep the old spacing before the pointee if the new prefix needs a space there too, as
+        // in `*const  T` to `*mut  T`.  A bare `&` gets no space.
+        if let Ok(snippet) = rcx.session().source_map().span_to_snippet(prefix) {
+            let spaces = snippet.len() - snippet.trim_end().len();
+            if spaces > 0 && text.ends_with(' ') {
+                prefix = prefix.with_hi(prefix.hi() - BytePos(spaces as u32));
+                text = text.trim_end();
+            }
+        }
+        info!("REWRITE (TY) {}", describe(rcx.session(), prefix));
+        info!("   INTO (TY) {:?}", text);
+        rcx.record_text(prefix, &text);
+    }
+    true
+}
+
+fn splice_ty(new: &P<Ty>, old_span: Span, rcx: RewriteCtxtRef) -> bool {
+    new.rewrite_at(old_span, rcx)
+}
+
+/// Rewrite the elements of the tuple type `old`.  This is only needed when the number of elements
+/// changes, since `recursive` handles the rest.  A one-element tuple type needs a trailing comma,
+/// as in `(A,)`, which is added if the old text doesn't have one.
+fn rewrite_tup(old: &Ty, elems1: &[P<Ty>], elems2: &[P<Ty>], mut rcx: RewriteCtxtRef) -> bool {
+    let tokens = match span_tokens(rcx.session(), old.span) {
+        Some(x) => x,
+        None => return false,
+    };
+    let (close, has_comma) = match tokens.last() {
+        Some(&(Token::CloseDelim(DelimToken::Paren), sp)) => {
+            let has_comma = tokens.len() > 1 && tokens[tokens.len() - 2].0 == Token::Comma;
+            (sp, has_comma)
+        }
+        _ => return false,
+    };
+    let at = close.shrink_to_lo();
+
+    if elems1.is_empty() {
+        for (i, ty) in elems2.iter().enumerate() {
+            if i > 0 {
+                record_insert(at, ", ", rcx.borrow());
+            }
+            if !splice_ty(ty, at, rcx.borrow()) {
+                return false;
+            }
+        }
+    } else if elems2.is_empty() {
+        record_delete(elems1[0].span.until(close), rcx.borrow());
+        return true;
+    } else {
+        let old_spans = elems1.iter().map(|ty| ty.span).collect::<Vec<_>>();
+        if !old_spans.iter().all(|&sp| is_rewritable(sp)) {
+            return false;
+        }
+        let old_keys = elems1.iter().map(|ty| ty.id).collect::<Vec<_>>();
+        let new_keys = elems2
+            .iter()
+            .map(|ty| rcx.new_to_old_id(ty.id))
+            .collect::<Vec<_>>();
+        let ok = rewrite_list(
+            elems1,
+            elems2,
+            &old_spans,
+            &old_keys,
+            &new_keys,
+            ListSep::Comma,
+            splice_ty,
+            rcx.borrow(),
+        );
+        if !ok {
+            return false;
+        }
+    }
+
+    if elems2.len() == 1 && (elems1.is_empty() || !has_comma) {
+        record_insert(at, ",", rcx);
+    }
+    true
+}
+
+/// Rewrite the qualified path type `<T as Trait>::Assoc` (or `<T>::Assoc`) into another with the
+/// same number of segments after the `<T as`.  The self type, each segment's name, and each
+/// segment's generic arguments are rewritten separately, so the `<`, `as`, `>::`, and the text
+/// between them are kept exactly.
+fn rewrite_qpath(
+    qself1: &QSelf,
+    path1: &Path,
+    qself2: &QSelf,
+    path2: &Path,
+    mut rcx: RewriteCtxtRef,
+) -> bool {
+    if qself1.position != qself2.position || path1.segments.len() != path2.segments.len() {
+        return false;
+    }
+    if !Rewrite::rewrite(&qself1.ty, &qself2.ty, rcx.borrow()) {
+        return false;
+    }
+
+    for (seg1, seg2) in path1.segments.iter().zip(&path2.segments) {
+        if seg1.ident.name != seg2.ident.name {
+            if !is_rewritable(seg1.ident.span) {
+                return false;
+            }
+            let text = seg2.ident.to_string();
+            info!("REWRITE (TY) {}", describe(rcx.session(), seg1.ident.span));
+            info!("   INTO (TY) {}", text);
+            rcx.record_text(seg1.ident.span, &text);
+        }
+        if !Rewrite::rewrite(&seg1.args, &seg2.args, rcx.borrow()) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Get the span of the closing `]` of the array or slice type `old`.
+fn close_bracket_span(old: &Ty, rcx: &RewriteCtxtRef) -> Option<Span> {
+    let tokens = span_tokens(rcx.session(), old.span)?;
+    match tokens.last() {
+        Some(&(Token::CloseDelim(DelimToken::Bracket), sp)) => Some(sp),
+        _ => None,
+    }
+}
+
+pub fn rewrite(old: &Ty, new: &Ty, mut rcx: RewriteCtxtRef) -> bool {
+    if !is_rewritable(old.span) {
+        return false;
+    }
+
+    match (&old.node, &new.node) {
+        (&TyKind::BareFn(ref fn1), &TyKind::BareFn(ref fn2)) => {
+            rewrite_bare_fn(old, fn1, fn2, rcx)
+        }
+
+        (
+            &TyKind::TraitObject(ref bounds1, syntax1),
+            &TyKind::TraitObject(ref bounds2, syntax2),
+        ) => {
+            rewrite_dyn(old, syntax1, syntax2, rcx.borrow())
+                && rewrite_bounds(bounds1, bounds2, rcx)
+        }
+
+        (&TyKind::ImplTrait(_, ref bounds1), &TyKind::ImplTrait(_, ref bounds2)) => {
+            rewrite_bounds(bounds1, bounds2, rcx)
+        }
+
+        (&TyKind::Rptr(ref lt1, ref mt1), &TyKind::Rptr(ref lt2, ref mt2)) => {
+            rewrite_ref_lifetime(old, lt1, lt2, rcx.borrow())
+                && rewrite_ref_mutbl(old, mt1, mt2.mutbl, rcx.borrow())
+                && Rewrite::rewrite(&mt1.ty, &mt2.ty, rcx)
+        }
+
+        (&TyKind::Ptr(ref mt1), &TyKind::Ptr(ref mt2))
+        | (&TyKind::Ptr(ref mt1), &TyKind::Rptr(_, ref mt2))
+        | (&TyKind::Rptr(_, ref mt1), &TyKind::Ptr(ref mt2)) => {
+            rewrite_pointer_prefix(old, mt1, new, mt2, rcx)
+        }
+
+        (
+            &TyKind::Path(Some(ref qself1), ref path1),
+            &TyKind::Path(Some(ref qself2), ref path2),
+        ) => rewrite_qpath(qself1, path1, qself2, path2, rcx),
+
+        (&TyKind::Tup(ref elems1), &TyKind::Tup(ref elems2)) => {
+            rewrite_tup(old, elems1, elems2, rcx)
+        }
+
+        (&TyKind::Array(ref elem1, _), &TyKind::Slice(ref elem2)) => {
+            let close = match close_bracket_span(old, &rcx) {
+                Some(x) => x,
+                None => return false,
+            };
+            if !Rewrite::rewrite(elem1, elem2, rcx.borrow()) {
+                return false;
+            }
+            record_delete(elem1.span.between(close), rcx);
+            true
+        }
+
+        (&TyKind::Slice(ref elem1), &TyKind::Array(ref elem2, ref len2)) => {
+            let close = match close_bracket_span(old, &rcx) {
+                Some(x) => x,
+                None => return false,
+            };
+            if !Rewrite::rewrite(elem1, elem2, rcx.borrow()) {
+                return false;
+            }
+            let at = close.shrink_to_lo();
+            record_insert(at, "; ", rcx.borrow());
+            len2.value.rewrite_at(at, rcx)
+        }
+
+        (_, _) => false,
+    }
+}
@@ -0,0 +1,57 @@
+//! Rewriting strategy for adding or removing the discriminant of an enum variant.
+//!
+//! The explicit discriminant of a variant (`Red = 1`) is optional, so adding one to a variant or
+//! removing one makes `recursive` fail, and the whole enum gets reprinted.  This strategy rewrites
+//! the rest of the variant as `recursive` would, and inserts or deletes only the ` = 1` part.
+//! Other variants, with or without discriminants, are left alone.  (Changing the value of an
+//! existing discriminant doesn't need this: `recursive` rewrites just the value expression.)
+//!
+//! It also handles attributes added to a variant that had none, such as a new doc comment, which
+//! `recursive` can't place.  They go at the start of the variant, on lines of their own if needed.
+use syntax::ast::*;
+
+use crate::rewrite::base::{describe, is_rewritable, MaybeRewriteSeq};
+use crate::rewrite::strategy::print::RewriteAt;
+use crate::rewrite::{Rewrite, RewriteCtxtRef};
+
+pub fn rewrite(old: &Variant, new: &Variant, mut rcx: RewriteCtxtRef) -> bool {
+    if !is_rewritable(old.span) {
+        return false;
+    }
+    let (v1, v2) = (&old.node, &new.node);
+
+    if !Rewrite::rewrite(&v1.ident, &v2.ident, rcx.borrow())
+        || !MaybeRewriteSeq::maybe_rewrite_seq(
+            &v1.attrs,
+            &v2.attrs,
+            v1.ident.span.shrink_to_lo(),
+            rcx.borrow(),
+        )
+        || !Rewrite::rewrite(&v1.data, &v2.data, rcx.borrow())
+    {
+        return false;
+    }
+
+    match (&v1.disr_expr, &v2.disr_expr) {
+        (&Some(ref disr1), &Some(ref disr2)) => Rewrite::rewrite(disr1, disr2, rcx),
+        (&None, &None) => true,
+        (&None, &Some(ref disr2)) => {
+            let at = old.span.shrink_to_hi();
+            info!("INSERT (VARIANT) {}", describe(rcx.session(), at));
+            rcx.record_text(at, " = ");
+            disr2.value.rewrite_at(at, rcx)
+        }
+        (&Some(_), &None) => {
+            // Only fieldless variants can have discriminants, so the discriminant follows the
+            // ident directly.
+            match v1.data {
+                VariantData::Unit(_) => {}
+                _ => return false,
+            }
+            let sp = v1.ident.span.between(old.span.shrink_to_hi());
+            info!("DELETE (VARIANT) {}", describe(rcx.session(), sp));
+            rcx.record_text(sp, "");
+            true
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Rewriting strategy for changes to `Visibility`.
+//!
+//! Unlike most qualifiers, visibility has a span of its own in the AST, even when it is implicit:
+//! the span of an `Inherited` visibility is empty and sits just before the next token of the
+//! item.  This lets us replace only the visibility text (including any path in a
+//! `pub(in some::path)` qualifier), leaving the rest of the item, field, etc. untouched.
+use syntax::ast::*;
+use syntax::print::pprust;
+
+use crate::rewrite::base::{describe, extend_span_spaces, is_rewritable};
+use crate::rewrite::RewriteCtxtRef;
+
+pub fn rewrite(old: &Visibility, new: &Visibility, mut rcx: RewriteCtxtRef) -> bool {
+    if !is_rewritable(old.span) {
+        return false;
+    }
+
+    // The printed visibility includes a trailing space, unless it's empty.
+    let printed = pprust::vis_to_string(new);
+    let text = printed.trim_end();
+
+    let (old_span, text) = if text.is_empty() {
+        // Deleting the visibility.  Also delete the space that separated it from the next token.
+        (extend_span_spaces(rcx.session(), old.span), String::new())
+    } else if old.span.lo() == old.span.hi() {
+        // Inserting a visibility where there was none.  Add a space before the next token.
+        (old.span, format!("{} ", text))
+    } else {
+        (old.span, text.to_owned())
+    };
+
+    info!("REWRITE (VIS) {}", describe(rcx.session(), old_span));
+    info!("   INTO (VIS) {:?}", text);
+    rcx.record_text(old_span, &text);
+    true
+}
@@ -0,0 +1,91 @@
+//! Helpers for the unit tests of the rewrite modules.
+use rustc::session::config::Options;
+use rustc::session::{build_session, Session};
+use rustc_errors::registry::Registry;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use syntax::ast::{Crate, Expr, ExprKind, ItemKind, NodeId, StmtKind, UnOp, DUMMY_NODE_ID};
+use syntax::parse;
+use syntax::ptr::P;
+use syntax::ThinVec;
+use syntax_pos::{BytePos, FileName, Pos, Span, SyntaxContext, DUMMY_SP};
+
+use crate::ast_manip::number_nodes::number_nodes;
+use crate::ast_manip::ListNodeIds;
+
+/// Parse `src` as the file `test.rs` in a fresh session, number the nodes of the resulting crate,
+/// and pass both to `f`.
+pub fn with_crate<R>(src: &str, f: impl FnOnce(&Session, Crate) -> R) -> R {
+    syntax::with_globals(|| {
+        let sess = build_session(Options::default(), None, Registry::new(&[]));
+        let mut krate = parse::parse_crate_from_source_str(
+            FileName::Real(PathBuf::from("test.rs")),
+            src.to_owned(),
+            &sess.parse_sess,
+        )
+        .unwrap_or_else(|mut db| {
+            db.emit();
+            panic!("failed to parse test crate")
+        });
+        number_nodes(&mut krate);
+        f(&sess, krate)
+    })
+}
+
+/// Get the span of the first occurrence of `text` in `src`, the source of the crate that
+/// `with_crate` parsed in `sess`.
+pub fn span_of(sess: &Session, src: &str, text: &str) -> Span {
+    let sf = sess
+        .source_map()
+        .get_source_file(&FileName::Real(PathBuf::from("test.rs")))
+        .unwrap();
+    let lo = sf.start_pos + BytePos::from_usize(src.find(text).unwrap());
+    Span::new(lo, lo + BytePos::from_usize(text.len()), SyntaxContext::empty())
+}
+
+/// Build a `node_id_map` that matches each node of `old` with the new node of the same ID, as when
+/// the new crate was edited from a clone of `old`.
+pub fn same_ids(old: &Crate) -> HashMap<NodeId, NodeId> {
+    old.list_node_ids().into_iter().map(|id| (id, id)).collect()
+}
+
+/// Get the elements of the tuple expression statement that starts the first function.
+fn tuple_elems(krate: &mut Crate) -> &mut Vec<P<Expr>> {
+    let e = match krate.module.items[0].node {
+        ItemKind::Fn(_, _, _, ref mut block) => match block.stmts[0].node {
+            StmtKind::Semi(ref mut e) => e,
+            _ => panic!("expected an expression statement"),
+        },
+        _ => panic!("expected a function"),
+    };
+    match e.node {
+        ExprKind::Tup(ref mut elems) => elems,
+        _ => panic!("expected a tuple"),
+    }
+}
+
+/// Set up a rewrite that changes the same text in two different ways.  The old crate is
+/// `fn f() { (1, 2); }`, with the second element of the tuple given the span of the first, as
+/// macro expansion does for `($e, $e)`.  The new crate negates both elements, one with `-` and one
+/// with `!`.  `f` gets the session, the old and new crates, and the span of `1`.
+pub fn with_overlapping_edits<R>(f: impl FnOnce(&Session, &Crate, &Crate, Span) -> R) -> R {
+    with_crate("fn f() { (1, 2); }", |sess, mut old| {
+        let span = {
+            let elems = tuple_elems(&mut old);
+            elems[1].span = elems[0].span;
+            elems[0].span
+        };
+
+        let mut new = old.clone();
+        for (e, &op) in tuple_elems(&mut new).iter_mut().zip(&[UnOp::Neg, UnOp::Not]) {
+            *e = P(Expr {
+                id: DUMMY_NODE_ID,
+                node: ExprKind::Unary(op, e.clone()),
+                span: DUMMY_SP,
+                attrs: ThinVec::new(),
+            });
+        }
+
+        f(sess, &old, &new, span)
+    })
+}
@@ -0,0 +1,90 @@
+//! Conversion of `TextRewrite`s into edits in the format of the Language Server Protocol's
+//! `TextEdit`.
+//!
+//! LSP positions are zero-based line and column numbers, where the column counts UTF-16 code
+//! units rather than bytes or `char`s.  This lets an editor apply a rewrite directly to its copy of
+//! the file.
+use rustc::session::Session;
+use std::collections::HashMap;
+use syntax::source_map::SourceFile;
+use syntax_pos::{BytePos, FileName};
+
+use crate::rewrite::files::{rewrite_file_edits, rewrites_by_file, Indent};
+use crate::rewrite::TextRewrite;
+
+/// A position in a file, as in the LSP `Position` type.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Position {
+    /// Zero-based line number.
+    pub line: u32,
+    /// Zero-based offset within the line, in UTF-16 code units.
+    pub character: u32,
+}
+
+/// A range of text between two `Position`s, as in the LSP `Range` type.  `end` is exclusive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// A replacement of the text in `range` with `new_text`, as in the LSP `TextEdit` type.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TextEdit {
+    pub range: Range,
+    pub new_text: String,
+}
+
+/// Convert the result of `rewrite` into a list of `TextEdit`s for each file it changes.  The
+/// edits for each file are sorted and don't overlap, so they can be applied as a single LSP
+/// `WorkspaceEdit`.  Rewrites of virtual files are skipped, as in `rewrite_files_with`.
+pub fn rewrites_to_text_edits(
+    sess: &Session,
+    rw: &TextRewrite,
+) -> HashMap<FileName, Vec<TextEdit>> {
+    let cm = sess.source_map();
+    let mut edits = HashMap::new();
+
+    for (rewrites, _nodes, sf) in rewrites_by_file(cm, rw) {
+        match sf.name {
+            FileName::Real(_) => {}
+            _ => {
+                warn!("can't produce edits for virtual file {:?}", sf.name);
+                continue;
+            }
+        }
+
+        let file_edits = rewrite_file_edits(cm, &sf, rewrites, Indent::default())
+            .into_iter()
+            .map(|(span, new_text)| TextEdit {
+                range: Range {
+                    start: position(&sf, span.lo()),
+                    end: position(&sf, span.hi()),
+                },
+                new_text,
+            })
+            .collect();
+        edits.insert(sf.name.clone(), file_edits);
+    }
+
+    edits
+}
+
+/// Get the LSP position of `pos`, which must lie within `sf`.
+fn position(sf: &SourceFile, pos: BytePos) -> Position {
+    let src = sf
+        .src
+        .as_ref()
+        .unwrap_or_else(|| panic!("source of file {} is not available", sf.name));
+    // `lookup_line` fails only for a file with no lines, which must be empty.
+    let line = match sf.lookup_line(pos) {
+        Some(x) => x,
+        None => return Position { line: 0, character: 0 },
+    };
+    let line_start = (sf.lines[line] - sf.start_pos).0 as usize;
+    let offset = (pos - sf.start_pos).0 as usize;
+    Position {
+        line: line as u32,
+        character: src[line_start..offset].encode_utf16().count() as u32,
+    }
+}
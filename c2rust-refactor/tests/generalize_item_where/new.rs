@@ -0,0 +1,12 @@
+fn f<U, V>(x: V, y: U)
+where
+    // The where clause is kept as written.
+    U: Clone,
+{
+}
+
+fn g<V>(x: V) {
+}
+
+fn main() {
+}
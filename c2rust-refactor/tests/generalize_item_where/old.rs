@@ -0,0 +1,12 @@
+fn f<U>(x: i16, y: U)
+where
+    // The where clause is kept as written.
+    U: Clone,
+{
+}
+
+fn g(x: i16) {
+}
+
+fn main() {
+}
@@ -0,0 +1,3 @@
+// Deleting the last item must not leave blank lines at the end of the file.
+
+fn main() {}
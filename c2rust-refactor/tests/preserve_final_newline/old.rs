@@ -0,0 +1,5 @@
+// Deleting the last item must not leave blank lines at the end of the file.
+
+fn main() {}
+
+fn unused() {}
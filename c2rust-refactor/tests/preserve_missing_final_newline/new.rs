@@ -0,0 +1,3 @@
+// This file has no final newline, and deleting the last item must not add one.
+
+fn main() {}
\ No newline at end of file
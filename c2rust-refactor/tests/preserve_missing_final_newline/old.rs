@@ -0,0 +1,5 @@
+// This file has no final newline, and deleting the last item must not add one.
+
+fn main() {}
+
+fn unused() {}
\ No newline at end of file
@@ -0,0 +1,14 @@
+// The block is recovered inside the new method call.  Only the tokens of the macro inside it
+// change, which must not be mistaken for the block being unchanged.
+
+fn wrap<T>(x: T) -> T {
+    x
+}
+
+fn main() {
+    let y = 2;
+    let _s = {
+        let msg = format!( "{:>4}" , y + 1);
+        msg
+    }.clone();
+}
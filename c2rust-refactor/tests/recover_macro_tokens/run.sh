@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'y' 'y + 1' \; \
+    rewrite_expr 'wrap($e:Expr)' '$e.clone()' \
+    -- old.rs $rustflags
@@ -0,0 +1,22 @@
+#![feature(custom_attribute, attr_literals)]
+
+// New outer attributes must be added after the existing outer ones, not next to the inner
+// attributes, which would make them apply to something else.
+#[allow(dead_code)]
+mod rc {
+    #![allow(unused_mut)]
+
+    pub struct RefCounted {
+        ref_count: usize,
+        data: i32,
+    }
+
+    #[inline]
+    pub unsafe fn inc_ref(rc: *mut RefCounted) -> *mut RefCounted {
+        #![allow(unused_unsafe)]
+        (*rc).ref_count += 1;
+        rc
+    }
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use std::rc::Rc;
+
+// The odd spacing inside each type is meant to detect (and fail the test) if rewriting resorts
+// to reprinting the bounds.  Only reordering the bounds keeps their old order.
+
+fn f(_x: Rc<dyn  Send  +  Sync>) {}
+
+fn main() {}
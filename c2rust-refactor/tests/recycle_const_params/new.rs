@@ -0,0 +1,9 @@
+#![feature(const_generics)]
+
+struct S</* len */ const N: usize, T> {
+    x: T,
+    y: i32,
+}
+
+fn main() {
+}
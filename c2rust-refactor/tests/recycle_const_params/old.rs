@@ -0,0 +1,9 @@
+#![feature(const_generics)]
+
+struct S</* len */ const N: usize> {
+    x: i16,
+    y: i32,
+}
+
+fn main() {
+}
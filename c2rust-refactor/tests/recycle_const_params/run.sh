@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(match_ty(i16));' \; \
+    select target 'crate; desc(struct && name("S"));' \; \
+    generalize_items \
+    -- old.rs $rustflags
@@ -0,0 +1,38 @@
+struct Inner {
+    d: i32,
+}
+
+struct Mid {
+    c: Inner,
+}
+
+impl Mid {
+    fn get(&self) -> &Inner {
+        &self.c
+    }
+}
+
+struct Outer {
+    b: Mid,
+}
+
+impl Outer {
+    fn mid(&self, _n: i32) -> &Mid {
+        &self.b
+    }
+}
+
+fn main() {
+    let a = Outer {
+        b: Mid {
+            c: Inner { d: 1 },
+        },
+    };
+    let _x = a.b // the mid
+        .get() // the inner
+        .d;
+    let _y = a
+        .mid(1) // by method
+        .c // by field
+        .d;
+}
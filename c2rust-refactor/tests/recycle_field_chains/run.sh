@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr '$e:Expr.get()' '$e.c' \; \
+    rewrite_expr '$e:Expr.mid($n:Expr)' '$e.b' \
+    -- old.rs $rustflags
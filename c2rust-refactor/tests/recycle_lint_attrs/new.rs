@@ -0,0 +1,19 @@
+#![feature(custom_attribute, attr_literals)]
+
+// Lint attributes keep their text, including the order of the lints, when other attributes are
+// added next to them.
+pub struct Flags {
+    #[allow(unused_mut, dead_code)]
+    #[ownership_static()]
+    bits: u32,
+    #[warn(missing_docs)]
+    #[allow(non_snake_case, dead_code)]
+    #[ownership_static()]
+    Mask: u32,
+    #[deny(unused)]
+    #[allow(non_camel_case_types, unused_parens, dead_code)]
+    #[ownership_static()]
+    extra: u8,
+}
+
+fn main() {}
@@ -0,0 +1,23 @@
+fn inc(x: u32) -> u32 {
+    x + 1
+}
+
+fn main() {
+    let v = [1u32, 2, 3];
+    let mut i = 0;
+    while  i <  3 {
+        i = i + 1;
+    }
+    'outer: for  &( x ) in v .iter() {
+        let _y = x + 1;
+        if x > 1 { break 'outer; }
+    }
+    while let Some( j ) = Some( i ) {
+        i = j + 1;
+        break;
+    }
+    loop {
+        i = i + 1;
+        if i > 10 { break; }
+    }
+}
@@ -0,0 +1,23 @@
+fn inc(x: u32) -> u32 {
+    x + 1
+}
+
+fn main() {
+    let v = [1u32, 2, 3];
+    let mut i = 0;
+    while  i <  3 {
+        i = inc(i);
+    }
+    'outer: for  &( x ) in v .iter() {
+        let _y = inc(x);
+        if x > 1 { break 'outer; }
+    }
+    while let Some( j ) = Some( i ) {
+        i = inc(j);
+        break;
+    }
+    loop {
+        i = inc(i);
+        if i > 10 { break; }
+    }
+}
@@ -0,0 +1,22 @@
+#![feature(custom_attribute, attr_literals)]
+
+// Attributes added to documented fields go after the doc comments, on lines of their own, instead
+// of being commented out.
+pub struct RefCounted {
+    /// The number of references.
+    #[ownership_static()]
+    ref_count: usize,
+    /// The value.
+    ///
+    /// Never zero.
+    #[allow(dead_code)]
+    #[ownership_static()]
+    data: i32,
+    /** Unused. */
+    #[ownership_static()]
+    pad: u8,
+    #[ownership_static()]
+    flags: u8,
+}
+
+fn main() {}
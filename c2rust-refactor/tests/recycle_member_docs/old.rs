@@ -0,0 +1,18 @@
+#![feature(custom_attribute, attr_literals)]
+
+// Attributes added to documented fields go after the doc comments, on lines of their own, instead
+// of being commented out.
+pub struct RefCounted {
+    /// The number of references.
+    ref_count: usize,
+    /// The value.
+    ///
+    /// Never zero.
+    #[allow(dead_code)]
+    data: i32,
+    /** Unused. */
+    pad: u8,
+    flags: u8,
+}
+
+fn main() {}
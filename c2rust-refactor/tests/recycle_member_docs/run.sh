@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select box 'crate; desc(match_ty(*mut __t));' \; \
+    select ann 'crate; desc(field);' \; \
+    ownership_annotate ann \
+    -- old.rs $rustflags
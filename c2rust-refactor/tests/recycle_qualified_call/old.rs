@@ -0,0 +1,9 @@
+fn main() {
+    let n = 4;
+    // Only the argument changes, so the qualified callee and its turbofish keep their text.
+    let v = Vec::<u8 /* bytes */>::with_capacity(n);
+    let w = Vec::<u8>::with_capacity(
+        n * 2, // doubled
+    );
+    println!("{} {}", v.capacity(), w.capacity());
+}
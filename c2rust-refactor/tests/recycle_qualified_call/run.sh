@@ -0,0 +1,10 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'Vec::<u8>::with_capacity($n:Expr)' 'Vec::<u8>::with_capacity($n + 1)' \
+    -- old.rs $rustflags
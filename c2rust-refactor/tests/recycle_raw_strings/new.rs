@@ -0,0 +1,8 @@
+fn pair<A, B>(_a: A, _b: B) {}
+fn tag(_a: u8) {}
+fn tag_bytes(_a: u8, _b: &[u8]) {}
+
+fn main() {
+    pair(br#"b "quote""#, r##"a "# quote"##);
+    tag_bytes(1, br##"say "#hi"##);
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'pair($a:Expr, $b:Expr)' 'pair($b, $a)' \; \
+    rewrite_expr 'tag($a:Expr)' 'tag_bytes($a, br##"say "#hi"##)' \
+    -- old.rs $rustflags
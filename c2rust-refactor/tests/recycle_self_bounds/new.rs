@@ -0,0 +1,10 @@
+// Making the methods unsafe reprints the trait, but the bounds on `Self` should keep their
+// original formatting.
+trait T {
+    unsafe fn a(&self) -> u8 where Self :  Sized { 1 }
+    unsafe fn b(&self) -> u8 where Self :  Sized {
+        1
+    }
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+// Making the methods unsafe reprints the trait, but the bounds on `Self` should keep their
+// original formatting.
+trait T {
+    fn a(&self) -> u8 where  Self :  Sized  { 1 }
+    fn b(&self) -> u8
+    where
+        Self :  Sized,
+    {
+        1
+    }
+}
+
+fn main() {}
@@ -0,0 +1,20 @@
+struct Old {
+    x: i32,
+    y: i32,
+}
+
+struct New {
+    x: i32,
+    y: i32,
+}
+
+// Only the struct path changes, so only the path should be reprinted.  The odd spacing and the
+// comment are meant to detect (and fail the test) if rewriting reprints the whole literal.
+
+fn f() {
+    let _s = Old {  x:  1,
+        // the second field
+        y:  2 + 3 };
+}
+
+fn main() {}
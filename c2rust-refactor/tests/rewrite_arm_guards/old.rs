@@ -0,0 +1,21 @@
+fn remove_guard(x: u8, y: u8) -> u8 {
+    match x {
+        1 if y  >  2 => {
+            // The body is kept as it is.
+            y  +  1
+        }
+        _ => 0,
+    }
+}
+
+fn add_guard(x: u8, y: u8) -> u8 {
+    match x {
+        1 => {
+            // The body is kept as it is.
+            y  *  2
+        }
+        _ => 1,
+    }
+}
+
+fn main() {}
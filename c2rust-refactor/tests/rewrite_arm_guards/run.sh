@@ -0,0 +1,13 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'match $x:Expr { $p:Pat if $g:Expr => $a:Expr, _ => 0 }' \
+        'match $x { $p => $a, _ => 0 }' \; \
+    rewrite_expr 'match $x:Expr { $p:Pat => $a:Expr, _ => 1 }' \
+        'match $x { $p if $x < 10 => $a, _ => 1 }' \
+    -- old.rs $rustflags
@@ -0,0 +1,20 @@
+fn add_alternative(x: u8, y: u8) -> u8 {
+    match x {
+        | 1 => {
+            // The body is kept as it is.
+            y  +  1
+        }
+        _ => 0,
+    }
+}
+
+fn remove_alternative(x: u8, y: u8) -> u8 {
+    match x {
+        1
+        | 2
+        | 3 => y,
+        _ => 0,
+    }
+}
+
+fn main() {}
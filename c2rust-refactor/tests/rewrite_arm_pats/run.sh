@@ -0,0 +1,13 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'match $x:Expr { $p:Pat => $a:Expr, _ => $b:Expr }' \
+        'match $x { $p | 9 => $a, _ => $b }' \; \
+    rewrite_expr 'match $x:Expr { $p:Pat | $q:Pat | $r:Pat => $a:Expr, _ => $b:Expr }' \
+        'match $x { $p | $r => $a, _ => $b }' \
+    -- old.rs $rustflags
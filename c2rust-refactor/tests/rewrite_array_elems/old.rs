@@ -0,0 +1,14 @@
+// The comments inside each literal are meant to detect (and fail the test) if rewriting resorts
+// to reprinting the entire literal.
+
+fn main() {
+    let a = 1;
+    let b = 2;
+    let _x = [a /* a */, b /* b */, a + b /* c */];
+    let _y = [
+        a, // first
+        b, // second
+        a * b, // third
+    ];
+    let _t = (a /* a */, b /* b */);
+}
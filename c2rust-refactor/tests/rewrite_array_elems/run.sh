@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr '[$x:Expr, $y:Expr, $z:Expr]' '[$x, $z]' \; \
+    rewrite_expr '($x:Expr, $y:Expr)' '($y, $x, $y)' \
+    -- old.rs $rustflags
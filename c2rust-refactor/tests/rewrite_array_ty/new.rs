@@ -0,0 +1,10 @@
+const BUF_LEN: usize = 16;
+
+static A: [i8; BUF_LEN] = [0; BUF_LEN];
+static B: [i8; 0x10] = [0; 0x10];
+
+fn f(_x: &[/* elem */ u16]) {}
+
+fn main() {
+    f(&[1, 2, 3, 4]);
+}
@@ -0,0 +1,10 @@
+// The comments inside each assignment are meant to detect (and fail the test) if rewriting resorts
+// to reprinting the entire expression.
+
+fn main() {
+    let mut a = 1;
+    let mut b = 2u32;
+    a /* a */ += 2;
+    b <<= /* count */ 1;
+    let _ = (a, b);
+}
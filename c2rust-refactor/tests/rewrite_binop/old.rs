@@ -0,0 +1,13 @@
+// The odd spacing around each operator is meant to detect (and fail the test) if rewriting
+// resorts to reprinting the entire expression.
+
+fn f(a: i32, b: i32, c: i32) -> bool {
+    let _x = a  -  b;
+    let _y = a  -  b  *  c;
+    let _z = a  -  (b  +  c);
+    a  -  b  ==  c
+}
+
+fn main() {
+    f(1, 2, 3);
+}
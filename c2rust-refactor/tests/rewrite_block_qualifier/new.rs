@@ -0,0 +1,14 @@
+#![feature(async_await)]
+
+// The odd spacing inside each call is meant to detect (and fail the test) if rewriting resorts
+// to reprinting the entire block.
+
+fn foo(x: i32) -> i32 {
+    x
+}
+
+fn main() {
+    let _a = async { foo( 1 ) };
+    let _b = async { foo( 2 ) };
+    let _c = { foo( 3 ) };
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr '{ foo(1) }' 'async { foo(1) }' \; \
+    rewrite_expr 'async move { foo(2) }' 'async { foo(2) }' \; \
+    rewrite_expr 'async { foo(3) }' '{ foo(3) }' \
+    -- old.rs $rustflags
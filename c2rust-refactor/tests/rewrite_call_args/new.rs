@@ -0,0 +1,10 @@
+fn g(a: i32, b: i32, c: i32) -> i32 {
+    a + b + c
+}
+
+fn main() {
+    let _x = g(
+        1, // one
+        3, // three
+    );
+}
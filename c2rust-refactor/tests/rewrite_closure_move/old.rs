@@ -0,0 +1,10 @@
+fn main() {
+    let v = vec![1, 2, 3];
+    // Only the qualifier changes, so the body keeps its comments.
+    let f = || {
+        // Sum the elements.
+        v.iter().sum::<i32>()
+    };
+    let g = |x: i32| x + 1;
+    println!("{} {}", f(), g(1));
+}
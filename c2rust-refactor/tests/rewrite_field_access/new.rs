@@ -0,0 +1,18 @@
+// The comments inside each base expression are meant to detect (and fail the test) if rewriting
+// resorts to reprinting the entire expression.
+
+struct P {
+    a: i32,
+    b: i32,
+}
+
+fn get(p: &P) -> &P {
+    p
+}
+
+fn main() {
+    let p = P { a: 1, b: 2 };
+    let t = (3, 4);
+    let _x = get( /* p */ &p ).b;
+    let _y = ( /* t */ t ).1;
+}
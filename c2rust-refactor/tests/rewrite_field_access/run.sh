@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr '$p:Expr.a' '$p.b' \; \
+    rewrite_expr '$t:Expr.0' '$t.1' \
+    -- old.rs $rustflags
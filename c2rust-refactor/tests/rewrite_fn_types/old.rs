@@ -0,0 +1,7 @@
+// The odd spacing inside each type is meant to detect (and fail the test) if rewriting resorts
+// to reprinting the entire type.
+
+type Callback = fn(&i32,  u8) -> i32;
+type Hook = fn( u16 );
+
+fn main() {}
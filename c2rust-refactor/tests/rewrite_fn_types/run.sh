@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_ty 'fn(&i32, u8) -> i32' 'fn(u8)' \; \
+    rewrite_ty 'fn(u16)' 'fn(u16) -> bool' \
+    -- old.rs $rustflags
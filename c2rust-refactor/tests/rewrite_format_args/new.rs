@@ -0,0 +1,9 @@
+// The odd spacing inside each invocation is meant to detect (and fail the test) if rewriting
+// resorts to reprinting the entire macro invocation.
+
+fn main() {
+    let x = 1;
+    let y = 2;
+    println!("x = {},  y = {}"  , x, y + 1);
+    let _s = format!( "{:>4}" , y + 1);
+}
@@ -0,0 +1,16 @@
+// Only the expression inside the inline module changes.  The text around it, inside and outside
+// the module, is kept as written.
+
+fn  before ( ) -> i32 { 2   +   1 }
+
+mod m {
+    pub  fn  f ( ) -> i32 {
+        3
+    }
+
+    pub const  C : i32 = 0 ;
+}
+
+fn main() {
+    let _x = m::f() + m::C;
+}
@@ -0,0 +1,12 @@
+// The odd spacing inside each pointee type is meant to detect (and fail the test) if rewriting
+// resorts to reprinting the whole pointer type.
+
+fn f(
+    _a: &mut Vec< u8 >,
+    _b: &Option< i32 >,
+    _c: &mut &( u8, u8 ),
+    _d: &'static [ u8 ],
+) {
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_ty '*mut $t:Ty' '&mut $t' \; \
+    rewrite_ty '*const $t:Ty' '&$t' \; \
+    rewrite_ty "&'static mut \$t:Ty" "&'static \$t" \
+    -- old.rs $rustflags
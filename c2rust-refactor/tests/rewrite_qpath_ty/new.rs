@@ -0,0 +1,7 @@
+fn count(it: < Vec<i8>  as  IntoIterator >::Item) -> usize {
+    it.count()
+}
+
+fn main() {
+    count(vec![1, 2, 3].into_iter());
+}
@@ -0,0 +1,7 @@
+fn count(it: < Vec<u8>  as  IntoIterator >::IntoIter) -> usize {
+    it.count()
+}
+
+fn main() {
+    count(vec![1, 2, 3].into_iter());
+}
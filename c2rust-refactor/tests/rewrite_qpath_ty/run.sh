@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_ty 'u8' 'i8' \; \
+    rewrite_ty '<$t:Ty as IntoIterator>::IntoIter' '<$t as IntoIterator>::Item' \
+    -- old.rs $rustflags
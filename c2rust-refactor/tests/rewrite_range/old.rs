@@ -0,0 +1,9 @@
+// The comments inside each range are meant to detect (and fail the test) if rewriting resorts to
+// reprinting the entire range expression.
+
+fn main() {
+    let a = 1;
+    let b = 10;
+    for _ in a /* lo */ ..= /* hi */ b {}
+    let _r = (a + 1)  ..=  (b - 1);
+}
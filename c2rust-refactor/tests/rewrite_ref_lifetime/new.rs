@@ -0,0 +1,8 @@
+// The comments are meant to detect (and fail the test) if rewriting resorts to reprinting the
+// entire type.
+
+static GREETING: &/* greeting */ str = "hi";
+
+fn main() {
+    let _s: &/* bytes */ [u8] = &[];
+}
@@ -0,0 +1,13 @@
+// The comments inside each repeat expression are meant to detect (and fail the test) if rewriting
+// resorts to reprinting the entire expression.
+
+const N: usize = 4;
+
+fn main() {
+    let _a = [(1 /* value */, [0u8; 2]); /* count */ N];
+    let _b = [
+        // value
+        (2, 3);
+        N
+    ];
+}
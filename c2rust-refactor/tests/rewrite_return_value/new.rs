@@ -0,0 +1,20 @@
+fn g_value() {}
+
+fn h_value() {}
+
+fn g(c: bool) {
+    if c {
+        return g_value();
+    }
+}
+
+fn h(c: bool) {
+    if c {
+        return;
+    }
+}
+
+fn main() {
+    g(true);
+    h(true);
+}
@@ -0,0 +1,20 @@
+fn g_value() {}
+
+fn h_value() {}
+
+fn g(c: bool) {
+    if c {
+        return;
+    }
+}
+
+fn h(c: bool) {
+    if c {
+        return h_value();
+    }
+}
+
+fn main() {
+    g(true);
+    h(true);
+}
@@ -0,0 +1,15 @@
+// The comments inside each struct literal are meant to detect (and fail the test) if rewriting
+// resorts to reprinting the entire expression.
+
+#[derive(Clone, Default)]
+struct S {
+    x: i32,
+}
+
+fn main() {
+    let base = S::default();
+    let _a = S { x: /* x */ 1 };
+    let _b = S {
+        x: /* x */ 2,
+    };
+}
@@ -0,0 +1,16 @@
+// The comments inside each struct literal are meant to detect (and fail the test) if rewriting
+// resorts to reprinting the entire expression.
+
+#[derive(Clone, Default)]
+struct S {
+    x: i32,
+}
+
+fn main() {
+    let base = S::default();
+    let _a = S { x: /* x */ 1, ..base.clone() };
+    let _b = S {
+        x: /* x */ 2,
+        ..base
+    };
+}
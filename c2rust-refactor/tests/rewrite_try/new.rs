@@ -0,0 +1,16 @@
+// The odd spacing inside each call is meant to detect (and fail the test) if rewriting resorts
+// to reprinting the entire expression.
+
+fn foo(x: i32) -> Result<i32, ()> {
+    Ok(x)
+}
+
+fn f() -> Result<(), ()> {
+    let _a = foo( 1 )?;
+    let _b = foo( 2 );
+    Ok(())
+}
+
+fn main() {
+    f().unwrap();
+}
@@ -0,0 +1,11 @@
+// The comments inside each struct are meant to detect (and fail the test) if rewriting resorts to
+// reprinting the entire item.
+
+pub struct Meters(/* m */ pub f64);
+
+struct Pair(pub(crate) u8, /* second */ f64);
+
+fn main() {
+    let _m = Meters(1.0);
+    let _p = Pair(1, 2.0);
+}
@@ -0,0 +1,5 @@
+type Pair = (Vec</* elem */ u16>,);
+
+fn f(_p: (char,)) {}
+
+fn main() {}
@@ -0,0 +1,5 @@
+type Pair = (u8, Vec</* elem */ u16>);
+
+fn f(_p: (u8, char)) {}
+
+fn main() {}
@@ -0,0 +1,11 @@
+// The odd spacing inside each call is meant to detect (and fail the test) if rewriting resorts
+// to reprinting the entire expression.
+
+fn foo(x: i32) -> i32 {
+    x
+}
+
+fn main() {
+    let _a = unsafe { foo( 1 ) };
+    let _b = foo( 2 );
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    rewrite_expr 'foo(1)' 'unsafe { foo(1) }' \; \
+    rewrite_expr 'unsafe { foo(2) }' 'foo(2)' \
+    -- old.rs $rustflags
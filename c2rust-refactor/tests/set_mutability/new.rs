@@ -0,0 +1,7 @@
+// The empty comments in each item header are meant to detect (and fail the test) if rewriting
+// resorts to reprinting the entire item.
+
+static /**/ mut A: [u8; 4] = [1, 2, 3, 4];
+pub static /**/ B: u32 = 1;
+
+fn main() {}
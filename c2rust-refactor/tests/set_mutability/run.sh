@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(name("A"));' \; set_mutability mut \; clear_marks \; \
+    select target 'crate; desc(name("B"));' \; set_mutability imm \; clear_marks \; \
+    -- old.rs $rustflags
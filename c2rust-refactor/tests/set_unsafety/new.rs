@@ -0,0 +1,14 @@
+#![feature(async_await, futures_api)]
+
+// The empty comments in each item header are meant to detect (and fail the test) if rewriting
+// resorts to reprinting the entire item.
+
+unsafe extern "C" /**/ fn f(x: i32) -> i32 {
+    x
+}
+
+pub extern "system" /**/ fn g() {}
+
+unsafe async /**/ fn h() {}
+
+fn main() {}
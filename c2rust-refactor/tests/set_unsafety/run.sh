@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(fn && (name("f") || name("h")));' \; set_unsafety unsafe \; clear_marks \; \
+    select target 'crate; desc(fn && name("g"));' \; set_unsafety safe \; clear_marks \; \
+    -- old.rs $rustflags
@@ -0,0 +1,14 @@
+// The empty comments are meant to detect (and fail the test) if rewriting resorts to reprinting
+// the entire impl.
+
+mod m {
+    pub struct S;
+
+    impl S {
+        fn priv1(&self) /**/ {}
+        pub(in crate::m) fn restricted1(&self) /**/ {}
+        pub(in crate::m) fn restricted2(&self) /**/ {}
+    }
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(fn && name("priv1"));' \; set_visibility pub \; clear_marks \; \
+    select target 'crate; desc(fn && name("restricted1"));' \; set_visibility '' \; clear_marks \; \
+    select target 'crate; desc(fn && name("restricted2"));' \; set_visibility 'pub(crate)' \; clear_marks \; \
+    -- old.rs $rustflags